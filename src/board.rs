@@ -1,18 +1,42 @@
 use std::collections::HashMap;
+use rule::Rule;
+use rule::MAX_SPECIES;
 
+// State 0 is dead and 1 is alive, rules with more states use the others for dying cells or, for
+// colour rules, as the species of a live cell. alive is kept alongside as it is all neighbour
+// counting looks at.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Cell {
     pub alive: bool, // TODO: write getter
     iteration: usize,
     previous_alive: bool,
+    state: u8,
+    previous_state: u8,
 }
 
 impl Cell {
     pub fn new(alive: bool, iteration: usize, previous_alive: bool) -> Cell {
+        Cell::with_state(alive as u8, iteration, previous_alive as u8)
+    }
+
+    pub fn with_state(state: u8, iteration: usize, previous_state: u8) -> Cell {
+        Cell {
+            alive: state == 1,
+            iteration: iteration,
+            previous_alive: previous_state == 1,
+            state: state,
+            previous_state: previous_state,
+        }
+    }
+
+    // Cell where the rule decides which states are alive, colour rules have more than one
+    pub fn for_rule(rule: &Rule, state: u8, iteration: usize, previous_state: u8) -> Cell {
         Cell {
-            alive: alive,
+            alive: rule.is_alive(state),
             iteration: iteration,
-            previous_alive: previous_alive,
+            previous_alive: rule.is_alive(previous_state),
+            state: state,
+            previous_state: previous_state,
         }
     }
 
@@ -23,43 +47,104 @@ impl Cell {
     pub fn get_previous_alive(&self) -> bool {
         self.previous_alive
     }
+
+    pub fn get_state(&self) -> u8 {
+        self.state
+    }
+
+    pub fn get_previous_state(&self) -> u8 {
+        self.previous_state
+    }
 }
 
+#[derive(Clone)]
 pub struct Board {
     width: u32,
     height: u32,
+    rule: Rule,
+    // Where the board's top left cell is on the whole board
+    origin: (u32, u32),
+    // Offsets of the cells the rule counts, worked out once rather than per cell. There is one
+    // for each column and row until the rule's neighbourhood repeats, see neighbourhood.
+    neighbourhoods: Vec<Vec<(i32, i32)>>,
     pub cells: Box<[Box<[Cell]>]>,
 }
 
 impl Board {
     pub fn new(width: u32, height: u32, alive_cells: &HashMap<(u32, u32), bool>) -> Board {
+        let states = alive_cells.iter().map(|(&coords, &alive)| (coords, alive as u8)).collect();
+
+        Board::with_states(width, height, &states, Rule::conway())
+    }
+
+    pub fn with_states(width: u32,
+                       height: u32,
+                       states: &HashMap<(u32, u32), u8>,
+                       rule: Rule)
+                       -> Board {
+        Board::with_states_at(width, height, states, rule, 0)
+    }
+
+    // Every cell already at the generation
+    pub fn with_states_at(width: u32,
+                          height: u32,
+                          states: &HashMap<(u32, u32), u8>,
+                          rule: Rule,
+                          generation: usize)
+                          -> Board {
         let mut cells = Vec::new();
         for x in 0..width {
             let mut col = Vec::new();
 
             for y in 0..height {
-                let false_pointer = &false;
-                let alive = alive_cells.get(&(x, y)).unwrap_or(false_pointer);
-                // TODO: had lifetime issue
-                let c = Cell {
-                    alive: *alive,
-                    iteration: 0,
-                    previous_alive: false,
-                };
-
-                col.push(c);
+                let state = states.get(&(x, y)).cloned().unwrap_or(0);
+
+                col.push(Cell::for_rule(&rule, state, generation, 0));
             }
 
             cells.push(col.into_boxed_slice());
         }
 
+        let (columns, rows) = rule.get_neighbourhood_period();
+        let mut neighbourhoods = Vec::with_capacity((columns * rows) as usize);
+        for y in 0..rows {
+            for x in 0..columns {
+                neighbourhoods.push(rule.get_neighbourhood(x, y));
+            }
+        }
+
         Board {
             width: width,
             height: height,
+            origin: (0, 0),
+            neighbourhoods: neighbourhoods,
+            rule: rule,
             cells: cells.into_boxed_slice(),
         }
     }
 
+    pub fn get_rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    pub fn get_origin(&self) -> (u32, u32) {
+        self.origin
+    }
+
+    // Hexagons, triangles and 3D rules depend on where the cell is on the whole board, not just
+    // the board
+    pub fn set_origin(&mut self, x: u32, y: u32) {
+        self.origin = (x, y);
+    }
+
+    fn neighbourhood(&self, x: u32, y: u32) -> &[(i32, i32)] {
+        let (columns, rows) = self.rule.get_neighbourhood_period();
+        let column = (self.origin.0 + x) % columns;
+        let row = (self.origin.1 + y) % rows;
+
+        &self.neighbourhoods[(column + columns * row) as usize]
+    }
+
     pub fn get_cell(&self, x: u32, y: u32) -> &Cell {
         &self.cells[x as usize][y as usize]
     }
@@ -88,73 +173,184 @@ impl Board {
         }
     }
 
-    pub fn neighbour_alive_count(&self, x: u32, y: u32, iteration: usize) -> Option<u8> {
-        let mut count = 0;
+    // How wide the joining cells around a section need to be for the rule
+    pub fn get_halo(&self) -> u32 {
+        self.rule.get_range()
+    }
+
+    // How many of the joining rows at the top and bottom the rule reaches, the nearest ones to
+    // the section's own cells. Only these are sent between sections, the others stay dead.
+    pub fn get_halo_rows(&self) -> u32 {
+        self.rule.get_row_range()
+    }
+
+    // Whether the cell at the offset was alive at the iteration and its state then, None if it
+    // isn't at the iteration or the one after. Cells off the board are dead.
+    fn neighbour_at(&self,
+                    x: u32,
+                    y: u32,
+                    x_offset: i32,
+                    y_offset: i32,
+                    iteration: usize)
+                    -> Option<(bool, u8)> {
+        let xi = x as i64 + x_offset as i64;
+        let yi = y as i64 + y_offset as i64;
+        if xi < 0 || yi < 0 {
+            return Some((false, 0));
+        }
 
-        for x_offset in 0..3 {
-            for y_offset in 0..3 {
-                if !(x_offset == 1 && y_offset == 1) {
-                    for xi in x.checked_sub(1).and_then(|x| x.checked_add(x_offset)) {
-                        for yi in y.checked_sub(1).and_then(|y| y.checked_add(y_offset)) {
-                            match self.get_cell_option(xi, yi) {
-                                Some(c) => {
-                                    let alive = if c.iteration == iteration {
-                                        c.alive
-                                    } else if c.iteration > 0 &&
-                                                   c.iteration - 1 == iteration {
-                                        c.previous_alive
-                                    } else {
-                                        return None;
-                                    };
-
-                                    if alive {
-                                        count += 1
-                                    }
-                                }
-                                None => {}
-                            }
-                        }
-                    }
+        match self.get_cell_option(xi as u32, yi as u32) {
+            Some(c) => {
+                if c.iteration == iteration {
+                    Some((c.alive, c.state))
+                } else if c.iteration > 0 && c.iteration - 1 == iteration {
+                    Some((c.previous_alive, c.previous_state))
+                } else {
+                    None
                 }
             }
+            None => Some((false, 0)),
+        }
+    }
+
+    fn alive_at(&self,
+                x: u32,
+                y: u32,
+                x_offset: i32,
+                y_offset: i32,
+                iteration: usize)
+                -> Option<bool> {
+        self.neighbour_at(x, y, x_offset, y_offset, iteration).map(|(alive, _)| alive)
+    }
+
+    pub fn neighbour_alive_count(&self, x: u32, y: u32, iteration: usize) -> Option<u16> {
+        let mut count = 0;
+
+        for &(x_offset, y_offset) in self.neighbourhood(x, y) {
+            match self.alive_at(x, y, x_offset, y_offset, iteration) {
+                Some(true) => count += 1,
+                Some(false) => {}
+                None => return None,
+            }
         }
 
         Some(count)
     }
 
-    // Any live cell with fewer than two live neighbours dies, as if caused by under-population.
-    // Any live cell with two or three live neighbours lives on to the next generation.
-    // Any live cell with more than three live neighbours dies, as if by over-population.
-    // Any dead cell with exactly three live neighbours becomes a live cell, as if by reproduction.
-    pub fn should_be_alive(alive: bool, alive_neighbours: u8) -> bool {
-        if alive {
-            if alive_neighbours < 2 {
-                false
-            } else if alive_neighbours > 3 {
-                false
-            } else {
-                true
+    // Which of the 3x3 cells around the cell (itself included) are alive, a bit each going
+    // along the rows from the top left
+    pub fn neighbour_configuration(&self, x: u32, y: u32, iteration: usize) -> Option<u16> {
+        let mut configuration = 0;
+
+        for y_offset in -1..2 {
+            for x_offset in -1..2 {
+                match self.alive_at(x, y, x_offset, y_offset, iteration) {
+                    Some(true) => configuration |= 1 << ((y_offset + 1) * 3 + x_offset + 1),
+                    Some(false) => {}
+                    None => return None,
+                }
+            }
+        }
+
+        Some(configuration)
+    }
+
+    // How many live neighbours there are of each species, indexed by their state
+    pub fn neighbour_species_counts(&self,
+                                    x: u32,
+                                    y: u32,
+                                    iteration: usize)
+                                    -> Option<[u16; MAX_SPECIES + 1]> {
+        let mut counts = [0; MAX_SPECIES + 1];
+
+        for &(x_offset, y_offset) in self.neighbourhood(x, y) {
+            match self.neighbour_at(x, y, x_offset, y_offset, iteration) {
+                Some((true, state)) => counts[state as usize] += 1,
+                Some((false, _)) => {}
+                None => return None,
+            }
+        }
+
+        Some(counts)
+    }
+
+    // State of each neighbour, in the order of the rule's neighbourhood. Only the first as many
+    // as there are neighbours are used.
+    pub fn neighbour_states(&self, x: u32, y: u32, iteration: usize) -> Option<[u8; 8]> {
+        let mut states = [0; 8];
+
+        for (i, &(x_offset, y_offset)) in self.neighbourhood(x, y).iter().enumerate() {
+            match self.neighbour_at(x, y, x_offset, y_offset, iteration) {
+                Some((_, state)) => states[i] = state,
+                None => return None,
+            }
+        }
+
+        Some(states)
+    }
+
+    // Which cells of the Margolus block the cell is in are alive, as bits going along the rows
+    // from the top left, and which of those bits is the cell. Blocks line up with the whole board
+    // and move by one cell each generation, see Rule::Margolus.
+    pub fn block_configuration(&self, x: u32, y: u32, iteration: usize) -> Option<(u8, u8)> {
+        let partition = (iteration % 2) as u32;
+        let block_x = (self.origin.0 + x + partition) % 2;
+        let block_y = (self.origin.1 + y + partition) % 2;
+        let mut block = 0;
+
+        for dy in 0..2 {
+            for dx in 0..2 {
+                let x_offset = dx - block_x as i32;
+                let y_offset = dy - block_y as i32;
+
+                match self.alive_at(x, y, x_offset, y_offset, iteration) {
+                    Some(true) => block |= 1 << (dy * 2 + dx),
+                    Some(false) => {}
+                    None => return None,
+                }
             }
-        } else {
-            alive_neighbours == 3
         }
 
+        Some((block, (block_y * 2 + block_x) as u8))
     }
 
     pub fn next_cell(&self, x: u32, y: u32, current: &Cell) -> Option<Cell> {
-        let alive_count_option = self.neighbour_alive_count(x, y, current.iteration);
+        let next_state = if self.rule.is_margolus() {
+            self.block_configuration(x, y, current.iteration).map(|(block, position)|
+                self.rule.next_state_from_block(block, position)
+            )
+        } else if self.rule.needs_neighbour_states() {
+            let neighbours = self.neighbourhood(x, y).len();
+
+            self.neighbour_states(x, y, current.iteration).map(|states|
+                self.rule.next_state_from_neighbours(current.state, &states[..neighbours])
+            )
+        } else if self.rule.get_species() > 1 {
+            self.neighbour_species_counts(x, y, current.iteration).map(|counts|
+                self.rule.next_state_from_species(current.state, &counts)
+            )
+        } else if self.rule.is_isotropic() {
+            self.neighbour_configuration(x, y, current.iteration).map(|configuration|
+                self.rule.next_state_from_configuration(current.state, configuration)
+            )
+        } else {
+            let alive_count_option = self.neighbour_alive_count(x, y, current.iteration);
 
-        let should_be_alive =
             alive_count_option.map(|alive_count|
-            	Board::should_be_alive(current.alive, alive_count)
-            );
-
-        should_be_alive.map(|alive| {
-            Cell {
-                alive: alive,
-                iteration: current.iteration + 1,
-                previous_alive: current.alive,
-            }
+            	self.rule.next_state(current.state, alive_count)
+            )
+        };
+
+        // Chances go by where the cell is on the whole board so sections all agree
+        let (origin_x, origin_y) = self.origin;
+        next_state.map(|state| {
+            let state = self.rule.next_state_by_chance(current.state,
+                                                       state,
+                                                       origin_x + x,
+                                                       origin_y + y,
+                                                       current.iteration);
+
+            Cell::for_rule(&self.rule, state, current.iteration + 1, current.state)
         })
     }
 }
@@ -162,6 +358,8 @@ impl Board {
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
+    use rule::Rule;
+    use rule::space_column;
     use super::*;
 
     fn get_test_board() -> Board {
@@ -285,4 +483,173 @@ mod tests {
 
         assert_eq!(actual.alive, true);
     }
+
+    #[test]
+    fn board_next_cell_generations_dying_cell_not_counted() {
+        let mut alives = HashMap::new();
+        alives.insert((2, 2), 1);
+        alives.insert((1, 1), 2);
+        alives.insert((1, 2), 1);
+        let rule = Rule::parse("B2/S/C3").unwrap();
+        let board = Board::with_states(5, 5, &alives, rule);
+
+        assert_eq!(board.neighbour_alive_count(2, 1, 0), Some(2));
+        assert_eq!(board.next_cell(2, 1, board.get_cell(2, 1)).unwrap().get_state(), 1);
+        assert_eq!(board.next_cell(2, 2, board.get_cell(2, 2)).unwrap().get_state(), 2);
+        assert_eq!(board.next_cell(1, 1, board.get_cell(1, 1)).unwrap().get_state(), 0);
+    }
+
+    #[test]
+    fn board_neighbour_alive_count_larger_than_life() {
+        let mut alives = HashMap::new();
+        alives.insert((2, 2), 1);
+        alives.insert((0, 0), 1);
+        alives.insert((4, 2), 1);
+        alives.insert((5, 2), 1);
+        let moore = Rule::parse("R2,C0,M1,S1..2,B3..3,NM").unwrap();
+        let von_neumann = Rule::parse("R2,C0,M0,S1..2,B3..3,NN").unwrap();
+
+        let board = Board::with_states(6, 6, &alives, moore);
+        assert_eq!(board.get_halo(), 2);
+        assert_eq!(board.neighbour_alive_count(2, 2, 0), Some(3));
+
+        let board = Board::with_states(6, 6, &alives, von_neumann);
+        assert_eq!(board.neighbour_alive_count(2, 2, 0), Some(1));
+    }
+
+    #[test]
+    fn board_hexagonal_neighbours_use_origin() {
+        let mut alives = HashMap::new();
+        alives.insert((2, 0), 1);
+        alives.insert((2, 2), 1);
+        let rule = Rule::parse("B2/S34H").unwrap();
+        let mut board = Board::with_states(4, 4, &alives, rule);
+
+        // Row 1 is odd so its neighbours above and below are the same column and one to the right
+        assert_eq!(board.neighbour_alive_count(1, 1, 0), Some(2));
+        assert_eq!(board.neighbour_alive_count(2, 1, 0), Some(2));
+        assert_eq!(board.neighbour_alive_count(3, 1, 0), Some(0));
+
+        // Starting on an odd row of the whole board row 1 is even
+        board.set_origin(0, 1);
+        assert_eq!(board.neighbour_alive_count(1, 1, 0), Some(0));
+        assert_eq!(board.neighbour_alive_count(3, 1, 0), Some(2));
+    }
+
+    #[test]
+    fn board_next_cell_isotropic() {
+        let mut alives = HashMap::new();
+        alives.insert((1, 1), 1);
+        alives.insert((2, 1), 1);
+        alives.insert((4, 1), 1);
+        let rule = Rule::parse("B2-a/S12").unwrap();
+        let board = Board::with_states(6, 4, &alives, rule);
+
+        assert_eq!(board.neighbour_configuration(2, 2, 0), Some(0b000000011));
+        // Two neighbours next to each other (2a) isn't a birth, two apart (2c) is
+        assert_eq!(board.next_cell(2, 2, board.get_cell(2, 2)).unwrap().get_state(), 0);
+        assert_eq!(board.next_cell(3, 2, board.get_cell(3, 2)).unwrap().get_state(), 1);
+    }
+
+    #[test]
+    fn board_next_cell_takes_majority_species() {
+        let mut states = HashMap::new();
+        states.insert((0, 0), 1);
+        states.insert((1, 0), 2);
+        states.insert((2, 0), 2);
+        let board = Board::with_states(3, 3, &states, Rule::parse("Immigration").unwrap());
+
+        assert!(board.get_cell(0, 0).alive && board.get_cell(1, 0).alive);
+        assert_eq!(board.neighbour_species_counts(1, 1, 0), Some([0, 1, 2, 0, 0]));
+        assert_eq!(board.next_cell(1, 1, board.get_cell(1, 1)).unwrap().get_state(), 2);
+        // Survivors keep their own species
+        assert_eq!(board.next_cell(1, 0, board.get_cell(1, 0)).unwrap().get_state(), 2);
+    }
+
+    #[test]
+    fn board_next_cell_rule_table() {
+        let mut states = HashMap::new();
+        states.insert((0, 1), 1);
+        states.insert((1, 1), 3);
+        states.insert((2, 1), 3);
+        let board = Board::with_states(3, 3, &states, Rule::parse("WireWorld").unwrap());
+
+        assert_eq!(board.neighbour_states(1, 1, 0), Some([0, 0, 3, 0, 0, 0, 1, 0]));
+        assert_eq!(board.next_cell(0, 1, board.get_cell(0, 1)).unwrap().get_state(), 2);
+        assert_eq!(board.next_cell(1, 1, board.get_cell(1, 1)).unwrap().get_state(), 1);
+        assert_eq!(board.next_cell(2, 1, board.get_cell(2, 1)).unwrap().get_state(), 3);
+    }
+
+    #[test]
+    fn board_next_cell_by_chance_uses_whole_board_position() {
+        let mut states = HashMap::new();
+        for x in 0..8 {
+            for y in 0..8 {
+                if (x * 7 + y * 3) % 5 < 2 {
+                    states.insert((x, y), 1);
+                }
+            }
+        }
+        let rule = Rule::parse("B3/S23;birth=0.5;survival=0.5;seed=3").unwrap();
+        let board = Board::with_states(8, 8, &states, rule.clone());
+
+        // The same cells as a board starting at (2, 3)
+        let shifted_states = states.iter()
+            .filter(|&(&(x, y), _)| x >= 2 && y >= 3)
+            .map(|(&(x, y), &state)| ((x - 2, y - 3), state))
+            .collect();
+        let mut shifted = Board::with_states(6, 5, &shifted_states, rule);
+        shifted.set_origin(2, 3);
+
+        for x in 3..7 {
+            for y in 4..7 {
+                assert_eq!(board.next_cell(x, y, board.get_cell(x, y)),
+                           shifted.next_cell(x - 2, y - 3, shifted.get_cell(x - 2, y - 3)));
+            }
+        }
+    }
+
+    #[test]
+    fn board_margolus_blocks_alternate() {
+        let mut states = HashMap::new();
+        states.insert((2, 2), 1);
+        let mut board = Board::with_states(6, 6, &states, Rule::parse("BBM").unwrap());
+
+        // Even generations the ball is top left of the block at (2, 2) and goes to bottom right
+        assert_eq!(board.block_configuration(3, 3, 0), Some((1, 3)));
+        assert_eq!(board.next_cell(3, 3, board.get_cell(3, 3)).unwrap().get_state(), 1);
+        assert_eq!(board.next_cell(2, 2, board.get_cell(2, 2)).unwrap().get_state(), 0);
+
+        // Odd generations the blocks start one further along, so it's the bottom right of (1, 1)
+        for x in 0..6 {
+            for y in 0..6 {
+                let alive = board.get_cell(x, y).alive;
+                board.set_cell(x, y, Cell::new(alive, 1, false));
+            }
+        }
+        assert_eq!(board.block_configuration(2, 2, 1), Some((8, 3)));
+
+        // The blocks are on the whole board, starting at an odd column moves them
+        board = Board::with_states(6, 6, &states, Rule::parse("BBM").unwrap());
+        board.set_origin(1, 0);
+        assert_eq!(board.block_configuration(2, 2, 0), Some((2, 1)));
+    }
+
+    #[test]
+    fn board_three_dimensional_counts_around_in_the_space() {
+        let mut states = HashMap::new();
+        // The cube of cells from (1, 1, 0) to (3, 3, 2)
+        for x in 1..4 {
+            for y in 1..4 {
+                for z in 0..3 {
+                    states.insert((space_column(x, z, 4), y), 1);
+                }
+            }
+        }
+        let board = Board::with_states(24, 6, &states, Rule::parse("3D4,5,5,5,D4").unwrap());
+
+        assert_eq!(board.neighbour_alive_count(space_column(2, 1, 4), 2, 0), Some(26));
+        // The next column along is the bottom of the next x, not above the top of the space
+        assert_eq!(board.neighbour_alive_count(space_column(2, 3, 4), 2, 0), Some(9));
+    }
 }
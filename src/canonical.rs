@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use view::Rectangle;
+
+// 64 bit FNV-1a, which comes out the same on every platform and every run unlike the standard
+// library's hasher
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+// What moving the cells about doesn't change the hash of
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Invariance {
+    // Moved anywhere
+    Translation,
+    // Moved, turned or reflected
+    Symmetry,
+}
+
+pub fn fnv(cells: &[(u32, u32, u8)]) -> u64 {
+    let mut hash = FNV_OFFSET;
+
+    for &(x, y, state) in cells {
+        let mut bytes = [state; 9];
+        for i in 0..4 {
+            bytes[i] = (x >> (8 * i)) as u8;
+            bytes[4 + i] = (y >> (8 * i)) as u8;
+        }
+
+        for &byte in bytes.iter() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    hash
+}
+
+// The non zero cells moved so the top left of their bounding box is at 0, 0, sorted, and where
+// the top left was
+pub fn translate(cells: &HashMap<(u32, u32), u8>) -> (Vec<(u32, u32, u8)>, (u32, u32)) {
+    let live: Vec<(u32, u32, u8)> = cells.iter()
+        .filter(|&(_, &state)| state != 0)
+        .map(|(&(x, y), &state)| (x, y, state))
+        .collect();
+    let min_x = live.iter().map(|&(x, _, _)| x).min().unwrap_or(0);
+    let min_y = live.iter().map(|&(_, y, _)| y).min().unwrap_or(0);
+
+    let mut moved: Vec<(u32, u32, u8)> =
+        live.into_iter().map(|(x, y, state)| (x - min_x, y - min_y, state)).collect();
+    moved.sort();
+
+    (moved, (min_x, min_y))
+}
+
+// The cells as they are compared, translated and for Symmetry the first (sorted) of the eight
+// ways round, so the same cells wherever they are and however they are split between sections
+// always come out the same
+pub fn canonical(cells: &HashMap<(u32, u32), u8>, invariance: Invariance) -> Vec<(u32, u32, u8)> {
+    let (moved, _) = translate(cells);
+    if invariance == Invariance::Translation {
+        return moved;
+    }
+
+    let width = moved.iter().map(|&(x, _, _)| x).max().unwrap_or(0);
+    let height = moved.iter().map(|&(_, y, _)| y).max().unwrap_or(0);
+
+    let mut best: Option<Vec<(u32, u32, u8)>> = None;
+    for orientation in 0..8 {
+        let mut turned: Vec<(u32, u32, u8)> = moved.iter()
+            .map(|&(x, y, state)| {
+                let (x, y) = if orientation & 4 != 0 { (y, x) } else { (x, y) };
+                let (across, down) = if orientation & 4 != 0 {
+                    (height, width)
+                } else {
+                    (width, height)
+                };
+                let x = if orientation & 1 != 0 { across - x } else { x };
+                let y = if orientation & 2 != 0 { down - y } else { y };
+
+                (x, y, state)
+            })
+            .collect();
+        turned.sort();
+
+        best = match best {
+            Some(ref current) if *current <= turned => continue,
+            _ => Some(turned),
+        };
+    }
+
+    best.unwrap_or_else(Vec::new)
+}
+
+pub fn hash(cells: &HashMap<(u32, u32), u8>, invariance: Invariance) -> u64 {
+    fnv(&canonical(cells, invariance))
+}
+
+// Hash of only the cells inside the region
+pub fn hash_region(cells: &HashMap<(u32, u32), u8>,
+                   region: &Rectangle,
+                   invariance: Invariance)
+                   -> u64 {
+    let inside = cells.iter()
+        .filter(|&(&(x, y), _)| region.contains(x, y))
+        .map(|(&position, &state)| (position, state))
+        .collect();
+
+    hash(&inside, invariance)
+}
+
+#[cfg(test)]
+mod tests {
+    use headless::HeadlessRun;
+    use library;
+    use library::Placement;
+    use rule::Rule;
+    use test_support::cells;
+    use whole::Whole;
+    use super::*;
+
+    #[test]
+    fn canonical_hash_ignores_position_and_turns() {
+        let glider = cells(&[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]);
+        let moved = cells(&[(11, 20), (12, 21), (10, 22), (11, 22), (12, 22)]);
+        let turned = cells(&[(0, 0), (0, 1), (2, 1), (0, 2), (1, 2)]);
+        let mut with_dead = glider.clone();
+        with_dead.insert((0, 0), 0);
+
+        assert_eq!(hash(&glider, Invariance::Translation),
+                   hash(&moved, Invariance::Translation));
+        assert_eq!(hash(&glider, Invariance::Translation),
+                   hash(&with_dead, Invariance::Translation));
+        assert!(hash(&glider, Invariance::Translation) != hash(&turned, Invariance::Translation));
+        assert_eq!(hash(&glider, Invariance::Symmetry), hash(&turned, Invariance::Symmetry));
+        assert_eq!(fnv(&[]), FNV_OFFSET);
+    }
+
+    #[test]
+    fn canonical_hash_same_whatever_the_sections() {
+        let rule = Rule::conway();
+        let acorn = library::built_in("acorn").unwrap();
+        let states = library::place(&rule, &acorn, &Placement::at(20, 20)).unwrap();
+
+        let mut hashes = Vec::new();
+        for &(section_size, sections) in [(10, 6), (16, 3)].iter() {
+            let created = Whole::create_sections_with(section_size,
+                                                      section_size,
+                                                      sections,
+                                                      rule.clone(),
+                                                      &states);
+            let mut run = HeadlessRun::new(created);
+            for _ in 0..10 {
+                run.step().unwrap();
+            }
+
+            let generation = run.get_generation();
+            let whole_hash = run.get_whole().get_hash_at(generation, Invariance::Translation);
+            assert_eq!(whole_hash, Some(hash(&run.get_cells(), Invariance::Translation)));
+            hashes.push(whole_hash);
+        }
+
+        assert_eq!(hashes[0], hashes[1]);
+    }
+}
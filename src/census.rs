@@ -0,0 +1,353 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use evolve;
+use periodicity::Periodicity;
+use rule::Rule;
+
+// Longest period an object is run for on its own to find out what it is
+pub const MAX_OBJECT_PERIOD: usize = 64;
+// What objects that don't repeat within the longest period are counted as
+pub const PATHOLOGICAL: &'static str = "PATHOLOGICAL";
+
+const DIGITS: &'static [u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+// The cells at most the distance apart (either way, diagonals included) in the same group
+pub fn components(cells: &HashSet<(i64, i64)>, distance: i64) -> Vec<HashSet<(i64, i64)>> {
+    let mut left = cells.clone();
+    let mut groups = Vec::new();
+
+    while let Some(&first) = left.iter().next() {
+        let mut group = HashSet::new();
+        let mut queue = VecDeque::new();
+        left.remove(&first);
+        queue.push_back(first);
+
+        while let Some((x, y)) = queue.pop_front() {
+            group.insert((x, y));
+
+            for dx in -distance..distance + 1 {
+                for dy in -distance..distance + 1 {
+                    if left.remove(&(x + dx, y + dy)) {
+                        queue.push_back((x + dx, y + dy));
+                    }
+                }
+            }
+        }
+
+        groups.push(group);
+    }
+
+    groups
+}
+
+fn restrict(cells: &HashMap<(i64, i64), u8>,
+            to: &HashSet<(i64, i64)>)
+            -> HashMap<(i64, i64), u8> {
+    cells.iter()
+        .filter(|&(position, _)| to.contains(position))
+        .map(|(&position, &state)| (position, state))
+        .collect()
+}
+
+// Whether each piece of the cluster run on its own gives the same cells as the cluster does
+fn independent(rule: &Rule,
+               start: &HashMap<(i64, i64), u8>,
+               phases: &[HashMap<(i64, i64), u8>],
+               cluster: &HashSet<(i64, i64)>,
+               pieces: &[HashSet<(i64, i64)>],
+               generation: usize)
+               -> Result<bool, String> {
+    let mut piece_phases = Vec::new();
+    for piece in pieces {
+        piece_phases.push(try!(evolve::phases(rule,
+                                              &restrict(start, piece),
+                                              generation,
+                                              phases.len())));
+    }
+
+    Ok((0..phases.len()).all(|t| {
+        let mut together = HashMap::new();
+        for phase in &piece_phases {
+            together.extend(phase[t].iter().map(|(&position, &state)| (position, state)));
+        }
+
+        together == restrict(&phases[t], cluster)
+    }))
+}
+
+// Splits the live cells of a board that has settled into its objects. Cells that come within two
+// of each other in any generation of the period are in the same object, unless the parts that
+// touch directly each carry on the same on their own (such as two blocks with a gap of one, a
+// pseudo still life), in which case they are objects of their own. The cells are at the
+// generation, and Err if the rule can't be run on its own.
+pub fn separate(rule: &Rule,
+                cells: &HashMap<(u32, u32), u8>,
+                generation: usize,
+                periodicity: &Periodicity)
+                -> Result<Vec<HashMap<(i64, i64), u8>>, String> {
+    let start = cells.iter()
+        .filter(|&(_, &state)| state != 0)
+        .map(|(&(x, y), &state)| ((x as i64, y as i64), state))
+        .collect();
+    let phases = try!(evolve::phases(rule, &start, generation, periodicity.get_period() + 1));
+    let union = phases.iter().flat_map(|phase| phase.keys().cloned()).collect();
+
+    let mut objects = Vec::new();
+    for cluster in components(&union, 2) {
+        let pieces = components(&cluster, 1);
+
+        if pieces.len() > 1 &&
+           try!(independent(rule, &start, &phases, &cluster, &pieces, generation)) {
+            objects.extend(pieces.iter().map(|piece| restrict(&start, piece)));
+        } else {
+            objects.push(restrict(&start, &cluster));
+        }
+    }
+
+    Ok(objects)
+}
+
+// Extended Wechsler format of one orientation. The cells are split into strips five rows deep,
+// each column of a strip is a digit with the top row as its lowest bit, and runs of empty columns
+// are shortened (w for two, x for three and y then a digit for four or more). z separates strips.
+fn wechsler(cells: &HashSet<(i64, i64)>,
+            width: i64,
+            height: i64,
+            orientation: usize)
+            -> String {
+    let (length, breadth) = if orientation < 4 { (width, height) } else { (height, width) };
+    // Where along and across the strips start, and which way each goes in x and y
+    let flip_x = if orientation % 2 == 1 { -1 } else { 1 };
+    let flip_y = if (orientation / 2) % 2 == 1 { -1 } else { 1 };
+    let origin_x = if flip_x < 0 { width - 1 } else { 0 };
+    let origin_y = if flip_y < 0 { height - 1 } else { 0 };
+
+    let mut text = String::new();
+    for strip in 0..(breadth - 1) / 5 + 1 {
+        if strip != 0 {
+            text.push('z');
+        }
+
+        let mut zeroes = 0;
+        for along in 0..length {
+            let mut digit: usize = 0;
+
+            for row in 0..5 {
+                let across = 5 * strip + row;
+                let (x, y) = if orientation < 4 {
+                    (origin_x + flip_x * along, origin_y + flip_y * across)
+                } else {
+                    (origin_x + flip_x * across, origin_y + flip_y * along)
+                };
+
+                if cells.contains(&(x, y)) {
+                    digit |= 1 << row;
+                }
+            }
+
+            if digit == 0 {
+                zeroes += 1;
+                continue;
+            }
+
+            while zeroes > 0 {
+                let run = if zeroes > 39 { 39 } else { zeroes };
+                match run {
+                    1 => text.push('0'),
+                    2 => text.push('w'),
+                    3 => text.push('x'),
+                    _ => {
+                        text.push('y');
+                        text.push(DIGITS[run - 4] as char);
+                    }
+                }
+                zeroes -= run;
+            }
+            text.push(DIGITS[digit] as char);
+        }
+    }
+
+    text
+}
+
+// Shortest then first alphabetically of every phase in every rotation and reflection, so an
+// object has the same code whichever way round and whatever phase it was found in
+fn canonical(phases: &[HashMap<(i64, i64), u8>]) -> String {
+    let mut best: Option<String> = None;
+
+    for phase in phases {
+        let (moved, _) = evolve::normalise(phase);
+        let width = moved.keys().map(|&(x, _)| x + 1).max().unwrap_or(0);
+        let height = moved.keys().map(|&(_, y)| y + 1).max().unwrap_or(0);
+        let cells = moved.keys().cloned().collect();
+
+        for orientation in 0..8 {
+            let text = wechsler(&cells, width, height, orientation);
+
+            best = match best {
+                Some(ref current) if current.len() < text.len() ||
+                                     (current.len() == text.len() && *current <= text) => {
+                    continue
+                }
+                _ => Some(text),
+            };
+        }
+    }
+
+    best.unwrap_or_else(|| "0".to_string())
+}
+
+// An object's period and how far it moves each period, running it on its own from the generation.
+// None if it doesn't repeat within the longest period.
+pub fn period_of(rule: &Rule,
+                 object: &HashMap<(i64, i64), u8>,
+                 generation: usize)
+                 -> Result<Option<(usize, (i64, i64))>, String> {
+    let phases = try!(evolve::phases(rule, object, generation, MAX_OBJECT_PERIOD + 1));
+    let (start, origin) = evolve::normalise(object);
+
+    for period in 1..phases.len() {
+        let (shape, moved_to) = evolve::normalise(&phases[period]);
+
+        if shape == start {
+            return Ok(Some((period, (moved_to.0 - origin.0, moved_to.1 - origin.1))));
+        }
+    }
+
+    Ok(None)
+}
+
+// Name of an object as apgsearch and Catagolue give it, xs (still life) then its population,
+// xp (oscillator) or xq (spaceship) then its period, then _ and the canonical cells
+pub fn apgcode(rule: &Rule,
+               object: &HashMap<(i64, i64), u8>,
+               generation: usize)
+               -> Result<String, String> {
+    Ok(match try!(period_of(rule, object, generation)) {
+        Some((period, displacement)) => {
+            let code = canonical(&try!(evolve::phases(rule, object, generation, period)));
+
+            if displacement != (0, 0) {
+                format!("xq{}_{}", period, code)
+            } else if period == 1 {
+                format!("xs{}_{}", object.len(), code)
+            } else {
+                format!("xp{}_{}", period, code)
+            }
+        }
+        None => PATHOLOGICAL.to_string(),
+    })
+}
+
+// How many of each object have been seen, by apgcode
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Census {
+    counts: BTreeMap<String, u64>,
+}
+
+impl Census {
+    pub fn new() -> Census {
+        Census::default()
+    }
+
+    // Census of a board at the generation that has settled into the periodicity
+    pub fn of_board(rule: &Rule,
+                    cells: &HashMap<(u32, u32), u8>,
+                    generation: usize,
+                    periodicity: &Periodicity)
+                    -> Result<Census, String> {
+        let mut census = Census::new();
+
+        for object in try!(separate(rule, cells, generation, periodicity)) {
+            if !object.is_empty() {
+                census.add(&try!(apgcode(rule, &object, generation)));
+            }
+        }
+
+        Ok(census)
+    }
+
+    pub fn add(&mut self, apgcode: &str) {
+        *self.counts.entry(apgcode.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn merge(&mut self, other: &Census) {
+        for (apgcode, &count) in &other.counts {
+            *self.counts.entry(apgcode.clone()).or_insert(0) += count;
+        }
+    }
+
+    pub fn get_count(&self, apgcode: &str) -> u64 {
+        self.counts.get(apgcode).cloned().unwrap_or(0)
+    }
+
+    pub fn get_counts(&self) -> &BTreeMap<String, u64> {
+        &self.counts
+    }
+
+    // Most common first
+    pub fn to_csv(&self) -> String {
+        let mut counts: Vec<(&String, &u64)> = self.counts.iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+
+        let mut out = String::from("apgcode,count\n");
+        for (apgcode, count) in counts {
+            out.push_str(&format!("{},{}\n", apgcode, count));
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use periodicity::PeriodDetector;
+    use rule::Rule;
+    use test_support::cells;
+    use super::*;
+
+    #[test]
+    fn census_apgcode_of_common_objects() {
+        let code = |positions: &[(i64, i64)]| {
+            apgcode(&Rule::conway(), &cells(positions), 0).unwrap()
+        };
+
+        assert_eq!(code(&[(5, 5), (6, 5), (5, 6), (6, 6)]), "xs4_33");
+        assert_eq!(code(&[(0, 1), (1, 1), (2, 1)]), "xp2_7");
+        assert_eq!(code(&[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]), "xq4_153");
+        // Beehive, whichever way round
+        assert_eq!(code(&[(1, 0), (2, 0), (0, 1), (3, 1), (1, 2), (2, 2)]), "xs6_696");
+        assert_eq!(code(&[(1, 0), (0, 1), (2, 1), (0, 2), (2, 2), (1, 3)]), "xs6_696");
+        assert!(apgcode(&Rule::parse("B0/S8").unwrap(), &cells(&[(0, 0)]), 0).is_err());
+    }
+
+    #[test]
+    fn census_splits_pseudo_objects_but_not_interacting_ones() {
+        let rule = Rule::conway();
+        let mut board = HashMap::new();
+        // Two blocks a cell apart and a blinker well away
+        for &position in [(2, 2), (3, 2), (2, 3), (3, 3), (5, 2), (6, 2), (5, 3), (6, 3)].iter() {
+            board.insert(position, 1);
+        }
+        for &position in [(20, 20), (21, 20), (22, 20)].iter() {
+            board.insert(position, 1);
+        }
+        let mut detector = PeriodDetector::new(4);
+        detector.add(0, &board);
+        let vertical = [(21, 19), (21, 20), (21, 21)].iter().map(|&p| (p, 1));
+        let mut next = board.clone();
+        next.retain(|&(x, _), _| x < 20);
+        next.extend(vertical);
+        detector.add(1, &next);
+        let periodicity = detector.add(2, &board).unwrap();
+
+        let census = Census::of_board(&rule, &board, 2, &periodicity).unwrap();
+
+        assert_eq!(census.get_count("xs4_33"), 2);
+        assert_eq!(census.get_count("xp2_7"), 1);
+        assert_eq!(census.to_csv(), "apgcode,count\nxs4_33,2\nxp2_7,1\n");
+    }
+}
@@ -0,0 +1,324 @@
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use board::Board;
+use board::Cell;
+use std::collections::HashMap;
+use rule::Rule;
+
+const HEADER: &'static str = "rust_game_of_life checkpoint 3";
+// Before each section had where it goes on the whole board
+const HEADER_VERSION_2: &'static str = "rust_game_of_life checkpoint 2";
+// Before cells had states, each cell was iteration:flags
+const HEADER_VERSION_1: &'static str = "rust_game_of_life checkpoint 1";
+
+// A copy of every section's board with where it goes on the whole board. The joining cells of
+// each board hold what its neighbours had sent, so nothing that was in flight between sections is
+// lost. Taken by Whole::request_checkpoint every section is at the generation, from a snapshot
+// sections can be behind it and the generation is the furthest along any of them got.
+pub struct Checkpoint {
+    generation: usize,
+    boards: Vec<Vec<Board>>,
+}
+
+impl Checkpoint {
+    pub fn new(generation: usize, boards: Vec<Vec<Board>>) -> Checkpoint {
+        Checkpoint {
+            generation: generation,
+            boards: boards,
+        }
+    }
+
+    pub fn get_generation(&self) -> usize {
+        self.generation
+    }
+
+    pub fn get_boards(&self) -> &Vec<Vec<Board>> {
+        &self.boards
+    }
+
+    pub fn sections_width(&self) -> usize {
+        self.boards.len()
+    }
+
+    pub fn sections_height(&self) -> usize {
+        self.boards.get(0).map(|c| c.len()).unwrap_or(0)
+    }
+
+    pub fn section_width(&self) -> u32 {
+        self.boards.get(0).and_then(|c| c.get(0)).map(|b| b.get_width()).unwrap_or(0)
+    }
+
+    pub fn section_height(&self) -> u32 {
+        self.boards.get(0).and_then(|c| c.get(0)).map(|b| b.get_height()).unwrap_or(0)
+    }
+
+    pub fn get_rule(&self) -> Rule {
+        self.boards
+            .get(0)
+            .and_then(|c| c.get(0))
+            .map(|b| b.get_rule().clone())
+            .unwrap_or(Rule::conway())
+    }
+
+    // Each cell is written as iteration:state:previous state, one line per column of a section
+    pub fn write(&self, writer: &mut Write) -> io::Result<()> {
+        try!(writeln!(writer, "{}", HEADER));
+        try!(writeln!(writer, "rule {}", self.get_rule()));
+        try!(writeln!(writer, "generation {}", self.generation));
+        try!(writeln!(writer,
+                      "sections {} {}",
+                      self.sections_width(),
+                      self.sections_height()));
+        try!(writeln!(writer,
+                      "section_size {} {}",
+                      self.section_width(),
+                      self.section_height()));
+
+        for (sx, col) in self.boards.iter().enumerate() {
+            for (sy, board) in col.iter().enumerate() {
+                let (origin_x, origin_y) = board.get_origin();
+                try!(writeln!(writer, "section {} {} {} {}", sx, sy, origin_x, origin_y));
+
+                for x in 0..board.get_width() {
+                    let cells: Vec<String> = (0..board.get_height())
+                        .map(|y| {
+                            let cell = board.get_cell(x, y);
+
+                            format!("{}:{}:{}",
+                                    cell.get_iteration(),
+                                    cell.get_state(),
+                                    cell.get_previous_state())
+                        })
+                        .collect();
+
+                    try!(writeln!(writer, "{}", cells.join(" ")));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn read(reader: &mut BufRead) -> io::Result<Checkpoint> {
+        let mut lines = reader.lines();
+        let mut next_line = || -> io::Result<String> {
+            match lines.next() {
+                Some(line) => line,
+                None => Err(invalid("Checkpoint ended early")),
+            }
+        };
+
+        let header = try!(next_line());
+        let version = match header.trim() {
+            HEADER => 3,
+            HEADER_VERSION_2 => 2,
+            HEADER_VERSION_1 => 1,
+            _ => return Err(invalid("Not a checkpoint")),
+        };
+
+        let rule = try!(Rule::parse(&try!(value(&try!(next_line()), "rule")))
+            .map_err(|e| invalid(&e)));
+
+        let generation = try!(number(&try!(value(&try!(next_line()), "generation"))));
+        let sections = try!(pair(&try!(value(&try!(next_line()), "sections"))));
+        let section_size = try!(pair(&try!(value(&try!(next_line()), "section_size"))));
+
+        let mut boards = Vec::with_capacity(sections.0);
+        for sx in 0..sections.0 {
+            let mut col = Vec::with_capacity(sections.1);
+
+            for sy in 0..sections.1 {
+                let section = try!(value(&try!(next_line()), "section"));
+                let (position, origin) = if version < 3 {
+                    // Sections were all the same size, side by side
+                    let overlap = 2 * rule.get_range() as usize;
+                    (try!(pair(&section)),
+                     (sx * section_size.0.saturating_sub(overlap),
+                      sy * section_size.1.saturating_sub(overlap)))
+                } else {
+                    let numbers = try!(numbers(&section, 4));
+                    ((numbers[0], numbers[1]), (numbers[2], numbers[3]))
+                };
+                if position != (sx, sy) {
+                    return Err(invalid(&format!("Expected section [{}, {}] but found [{}, {}]",
+                                                sx,
+                                                sy,
+                                                position.0,
+                                                position.1)));
+                }
+
+                let mut board = Board::with_states(section_size.0 as u32,
+                                                   section_size.1 as u32,
+                                                   &HashMap::new(),
+                                                   rule.clone());
+                board.set_origin(origin.0 as u32, origin.1 as u32);
+
+                for x in 0..board.get_width() {
+                    let line = try!(next_line());
+                    let cells: Vec<&str> = line.split_whitespace().collect();
+                    if cells.len() != board.get_height() as usize {
+                        return Err(invalid(&format!("Wrong number of cells in column [{}] of \
+                                                     section [{}, {}]",
+                                                    x,
+                                                    sx,
+                                                    sy)));
+                    }
+
+                    for (y, text) in cells.iter().enumerate() {
+                        board.set_cell(x, y as u32, try!(cell(text, version == 1, &rule)));
+                    }
+                }
+
+                col.push(board);
+            }
+
+            boards.push(col);
+        }
+
+        Ok(Checkpoint::new(generation, boards))
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn value(line: &str, name: &str) -> io::Result<String> {
+    let mut parts = line.trim().splitn(2, ' ');
+
+    if parts.next() != Some(name) {
+        return Err(invalid(&format!("Expected [{}] but found [{}]", name, line)));
+    }
+
+    Ok(parts.next().unwrap_or("").trim().to_string())
+}
+
+fn number(text: &str) -> io::Result<usize> {
+    text.parse().map_err(|_| invalid(&format!("Expected a number but found [{}]", text)))
+}
+
+fn numbers(text: &str, count: usize) -> io::Result<Vec<usize>> {
+    let parts: Vec<&str> = text.split_whitespace().collect();
+    if parts.len() != count {
+        return Err(invalid(&format!("Expected {} numbers but found [{}]", count, text)));
+    }
+
+    parts.iter().map(|part| number(part)).collect()
+}
+
+fn pair(text: &str) -> io::Result<(usize, usize)> {
+    let numbers = try!(numbers(text, 2));
+
+    Ok((numbers[0], numbers[1]))
+}
+
+fn cell(text: &str, version_1: bool, rule: &Rule) -> io::Result<Cell> {
+    let parts: Vec<&str> = text.split(':').collect();
+    let iteration = try!(number(parts[0]));
+
+    if version_1 {
+        let flags = try!(number(parts.get(1).cloned().unwrap_or("")));
+        if flags > 3 || parts.len() != 2 {
+            return Err(invalid(&format!("Bad cell flags in [{}]", text)));
+        }
+
+        return Ok(Cell::new(flags & 1 == 1, iteration, flags & 2 == 2));
+    }
+
+    if parts.len() != 3 {
+        return Err(invalid(&format!("Bad cell [{}]", text)));
+    }
+    let state = try!(number(parts[1]));
+    let previous_state = try!(number(parts[2]));
+    if state > 255 || previous_state > 255 {
+        return Err(invalid(&format!("Bad cell state in [{}]", text)));
+    }
+
+    Ok(Cell::for_rule(rule, state as u8, iteration, previous_state as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Cursor;
+    use board::Board;
+    use board::Cell;
+    use rule::Rule;
+    use super::*;
+
+    #[test]
+    fn checkpoint_write_then_read_is_same() {
+        let mut alives = HashMap::new();
+        alives.insert((1, 2), true);
+        let mut board = Board::new(4, 5, &alives);
+        board.set_cell(3, 3, Cell::new(false, 7, true));
+        let mut below = board.clone();
+        below.set_origin(0, 3);
+        let checkpoint = Checkpoint::new(7, vec![vec![board.clone(), below]]);
+
+        let mut written = Vec::new();
+        checkpoint.write(&mut written).unwrap();
+        let read = Checkpoint::read(&mut Cursor::new(written)).unwrap();
+
+        assert_eq!(read.get_generation(), 7);
+        assert_eq!(read.sections_width(), 1);
+        assert_eq!(read.sections_height(), 2);
+        assert_eq!(read.get_boards()[0][1].get_origin(), (0, 3));
+        for b in &read.get_boards()[0] {
+            assert_eq!(b.get_width(), 4);
+            assert_eq!(b.get_height(), 5);
+            assert_eq!(b.get_cell(1, 2), board.get_cell(1, 2));
+            assert_eq!(b.get_cell(3, 3), &Cell::new(false, 7, true));
+        }
+    }
+
+    #[test]
+    fn checkpoint_keeps_rule_and_states() {
+        let rule = Rule::parse("B2/S/C3").unwrap();
+        let mut board = Board::with_states(3, 3, &HashMap::new(), rule.clone());
+        board.set_cell(1, 1, Cell::with_state(2, 4, 1));
+        let checkpoint = Checkpoint::new(4, vec![vec![board]]);
+
+        let mut written = Vec::new();
+        checkpoint.write(&mut written).unwrap();
+        let read = Checkpoint::read(&mut Cursor::new(written)).unwrap();
+
+        assert_eq!(read.get_rule(), rule);
+        assert_eq!(read.get_boards()[0][0].get_cell(1, 1), &Cell::with_state(2, 4, 1));
+    }
+
+    #[test]
+    fn checkpoint_keeps_species_alive() {
+        let rule = Rule::parse("QuadLife").unwrap();
+        let mut board = Board::with_states(1, 1, &HashMap::new(), rule.clone());
+        board.set_cell(0, 0, Cell::for_rule(&rule, 3, 2, 4));
+        let checkpoint = Checkpoint::new(2, vec![vec![board]]);
+
+        let mut written = Vec::new();
+        checkpoint.write(&mut written).unwrap();
+        let read = Checkpoint::read(&mut Cursor::new(written)).unwrap();
+
+        let cell = read.get_boards()[0][0].get_cell(0, 0);
+        assert!(cell.alive && cell.get_previous_alive());
+        assert_eq!(cell.get_state(), 3);
+    }
+
+    #[test]
+    fn checkpoint_reads_version_1() {
+        let text = "rust_game_of_life checkpoint 1\nrule B3/S23\ngeneration 1\nsections 1 1\n\
+                    section_size 1 2\nsection 0 0\n1:1 1:2\n";
+        let read = Checkpoint::read(&mut Cursor::new(text.as_bytes())).unwrap();
+
+        assert_eq!(read.get_boards()[0][0].get_origin(), (0, 0));
+        assert_eq!(read.get_boards()[0][0].get_cell(0, 0), &Cell::new(true, 1, false));
+        assert_eq!(read.get_boards()[0][0].get_cell(0, 1), &Cell::new(false, 1, true));
+    }
+
+    #[test]
+    fn checkpoint_read_rejects_other_files() {
+        let result = Checkpoint::read(&mut Cursor::new("x = 3, y = 3\n".as_bytes()));
+
+        assert!(result.is_err());
+    }
+}
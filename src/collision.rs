@@ -0,0 +1,542 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::mpsc::channel;
+use threadpool::ThreadPool;
+use canonical;
+use canonical::Invariance;
+use census;
+use census::Census;
+use evolve;
+use library;
+use library::Placement;
+use rle::RlePattern;
+use rule::Rule;
+use soup::SoupSearch;
+use soup::MAX_SAMPLES;
+
+// Objects have to start at least this far apart (either way), and still be a generation on, so
+// they don't touch before they have moved towards each other
+pub const MIN_SEPARATION: i64 = 3;
+// Room around where each object is placed for a phase to have moved it
+const PHASE_ROOM: u32 = 64;
+
+// One of the objects in a collision, which way round, its phase and where it goes relative to
+// the first object
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Projectile {
+    name: String,
+    offset: (i64, i64),
+    quarter_turns: u8,
+    reflected: bool,
+    phase: usize,
+}
+
+impl Projectile {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_offset(&self) -> (i64, i64) {
+        self.offset
+    }
+
+    pub fn get_quarter_turns(&self) -> u8 {
+        self.quarter_turns
+    }
+
+    pub fn is_reflected(&self) -> bool {
+        self.reflected
+    }
+
+    pub fn get_phase(&self) -> usize {
+        self.phase
+    }
+}
+
+// As glider@5;-3 then /t with the quarter turns, /r when reflected and /p with the phase, each
+// only when there is one
+impl fmt::Display for Projectile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}@{};{}", self.name, self.offset.0, self.offset.1));
+        if self.quarter_turns != 0 {
+            try!(write!(f, "/t{}", self.quarter_turns));
+        }
+        if self.reflected {
+            try!(write!(f, "/r"));
+        }
+        if self.phase != 0 {
+            try!(write!(f, "/p{}", self.phase));
+        }
+
+        Ok(())
+    }
+}
+
+// Objects that are set off towards each other, numbered in the order they were made
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Collision {
+    id: usize,
+    projectiles: Vec<Projectile>,
+}
+
+impl Collision {
+    pub fn get_id(&self) -> usize {
+        self.id
+    }
+
+    pub fn get_projectiles(&self) -> &[Projectile] {
+        &self.projectiles
+    }
+
+    // Fewer objects first, then the first made
+    fn cost(&self) -> (usize, usize) {
+        (self.projectiles.len(), self.id)
+    }
+}
+
+impl fmt::Display for Collision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let projectiles: Vec<String> = self.projectiles.iter().map(|p| p.to_string()).collect();
+
+        write!(f, "{}", projectiles.join(" + "))
+    }
+}
+
+// An object turned, reflected and run on to a phase, with its cells relative to where it goes,
+// their states and where they are a generation on
+struct Variant {
+    object: usize,
+    quarter_turns: u8,
+    reflected: bool,
+    phase: usize,
+    cells: Vec<(i64, i64)>,
+    states: Vec<u8>,
+    next: Vec<(i64, i64)>,
+}
+
+fn apart(cells: &[(i64, i64)],
+         offset: (i64, i64),
+         others: &[(i64, i64)],
+         other_offset: (i64, i64))
+         -> bool {
+    cells.iter().all(|&(x, y)| {
+        others.iter().all(|&(ox, oy)| {
+            let (dx, dy) = (x + offset.0 - ox - other_offset.0, y + offset.1 - oy - other_offset.1);
+
+            dx.abs() >= MIN_SEPARATION || dy.abs() >= MIN_SEPARATION
+        })
+    })
+}
+
+// Sets gliders, spaceships or any other objects off at each other in every way round, phase and
+// offset within a range, runs each through the sections until it settles and keeps what came out
+// of the ones where anything happened
+#[derive(Clone, Debug)]
+pub struct CollisionSearch {
+    engine: SoupSearch,
+    objects: Vec<(String, RlePattern)>,
+    max_offset: i64,
+}
+
+impl CollisionSearch {
+    // The engine runs the collisions, which start in the middle of its board
+    pub fn new(engine: SoupSearch,
+               objects: Vec<(String, RlePattern)>,
+               max_offset: i64)
+               -> CollisionSearch {
+        CollisionSearch {
+            engine: engine,
+            objects: objects,
+            max_offset: max_offset,
+        }
+    }
+
+    fn place(&self,
+             object: usize,
+             placement: &Placement)
+             -> Result<HashMap<(u32, u32), u8>, String> {
+        library::place(self.engine.get_rule(), &self.objects[object].1, placement)
+    }
+
+    // Every different way round and phase of every object
+    fn variants(&self) -> Result<Vec<Variant>, String> {
+        let rule = self.engine.get_rule();
+        let mut variants = Vec::new();
+
+        for (object, &(ref name, ref pattern)) in self.objects.iter().enumerate() {
+            let cells = pattern.get_cells()
+                .iter()
+                .map(|(&(x, y), &state)| ((x as i64, y as i64), state))
+                .collect();
+            let period = match try!(census::period_of(rule, &cells, 0)) {
+                Some((period, _)) => period,
+                None => return Err(format!("Object [{}] doesn't repeat", name)),
+            };
+
+            let mut seen = HashSet::new();
+            for quarter_turns in 0..4 {
+                for &reflected in [false, true].iter() {
+                    for phase in 0..period {
+                        let mut placement = Placement::at(PHASE_ROOM, PHASE_ROOM)
+                            .with_rotation(quarter_turns)
+                            .with_phase(phase);
+                        if reflected {
+                            placement = placement.with_reflection();
+                        }
+
+                        let placed: HashMap<(i64, i64), u8> =
+                            try!(self.place(object, &placement))
+                                .into_iter()
+                                .map(|((x, y), state)| {
+                                    ((x as i64 - PHASE_ROOM as i64, y as i64 - PHASE_ROOM as i64),
+                                     state)
+                                })
+                                .collect();
+                        let cells: Vec<(i64, i64)> = placed.keys().cloned().collect();
+
+                        // Ones that are only moved from another are covered by the offsets
+                        let min_x = cells.iter().map(|&(x, _)| x).min().unwrap_or(0);
+                        let min_y = cells.iter().map(|&(_, y)| y).min().unwrap_or(0);
+                        let mut shape: Vec<(i64, i64)> =
+                            cells.iter().map(|&(x, y)| (x - min_x, y - min_y)).collect();
+                        shape.sort();
+
+                        if seen.insert(shape) {
+                            variants.push(Variant {
+                                object: object,
+                                quarter_turns: quarter_turns,
+                                reflected: reflected,
+                                phase: phase,
+                                states: cells.iter().map(|cell| placed[cell]).collect(),
+                                cells: cells,
+                                next: try!(evolve::step(rule, &placed, 0))
+                                    .keys()
+                                    .cloned()
+                                    .collect(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(variants)
+    }
+
+    // Every collision of the number of objects. The first object is as it comes at no offset and
+    // each of the others is any variant at any offset, far enough from the rest. Collisions whose
+    // cells are the same moved, turned or reflected only come once, the first one made.
+    pub fn enumerate(&self, objects: usize) -> Result<Vec<Collision>, String> {
+        let variants = try!(self.variants());
+        let range = -self.max_offset..self.max_offset + 1;
+        let mut placed = Vec::new();
+        for index in 0..variants.len() {
+            for dx in range.clone() {
+                for dy in range.clone() {
+                    placed.push((index, (dx, dy)));
+                }
+            }
+        }
+
+        let mut collisions = Vec::new();
+        let mut seen = HashSet::new();
+        for (first, first_variant) in variants.iter().enumerate() {
+            if first_variant.quarter_turns != 0 || first_variant.reflected ||
+               first_variant.phase != 0 {
+                continue;
+            }
+
+            let mut chosen = vec![(first, (0, 0))];
+            self.choose(&variants, &placed, 0, objects, &mut chosen, &mut seen, &mut collisions);
+        }
+
+        Ok(collisions)
+    }
+
+    // The chosen variants' cells together, the same for collisions that are only moved, turned
+    // or reflected from each other
+    fn key(&self, variants: &[Variant], chosen: &[(usize, (i64, i64))]) -> Vec<(u32, u32, u8)> {
+        // Far enough along that no cell is above or left of 0, 0
+        let room = self.max_offset + PHASE_ROOM as i64;
+        let mut cells = HashMap::new();
+        for &(index, (dx, dy)) in chosen {
+            let variant = &variants[index];
+
+            for (&(x, y), &state) in variant.cells.iter().zip(variant.states.iter()) {
+                cells.insert(((x + dx + room) as u32, (y + dy + room) as u32), state);
+            }
+        }
+
+        canonical::canonical(&cells, Invariance::Symmetry)
+    }
+
+    fn choose(&self,
+              variants: &[Variant],
+              placed: &[(usize, (i64, i64))],
+              from: usize,
+              objects: usize,
+              chosen: &mut Vec<(usize, (i64, i64))>,
+              seen: &mut HashSet<Vec<(u32, u32, u8)>>,
+              collisions: &mut Vec<Collision>) {
+        if chosen.len() == objects {
+            if !seen.insert(self.key(variants, chosen)) {
+                return;
+            }
+
+            let projectiles = chosen.iter()
+                .map(|&(index, offset)| {
+                    let variant = &variants[index];
+
+                    Projectile {
+                        name: self.objects[variant.object].0.clone(),
+                        offset: offset,
+                        quarter_turns: variant.quarter_turns,
+                        reflected: variant.reflected,
+                        phase: variant.phase,
+                    }
+                })
+                .collect();
+
+            let id = collisions.len();
+            collisions.push(Collision {
+                id: id,
+                projectiles: projectiles,
+            });
+            return;
+        }
+
+        for (i, &(index, offset)) in placed.iter().enumerate().skip(from) {
+            // The first object is the one with the lowest index in the list
+            if variants[index].object < variants[chosen[0].0].object {
+                continue;
+            }
+
+            let variant = &variants[index];
+            let separate = chosen.iter().all(|&(other, other_offset)| {
+                apart(&variant.cells, offset, &variants[other].cells, other_offset) &&
+                apart(&variant.next, offset, &variants[other].next, other_offset)
+            });
+            if separate {
+                chosen.push((index, offset));
+                self.choose(variants, placed, i + 1, objects, chosen, seen, collisions);
+                chosen.pop();
+            }
+        }
+    }
+
+    // The collision's cells on the engine's board, the first object in the middle
+    pub fn states(&self, collision: &Collision) -> Result<HashMap<(u32, u32), u8>, String> {
+        let middle = (self.engine.get_width() / 2) as i64;
+        let mut states = HashMap::new();
+
+        for projectile in &collision.projectiles {
+            let found = self.objects.iter().position(|&(ref name, _)| *name == projectile.name);
+            let object = match found {
+                Some(object) => object,
+                None => return Err(format!("No object [{}]", projectile.name)),
+            };
+            let (x, y) = (middle + projectile.offset.0, middle + projectile.offset.1);
+            if x < 0 || y < 0 {
+                return Err(format!("Collision [{}] is off the board", collision));
+            }
+
+            let mut placement = Placement::at(x as u32, y as u32)
+                .with_rotation(projectile.quarter_turns)
+                .with_phase(projectile.phase);
+            if projectile.reflected {
+                placement = placement.with_reflection();
+            }
+            states.extend(try!(self.place(object, &placement)));
+        }
+
+        Ok(states)
+    }
+
+    // What the objects are when nothing happens to them
+    fn inputs(&self, collision: &Collision) -> Result<Census, String> {
+        let mut inputs = Census::new();
+        for projectile in &collision.projectiles {
+            for &(ref name, ref pattern) in &self.objects {
+                if *name == projectile.name {
+                    let cells = pattern.get_cells()
+                        .iter()
+                        .map(|(&(x, y), &state)| ((x as i64, y as i64), state))
+                        .collect();
+
+                    inputs.add(&try!(census::apgcode(self.engine.get_rule(), &cells, 0)));
+                }
+            }
+        }
+
+        Ok(inputs)
+    }
+
+    // Runs the collisions spread over the threads
+    pub fn run(&self,
+               collisions: Vec<Collision>,
+               threads: usize)
+               -> Result<CollisionIndex, String> {
+        let pool = ThreadPool::new(threads);
+        let (tx, rx) = channel();
+        let count = collisions.len();
+
+        for collision in collisions {
+            let states = try!(self.states(&collision));
+            let inputs = try!(self.inputs(&collision));
+            let engine = self.engine.clone();
+            let tx = tx.clone();
+
+            pool.execute(move || {
+                let products = engine.settle(&states)
+                    .map(|products| products.filter(|products| *products != inputs));
+
+                if tx.send((collision, products)).is_err() {
+                    warn!("Collision search has gone, dropping a collision");
+                }
+            });
+        }
+
+        let mut index = CollisionIndex::new(self.engine.get_rule().clone());
+        for (collision, products) in rx.iter().take(count) {
+            index.add(&collision, try!(products).as_ref());
+        }
+
+        Ok(index)
+    }
+}
+
+// What came out of collisions by apgcode, with the cheapest collisions that gave each
+#[derive(Clone, Debug)]
+pub struct CollisionIndex {
+    rule: Rule,
+    collisions: u64,
+    census: Census,
+    products: BTreeMap<String, Vec<Collision>>,
+}
+
+impl CollisionIndex {
+    pub fn new(rule: Rule) -> CollisionIndex {
+        CollisionIndex {
+            rule: rule,
+            collisions: 0,
+            census: Census::new(),
+            products: BTreeMap::new(),
+        }
+    }
+
+    // What a collision gave, None if nothing happened or it didn't settle
+    pub fn add(&mut self, collision: &Collision, products: Option<&Census>) {
+        self.collisions += 1;
+
+        if let Some(products) = products {
+            self.census.merge(products);
+
+            for apgcode in products.get_counts().keys() {
+                let collisions =
+                    self.products.entry(apgcode.clone()).or_insert_with(|| Vec::new());
+                collisions.push(collision.clone());
+                collisions.sort_by_key(|collision| collision.cost());
+                collisions.truncate(MAX_SAMPLES);
+            }
+        }
+    }
+
+    pub fn get_collisions(&self) -> u64 {
+        self.collisions
+    }
+
+    pub fn get_census(&self) -> &Census {
+        &self.census
+    }
+
+    // Cheapest first
+    pub fn get_syntheses(&self, apgcode: &str) -> &[Collision] {
+        self.products.get(apgcode).map(|collisions| &collisions[..]).unwrap_or(&[])
+    }
+
+    // Census CSV with the cheapest collision for each object in the last column, after comment
+    // lines with the rule and how many collisions there were
+    pub fn to_csv(&self) -> String {
+        let mut out = format!("# rule {}\n# collisions {}\n", self.rule, self.collisions);
+
+        for (i, line) in self.census.to_csv().lines().enumerate() {
+            if i == 0 {
+                out.push_str(&format!("{},collision\n", line));
+            } else {
+                let apgcode = line.split(',').next().unwrap_or("");
+                let cheapest = self.get_syntheses(apgcode)
+                    .first()
+                    .map(|collision| collision.to_string())
+                    .unwrap_or_else(String::new);
+
+                out.push_str(&format!("{},{}\n", line, cheapest));
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use canonical;
+    use canonical::Invariance;
+    use library;
+    use rule::Rule;
+    use soup::SoupSearch;
+    use super::*;
+
+    fn gliders(max_offset: i64) -> CollisionSearch {
+        let engine = SoupSearch::new(Rule::conway(), 16, 3, 300);
+
+        CollisionSearch::new(engine,
+                             vec![("glider".to_string(), library::built_in("glider").unwrap())],
+                             max_offset)
+    }
+
+    #[test]
+    fn collision_enumerate_keeps_objects_apart() {
+        let search = gliders(4);
+        let collisions = search.enumerate(2).unwrap();
+
+        // The glider's four phases each way round come to 16 different variants
+        assert_eq!(search.variants().unwrap().len(), 16);
+        assert!(collisions.len() > 16);
+        // None are the same cells as another moved, turned or reflected
+        let mut seen = HashSet::new();
+        for collision in &collisions {
+            let states = search.states(collision).unwrap();
+            assert_eq!(states.len(), 10);
+            assert!(seen.insert(canonical::canonical(&states, Invariance::Symmetry)));
+        }
+        assert_eq!(collisions[0].get_projectiles()[0].to_string(), "glider@0;0");
+    }
+
+    #[test]
+    fn collision_index_finds_products() {
+        let search = gliders(4);
+        // Two gliders that make a traffic light, and two that never meet
+        let collisions: Vec<Collision> = search.enumerate(2)
+            .unwrap()
+            .into_iter()
+            .filter(|collision| {
+                let text = collision.to_string();
+                text == "glider@0;0 + glider@4;-4/r" || text == "glider@0;0 + glider@-4;-4"
+            })
+            .collect();
+        assert_eq!(collisions.len(), 2);
+        let index = search.run(collisions, 2).unwrap();
+
+        assert_eq!(index.get_collisions(), 2);
+        assert_eq!(index.get_census().get_count("xp2_7"), 4);
+        assert_eq!(index.get_syntheses("xp2_7")[0].to_string(),
+                   "glider@0;0 + glider@4;-4/r");
+        assert!(index.get_syntheses("xq4_153").is_empty());
+        assert_eq!(index.to_csv(),
+                   "# rule B3/S23\n# collisions 2\napgcode,count,collision\n\
+                    xp2_7,4,glider@0;0 + glider@4;-4/r\n");
+    }
+}
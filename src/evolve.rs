@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use board::Board;
+use rule::Rule;
+
+// Runs a handful of cells on their own, without sections, for looking at objects away from the
+// rest of the board. Positions can go anywhere as the cells are moved onto a board just big
+// enough to hold them and their next generation, and moved back after. The board keeps whether
+// each row and column is odd or even (or for 3D rules its z), and where it is on the whole board
+// when that is on the whole board, and its cells are at the generation given, so hexagonal,
+// triangular, Margolus, 3D and stochastic rules do what they would in the sections.
+
+// A board holding the cells and the area, with the margin around it, and the position its top
+// left cell stands for
+fn board_around(rule: &Rule,
+                cells: &HashMap<(i64, i64), u8>,
+                area: ((i64, i64), (i64, i64)),
+                margin: i64,
+                generation: usize)
+                -> (Board, (i64, i64)) {
+    let ((min_x, min_y), (max_x, max_y)) = area;
+    let left = (min_x - margin, min_y - margin);
+    let width = (max_x - min_x + 1 + 2 * margin) as u32;
+    let height = (max_y - min_y + 1 + 2 * margin) as u32;
+    let states = cells.iter()
+        .map(|(&(x, y), &state)| (((x - left.0) as u32, (y - left.1) as u32), state))
+        .collect();
+
+    let (columns, rows) = rule.get_neighbourhood_period();
+    let mut board = Board::with_states_at(width, height, &states, rule.clone(), generation);
+    board.set_origin(on_board(left.0, columns as i64), on_board(left.1, rows as i64));
+
+    (board, left)
+}
+
+// Where a position is on the whole board, or if it is above or left of the board moved on by
+// a whole number of the period, just enough to be on it
+fn on_board(position: i64, period: i64) -> u32 {
+    if position < 0 {
+        (position + (period - 1 - position) / period * period) as u32
+    } else {
+        position as u32
+    }
+}
+
+// State of the one cell a generation on. Only the cells around it are needed, so this works for
+// every rule even when cells are born with no live neighbours.
+pub fn next_state(rule: &Rule,
+                  cells: &HashMap<(i64, i64), u8>,
+                  position: (i64, i64),
+                  generation: usize)
+                  -> u8 {
+    let (x, y) = position;
+    let area = cells.keys().fold(((x, y), (x, y)), |((min_x, min_y), (max_x, max_y)), &(cx, cy)| {
+        ((min_x.min(cx), min_y.min(cy)), (max_x.max(cx), max_y.max(cy)))
+    });
+    let (board, left) = board_around(rule, cells, area, rule.get_range() as i64, generation);
+    let (bx, by) = ((x - left.0) as u32, (y - left.1) as u32);
+
+    board.next_cell(bx, by, board.get_cell(bx, by)).map(|cell| cell.get_state()).unwrap_or(0)
+}
+
+// Err if cells can't be run on their own under the rule, which is when they are born with no live
+// neighbours as every dead cell around them would come alive too
+pub fn check(rule: &Rule) -> Result<(), String> {
+    // Chances could say no wherever it is tried, what matters is whether it can happen at all
+    if let Rule::Stochastic { ref rule, .. } = *rule {
+        return check(rule);
+    }
+
+    let empty = HashMap::new();
+    for generation in 0..2 {
+        for &position in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+            if next_state(rule, &empty, position, generation) != 0 {
+                return Err(format!("Rule [{}] has cells born with no live neighbours, so \
+                                    cells can't be run on their own",
+                                   rule));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn step_checked(rule: &Rule,
+                cells: &HashMap<(i64, i64), u8>,
+                generation: usize)
+                -> HashMap<(i64, i64), u8> {
+    if cells.is_empty() {
+        return HashMap::new();
+    }
+
+    let min_x = cells.keys().map(|&(x, _)| x).min().unwrap();
+    let min_y = cells.keys().map(|&(_, y)| y).min().unwrap();
+    let max_x = cells.keys().map(|&(x, _)| x).max().unwrap();
+    let max_y = cells.keys().map(|&(_, y)| y).max().unwrap();
+
+    // Room for the cells to grow by the range, and a dead edge outside that
+    let margin = 2 * rule.get_range() as i64;
+    let (board, left) =
+        board_around(rule, cells, ((min_x, min_y), (max_x, max_y)), margin, generation);
+
+    let halo = board.get_halo();
+    let mut next = HashMap::new();
+    for x in halo..board.get_width() - halo {
+        for y in halo..board.get_height() - halo {
+            let state = board.next_cell(x, y, board.get_cell(x, y))
+                .map(|cell| cell.get_state())
+                .unwrap_or(0);
+
+            if state != 0 {
+                next.insert((x as i64 + left.0, y as i64 + left.1), state);
+            }
+        }
+    }
+
+    next
+}
+
+// Non zero states of the cells a generation on from the generation they are at, keyed the same
+// way. Err if the rule can't be run on its own, see check.
+pub fn step(rule: &Rule,
+            cells: &HashMap<(i64, i64), u8>,
+            generation: usize)
+            -> Result<HashMap<(i64, i64), u8>, String> {
+    try!(check(rule));
+
+    Ok(step_checked(rule, cells, generation))
+}
+
+// The cells after each of the generations, the first being the cells as given at the generation
+pub fn phases(rule: &Rule,
+              cells: &HashMap<(i64, i64), u8>,
+              generation: usize,
+              generations: usize)
+              -> Result<Vec<HashMap<(i64, i64), u8>>, String> {
+    try!(check(rule));
+    let mut all = vec![cells.clone()];
+
+    for i in 1..generations {
+        let next = step_checked(rule, all.last().unwrap(), generation + i - 1);
+        all.push(next);
+    }
+
+    Ok(all)
+}
+
+// Cells moved so their bounding box starts at 0, 0, and where it started
+pub fn normalise(cells: &HashMap<(i64, i64), u8>) -> (HashMap<(i64, i64), u8>, (i64, i64)) {
+    let min_x = cells.keys().map(|&(x, _)| x).min().unwrap_or(0);
+    let min_y = cells.keys().map(|&(_, y)| y).min().unwrap_or(0);
+    let moved = cells.iter().map(|(&(x, y), &state)| ((x - min_x, y - min_y), state)).collect();
+
+    (moved, (min_x, min_y))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use headless::HeadlessRun;
+    use rule::Rule;
+    use test_support::cells;
+    use whole::Whole;
+    use super::*;
+
+    #[test]
+    fn evolve_step_goes_past_zero() {
+        let blinker = cells(&[(-1, 0), (0, 0), (1, 0)]);
+
+        let next = step(&Rule::conway(), &blinker, 0).unwrap();
+
+        assert_eq!(next, cells(&[(0, -1), (0, 0), (0, 1)]));
+        assert_eq!(phases(&Rule::conway(), &blinker, 0, 3).unwrap()[2], blinker);
+    }
+
+    #[test]
+    fn evolve_same_as_sections_wherever_and_whenever() {
+        let at = |run: &HeadlessRun, dx: i64, dy: i64| -> HashMap<(i64, i64), u8> {
+            run.get_cells()
+                .into_iter()
+                .map(|((x, y), state)| ((x as i64 - dx, y as i64 - dy), state))
+                .collect()
+        };
+
+        // Billiard balls go by the generation, hexagons and triangles by where the cell is
+        for &(name, start) in [("BBM", 0), ("BBM", 1), ("B2/S34H", 0), ("B4/S345L", 0)].iter() {
+            let rule = Rule::parse(name).unwrap();
+            let states: HashMap<(u32, u32), u8> =
+                cells(&[(9, 9), (12, 9), (10, 10), (11, 11), (13, 12), (9, 13)]);
+
+            // Moved up and left by even numbers the rows and columns stay odd or even, including
+            // off the top left of the board
+            for &(dx, dy) in [(0, 0), (8, 4), (14, 12)].iter() {
+                let created = Whole::create_sections_with(12, 12, 3, rule.clone(), &states);
+                let mut run = HeadlessRun::new(created);
+                for _ in 0..start {
+                    run.step().unwrap();
+                }
+
+                for phase in phases(&rule, &at(&run, dx, dy), start, 4).unwrap().iter().skip(1) {
+                    run.step().unwrap();
+                    assert_eq!(*phase,
+                               at(&run, dx, dy),
+                               "{} from {} moved {:?}",
+                               name,
+                               start,
+                               (dx, dy));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn evolve_rejects_births_from_nothing() {
+        let rule = Rule::parse("B0/S8").unwrap();
+
+        assert!(check(&Rule::conway()).is_ok());
+        assert!(step(&rule, &HashMap::new(), 0).is_err());
+        assert!(phases(&Rule::parse("Critters").unwrap(), &cells(&[(0, 0)]), 0, 2).is_err());
+        assert_eq!(next_state(&rule, &HashMap::new(), (-3, 5), 0), 1);
+    }
+}
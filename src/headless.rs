@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::sync::mpsc::SyncSender;
+use board::Cell;
+use periodicity::PeriodDetector;
+use periodicity::Periodicity;
+use rule::Rule;
+use section::BoardSection;
+use section::BoardSectionSide;
+use section::HaloMessage;
+use view::Rectangle;
+use whole::Whole;
+
+// Passes over the sections in a row that no cell can move on in before a step gives up. Halos can
+// take a few passes to get through, and longer going round a corner through another section.
+const STALLED_PASSES: usize = 10;
+
+// Runs a whole board without a window, a generation at a time, feeding the outside edges dead
+// cells as the viewer does
+pub struct HeadlessRun {
+    whole: Whole,
+    rule: Rule,
+    edge_senders: HashMap<BoardSectionSide, Vec<SyncSender<HaloMessage>>>,
+    // Size of each section and its joining cells, for the dead cells sent to the edges
+    section_size: (u32, u32),
+    halo: u32,
+    halo_rows: u32,
+    generation: usize,
+}
+
+impl HeadlessRun {
+    // Takes what Whole::create_sections_with (and the others) give back
+    pub fn new(created: (Vec<Vec<Box<BoardSection>>>,
+                         HashMap<BoardSectionSide, Vec<SyncSender<HaloMessage>>>,
+                         Box<[(Rectangle, Sender<Sender<Box<[Box<[Cell]>]>>>)]>))
+               -> HeadlessRun {
+        let (sections, edge_senders, _) = created;
+        let (rule, section_size, halo, halo_rows) = {
+            let board = sections[0][0].get_board();
+
+            (board.get_rule().clone(),
+             (board.get_width(), board.get_height()),
+             board.get_halo(),
+             board.get_halo_rows())
+        };
+        let whole = Whole::new(sections);
+        let generation = whole.metrics().get_min_generation();
+
+        HeadlessRun {
+            whole: whole,
+            rule: rule,
+            edge_senders: edge_senders,
+            section_size: section_size,
+            halo: halo,
+            halo_rows: halo_rows,
+            generation: generation,
+        }
+    }
+
+    pub fn get_whole(&self) -> &Whole {
+        &self.whole
+    }
+
+    pub fn get_rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    pub fn get_generation(&self) -> usize {
+        self.generation
+    }
+
+    pub fn get_cells(&self) -> HashMap<(u32, u32), u8> {
+        self.whole
+            .get_cells_at(self.generation)
+            .expect("Every section should be at the run's generation")
+    }
+
+    fn send_edges(&self, iteration: usize) {
+        for (&side, senders) in self.edge_senders.iter() {
+            let size = match side {
+                BoardSectionSide::Top | BoardSectionSide::Bottom => {
+                    self.section_size.0 * self.halo_rows
+                }
+                BoardSectionSide::Left | BoardSectionSide::Right => {
+                    self.section_size.1 * self.halo
+                }
+            };
+            let cells = Arc::new(vec![Cell::new(false, iteration, false); size as usize]);
+
+            for sender in senders {
+                // A full channel already has the edge for a generation the section needs
+                let _ = sender.try_send(HaloMessage::Cells(cells.clone()));
+            }
+        }
+    }
+
+    // Moves every section on to the next generation. Err if the sections stop moving on before
+    // they get there, such as when one is held at a checkpoint.
+    pub fn step(&mut self) -> Result<(), String> {
+        let next = self.generation + 1;
+        let mut updated = None;
+        let mut stalled = 0;
+
+        while self.whole.get_cells_at(next).is_none() {
+            self.send_edges(next);
+
+            let mut now_updated = 0;
+            for x in 0..self.whole.sections_width() {
+                for y in 0..self.whole.sections_height() {
+                    let section = self.whole.get_section(x, y);
+
+                    section.try_iteration(next);
+                    now_updated += section.get_metrics().get_cells_updated();
+                }
+            }
+
+            stalled = if updated == Some(now_updated) { stalled + 1 } else { 0 };
+            if stalled >= STALLED_PASSES {
+                return Err(format!("Sections stopped moving on before generation [{}]", next));
+            }
+            updated = Some(now_updated);
+        }
+
+        self.whole.update_population();
+        self.generation = next;
+        Ok(())
+    }
+
+    // Steps until the cells repeat or the generation is reached, the detector is given every
+    // generation including the one the run is at. Err if a step can't be made, see step.
+    pub fn run_until_stable(&mut self,
+                            detector: &mut PeriodDetector,
+                            until_generation: usize)
+                            -> Result<Option<Periodicity>, String> {
+        loop {
+            let cells = self.get_cells();
+
+            if let Some(found) = detector.add(self.generation, &cells) {
+                return Ok(Some(found));
+            }
+            if self.generation >= until_generation {
+                return Ok(None);
+            }
+
+            try!(self.step());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::collections::HashSet;
+    use std::mem;
+    use periodicity::PeriodDetector;
+    use periodicity::DEFAULT_MAX_PERIOD;
+    use rule::Rule;
+    use rule::space_position;
+    use whole::Whole;
+    use super::*;
+
+    #[test]
+    fn headless_run_stops_when_glider_repeats() {
+        let mut states = HashMap::new();
+        for &position in [(4, 3), (5, 4), (3, 5), (4, 5), (5, 5)].iter() {
+            states.insert(position, 1);
+        }
+        let created = Whole::create_sections_with(10, 10, 3, Rule::conway(), &states);
+        let mut run = HeadlessRun::new(created);
+        let mut detector = PeriodDetector::new(DEFAULT_MAX_PERIOD);
+
+        let found = run.run_until_stable(&mut detector, 20).unwrap().unwrap();
+
+        assert_eq!((found.get_period(), found.get_displacement()), (4, (1, 1)));
+        assert_eq!(run.get_generation(), 4);
+        assert_eq!(run.get_cells().len(), 5);
+    }
+
+    #[test]
+    fn headless_run_finds_block_across_sections() {
+        // A pre-block, settling into a block over the corner of four sections
+        let mut states = HashMap::new();
+        for &position in [(8, 8), (9, 8), (8, 9)].iter() {
+            states.insert(position, 1);
+        }
+        let created = Whole::create_sections_with(10, 10, 2, Rule::conway(), &states);
+        let mut run = HeadlessRun::new(created);
+        let mut detector = PeriodDetector::new(DEFAULT_MAX_PERIOD);
+
+        let found = run.run_until_stable(&mut detector, 20).unwrap().unwrap();
+
+        assert!(found.is_still());
+        assert_eq!(found.get_stabilised_at(), 1);
+        assert_eq!(run.get_whole().get_population().get_last().map(|p| p.get_live()), Some(4));
+    }
+
+    #[test]
+    fn headless_run_gives_up_when_sections_stop_moving_on() {
+        // Without the dead cells at the outside edges the sections there can't get past the
+        // first generation
+        let (sections, _, registerers) =
+            Whole::create_sections_with(10, 10, 2, Rule::conway(), &HashMap::new());
+        let mut run = HeadlessRun::new((sections, HashMap::new(), registerers));
+
+        assert!(run.step().is_ok());
+        assert!(run.step().is_err());
+        assert_eq!(run.get_generation(), 1);
+    }
+
+    #[test]
+    fn headless_run_three_dimensional_across_sections() {
+        let rule = Rule::parse("3D4,5,5,5,D4").unwrap();
+        // Own cells are x 1 to 9 through every z and y 5 to 13, the rest is halo
+        let own = |&(x, y, _): &(u32, u32, u32)| x >= 1 && x <= 9 && y >= 5 && y <= 13;
+        let mut live = HashSet::new();
+        for x in 1..10 {
+            for y in 5..14 {
+                for z in 0..4 {
+                    if (x * 7 + y * 5 + z * 3) % 4 == 0 {
+                        live.insert((x, y, z));
+                    }
+                }
+            }
+        }
+        let states = live.iter().map(|&position| (position, 1)).collect();
+        let created = Whole::create_space_sections(3, 3, 3, rule.clone(), &states);
+        let mut run = HeadlessRun::new(created);
+
+        for _ in 0..4 {
+            let mut next = HashSet::new();
+            for x in 1..10 {
+                for y in 5..14 {
+                    for z in 0..4 {
+                        let near = |a: u32, b: u32| a + 1 >= b && a <= b + 1;
+                        let count = live.iter()
+                            .filter(|&&(nx, ny, nz)| near(nx, x) && near(ny, y) && near(nz, z))
+                            .filter(|&&position| position != (x, y, z))
+                            .count() as u16;
+
+                        let state = if live.contains(&(x, y, z)) { 1 } else { 0 };
+                        if rule.next_state(state, count) == 1 {
+                            next.insert((x, y, z));
+                        }
+                    }
+                }
+            }
+            live = next;
+            run.step().unwrap();
+
+            let cells: HashSet<(u32, u32, u32)> = run.get_cells()
+                .keys()
+                .map(|&(column, y)| {
+                    let (x, z) = space_position(column, 4).unwrap();
+                    (x, y, z)
+                })
+                .collect();
+            assert!(cells.iter().all(&own));
+            assert_eq!(cells, live);
+        }
+        assert!(!live.is_empty());
+    }
+
+    fn three_dimensional_run() -> HeadlessRun {
+        let rule = Rule::parse("3D4,5,5,5,D4").unwrap();
+        let mut states = HashMap::new();
+        for x in 1..10 {
+            for y in 5..14 {
+                for z in 0..4 {
+                    if (x * 7 + y * 5 + z * 3) % 4 == 0 {
+                        states.insert((x, y, z), 1);
+                    }
+                }
+            }
+        }
+
+        HeadlessRun::new(Whole::create_space_sections(3, 3, 3, rule, &states))
+    }
+
+    #[test]
+    fn headless_run_three_dimensional_sends_one_row_up_and_down() {
+        let mut run = three_dimensional_run();
+        run.step().unwrap();
+        run.step().unwrap();
+
+        let cell_size = mem::size_of::<Cell>() as u64;
+        let metrics = run.get_whole().metrics();
+        let section = metrics.get_section(0, 0).unwrap();
+        let below = section.get_link(BoardSectionSide::Bottom).unwrap();
+        let right = section.get_link(BoardSectionSide::Right).unwrap();
+
+        assert!(below.get_messages_sent() > 0);
+        assert_eq!(below.get_bytes_sent(),
+                   below.get_messages_sent() * run.section_size.0 as u64 * cell_size);
+        assert_eq!(right.get_bytes_sent(),
+                   right.get_messages_sent() * (run.section_size.1 * run.halo) as u64 * cell_size);
+    }
+
+    #[test]
+    fn headless_run_three_dimensional_checkpoint_restores() {
+        let mut expected = three_dimensional_run();
+        for _ in 0..4 {
+            expected.step().unwrap();
+        }
+
+        let mut run = three_dimensional_run();
+        run.step().unwrap();
+        run.step().unwrap();
+        assert_eq!(run.whole.request_checkpoint(), Ok(2));
+        run.step().unwrap();
+        let checkpoint = run.whole.try_take_checkpoint().unwrap();
+        assert_eq!(checkpoint.get_generation(), 2);
+
+        let mut restored = HeadlessRun::new(Whole::restore_sections(&checkpoint));
+        restored.step().unwrap();
+        restored.step().unwrap();
+        assert_eq!(restored.get_generation(), 4);
+        assert_eq!(restored.get_cells(), expected.get_cells());
+    }
+}
@@ -0,0 +1,232 @@
+// Hensel notation for isotropic non-totalistic rules. Each neighbour count is split into the
+// configurations that are the same under rotation and reflection, each given a letter.
+//
+// A configuration is the 3x3 block around a cell as bits, (dy + 1) * 3 + dx + 1 so the top left
+// is bit 0 and the middle cell is bit 4.
+
+pub const TABLE_SIZE: usize = 512;
+const MIDDLE: u16 = 1 << 4;
+const NEIGHBOURS: u16 = 0x1ff ^ MIDDLE;
+
+// One configuration for each letter of the neighbour counts up to 4, in the same order as the
+// letters. Counts above 4 use the opposite configurations of 8 - count.
+const REPRESENTATIVES: [&'static [u16]; 5] = [&[0],
+                                              &[1, 2],
+                                              &[5, 10, 33, 3, 40, 68],
+                                              &[69, 42, 98, 11, 7, 13, 97, 70, 14, 41],
+                                              &[325, 170, 99, 15, 45, 71, 101, 102, 106, 43,
+                                                105, 78, 108]];
+
+pub fn letters(count: usize) -> &'static str {
+    match count {
+        1 | 7 => "ce",
+        2 | 6 => "cekain",
+        3 | 5 => "cekainyqjr",
+        4 => "cekainyqjrtwz",
+        _ => "",
+    }
+}
+
+// Conditions are a mask per neighbour count of which letters are included, counts without
+// letters (0 and 8) just use the lowest bit
+fn all_letters(count: usize) -> u16 {
+    (1 << letters(count).len().max(1)) - 1
+}
+
+fn representative(count: usize, letter: usize) -> u16 {
+    if count <= 4 {
+        REPRESENTATIVES[count][letter]
+    } else {
+        NEIGHBOURS ^ REPRESENTATIVES[8 - count][letter]
+    }
+}
+
+// Moves each bit of the configuration to where the position function puts its (row, column)
+fn transform(configuration: u16, position: &Fn(u16, u16) -> (u16, u16)) -> u16 {
+    let mut transformed = 0;
+    for bit in 0..9 {
+        if configuration & (1 << bit) != 0 {
+            let (row, column) = position(bit / 3, bit % 3);
+            transformed |= 1 << (row * 3 + column);
+        }
+    }
+
+    transformed
+}
+
+// The configuration in all 8 rotations and reflections, some may be the same
+fn symmetries(configuration: u16) -> Vec<u16> {
+    let mut all = Vec::with_capacity(8);
+    let mut rotated = configuration;
+
+    for _ in 0..4 {
+        all.push(rotated);
+        all.push(transform(rotated, &|row, column| (row, 2 - column)));
+        rotated = transform(rotated, &|row, column| (column, 2 - row));
+    }
+
+    all
+}
+
+// Parses the part after B or S, such as 2-a3 or 12ce, into conditions
+pub fn parse_conditions(text: &str) -> Result<[u16; 9], String> {
+    let mut conditions = [0; 9];
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let count = match c.to_digit(10) {
+            Some(d) if d <= 8 => d as usize,
+            _ => return Err(format!("Bad neighbour count [{}]", c)),
+        };
+        if conditions[count] != 0 {
+            return Err(format!("Neighbour count [{}] repeated", count));
+        }
+
+        let excluded = chars.peek() == Some(&'-');
+        if excluded {
+            chars.next();
+        }
+
+        let mut mask = 0;
+        while let Some(&letter) = chars.peek() {
+            if letter.is_digit(10) {
+                break;
+            }
+            chars.next();
+
+            match letters(count).find(letter) {
+                Some(i) => mask |= 1 << i,
+                None => {
+                    return Err(format!("No [{}] configuration of [{}] neighbours", letter, count))
+                }
+            }
+        }
+
+        conditions[count] = match (excluded, mask) {
+            (true, 0) => return Err(format!("Nothing after - for [{}] neighbours", count)),
+            (true, mask) => all_letters(count) & !mask,
+            (false, 0) => all_letters(count),
+            (false, mask) => mask,
+        };
+    }
+
+    Ok(conditions)
+}
+
+// Whether every count is either all in or all out, so only the count matters
+pub fn is_totalistic(conditions: &[u16; 9]) -> bool {
+    conditions.iter().enumerate().all(|(count, &mask)| mask == 0 || mask == all_letters(count))
+}
+
+// Which configurations (including the middle cell) give a live cell, birth conditions apply
+// when the middle cell is dead and survival ones when it is alive
+pub fn create_table(birth: &[u16; 9], survival: &[u16; 9]) -> Vec<bool> {
+    let mut table = vec![false; TABLE_SIZE];
+
+    for count in 0..9 {
+        for letter in 0..letters(count).len().max(1) {
+            for configuration in symmetries(representative(count, letter)) {
+                table[configuration as usize] = birth[count] & (1 << letter) != 0;
+                table[(configuration | MIDDLE) as usize] = survival[count] & (1 << letter) != 0;
+            }
+        }
+    }
+
+    table
+}
+
+// The birth (middle dead) or survival (middle alive) conditions that made the table
+pub fn table_conditions(table: &[bool], alive: bool) -> [u16; 9] {
+    let middle = if alive { MIDDLE } else { 0 };
+    let mut conditions = [0; 9];
+
+    for count in 0..9 {
+        for letter in 0..letters(count).len().max(1) {
+            if table[(representative(count, letter) | middle) as usize] {
+                conditions[count] |= 1 << letter;
+            }
+        }
+    }
+
+    conditions
+}
+
+// Writes whichever of the included or excluded letters is shorter, or just the count if all are
+pub fn format_conditions(conditions: &[u16; 9]) -> String {
+    let mut text = String::new();
+
+    for (count, &mask) in conditions.iter().enumerate() {
+        if mask == 0 {
+            continue;
+        }
+
+        text.push_str(&count.to_string());
+        if mask == all_letters(count) {
+            continue;
+        }
+
+        let included: String = letters(count)
+            .chars()
+            .enumerate()
+            .filter(|&(i, _)| mask & (1 << i) != 0)
+            .map(|(_, l)| l)
+            .collect();
+        let excluded: String = letters(count)
+            .chars()
+            .enumerate()
+            .filter(|&(i, _)| mask & (1 << i) == 0)
+            .map(|(_, l)| l)
+            .collect();
+
+        if excluded.len() < included.len() {
+            text.push('-');
+            text.push_str(&excluded);
+        } else {
+            text.push_str(&included);
+        }
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hensel_letters_cover_each_configuration_once() {
+        let mut seen = vec![0; TABLE_SIZE];
+
+        for count in 0..9 {
+            for letter in 0..letters(count).len().max(1) {
+                let mut classes = symmetries(representative(count, letter));
+                classes.sort();
+                classes.dedup();
+
+                for configuration in classes {
+                    assert_eq!((configuration & NEIGHBOURS).count_ones() as usize, count);
+                    seen[configuration as usize] += 1;
+                }
+            }
+        }
+
+        for configuration in 0..TABLE_SIZE {
+            let expected = if configuration as u16 & MIDDLE == 0 { 1 } else { 0 };
+            assert_eq!(seen[configuration], expected, "configuration [{}]", configuration);
+        }
+    }
+
+    #[test]
+    fn hensel_parse_conditions() {
+        let conditions = parse_conditions("2-a3").unwrap();
+
+        assert_eq!(conditions[2], 0b110111);
+        assert_eq!(conditions[3], 0b1111111111);
+        assert_eq!(format_conditions(&conditions), "2-a3");
+        assert_eq!(format_conditions(&parse_conditions("4qc").unwrap()), "4cq");
+        assert!(parse_conditions("1a").is_err());
+        assert!(parse_conditions("2-").is_err());
+        assert!(parse_conditions("22").is_err());
+        assert!(parse_conditions("9").is_err());
+    }
+}
@@ -0,0 +1,413 @@
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+use std::sync::mpsc::Sender;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::TryRecvError;
+use std::sync::mpsc::channel;
+use board::Cell;
+use view::Rectangle;
+
+// Every this many generations a full copy of a section is kept, the generations in between only
+// store the cells that changed. Keeps lookups cheap without storing every generation in full.
+const KEYFRAME_INTERVAL: usize = 32;
+
+enum Frame {
+    Key(Vec<u8>),
+    // Index (sorted) and new state of the cells that changed since the previous generation
+    Delta(Vec<(u32, u8)>),
+}
+
+struct PartialFrame {
+    cells: Vec<Option<u8>>,
+    known: usize,
+}
+
+impl PartialFrame {
+    fn new(size: usize) -> PartialFrame {
+        PartialFrame {
+            cells: vec![None; size],
+            known: 0,
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.known == self.cells.len()
+    }
+}
+
+// History of the interior (non joining) cells of a single section
+struct HistoryPart {
+    covered: Rectangle,
+    halo: u32,
+    receiver: Receiver<Box<[Box<[Cell]>]>>,
+    pending: BTreeMap<usize, PartialFrame>,
+    first_generation: usize,
+    frames: VecDeque<Frame>,
+    latest: Vec<u8>,
+}
+
+impl HistoryPart {
+    fn new(covered: Rectangle, halo: u32, receiver: Receiver<Box<[Box<[Cell]>]>>) -> HistoryPart {
+        HistoryPart {
+            covered: covered,
+            halo: halo,
+            receiver: receiver,
+            pending: BTreeMap::new(),
+            first_generation: 0,
+            frames: VecDeque::new(),
+            latest: Vec::new(),
+        }
+    }
+
+    fn inner_width(&self) -> u32 {
+        self.covered.get_width().checked_sub(2 * self.halo).unwrap_or(0)
+    }
+
+    fn inner_height(&self) -> u32 {
+        self.covered.get_height().checked_sub(2 * self.halo).unwrap_or(0)
+    }
+
+    fn size(&self) -> usize {
+        (self.inner_width() * self.inner_height()) as usize
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        (x * self.inner_height() + y) as usize
+    }
+
+    fn owns(&self, x: u32, y: u32) -> bool {
+        x >= self.covered.get_start_x() + self.halo && x + self.halo < self.covered.get_end_x() &&
+        y >= self.covered.get_start_y() + self.halo && y + self.halo < self.covered.get_end_y()
+    }
+
+    fn newest_generation(&self) -> Option<usize> {
+        if self.frames.is_empty() {
+            None
+        } else {
+            Some(self.first_generation + self.frames.len() - 1)
+        }
+    }
+
+    fn next_generation(&self) -> Option<usize> {
+        self.newest_generation().map(|g| g + 1)
+    }
+
+    fn record(&mut self, cells: &Box<[Box<[Cell]>]>, retention: usize) {
+        let size = self.size();
+
+        for x in 0..self.inner_width() {
+            for y in 0..self.inner_height() {
+                let cell = cells[(x + self.halo) as usize][(y + self.halo) as usize];
+                let index = self.index(x, y);
+
+                self.learn(size, index, cell.get_iteration(), cell.get_state());
+                if cell.get_iteration() > 0 {
+                    self.learn(size, index, cell.get_iteration() - 1, cell.get_previous_state());
+                }
+            }
+        }
+
+        self.commit(retention);
+    }
+
+    fn learn(&mut self, size: usize, index: usize, generation: usize, state: u8) {
+        if self.next_generation().map(|n| generation < n).unwrap_or(false) {
+            return;
+        }
+
+        let frame = self.pending.entry(generation).or_insert_with(|| PartialFrame::new(size));
+        if frame.cells[index].is_none() {
+            frame.cells[index] = Some(state);
+            frame.known += 1;
+        }
+    }
+
+    fn commit(&mut self, retention: usize) {
+        loop {
+            let generation = match self.pending.iter().find(|&(_, f)| f.is_complete()) {
+                Some((&g, _)) => g,
+                None => break,
+            };
+
+            // Anything older than a complete generation will never be filled in
+            let older: Vec<usize> =
+                self.pending.keys().cloned().filter(|&g| g < generation).collect();
+            for g in older {
+                debug!("Dropping incomplete history generation [{}]", g);
+                self.pending.remove(&g);
+            }
+
+            let frame = self.pending.remove(&generation).unwrap();
+            let cells: Vec<u8> = frame.cells.into_iter().map(|c| c.unwrap()).collect();
+
+            if self.next_generation() != Some(generation) {
+                // Nothing before this, or a gap we can't fill so start again from here
+                self.frames.clear();
+                self.first_generation = generation;
+                self.frames.push_back(Frame::Key(cells.clone()));
+            } else if generation % KEYFRAME_INTERVAL == 0 {
+                self.frames.push_back(Frame::Key(cells.clone()));
+            } else {
+                let changed = self.latest
+                    .iter()
+                    .zip(cells.iter())
+                    .enumerate()
+                    .filter(|&(_, (a, b))| a != b)
+                    .map(|(i, (_, &b))| (i as u32, b))
+                    .collect();
+                self.frames.push_back(Frame::Delta(changed));
+            }
+
+            self.latest = cells;
+            self.trim(retention);
+        }
+    }
+
+    fn trim(&mut self, retention: usize) {
+        while self.frames.len() > retention {
+            let oldest = match self.frames.pop_front() {
+                Some(Frame::Key(cells)) => cells,
+                _ => panic!("Oldest history frame should always be a key frame"),
+            };
+            self.first_generation += 1;
+
+            // Make sure we can still rebuild from the new oldest generation
+            let replacement = match self.frames.front() {
+                Some(&Frame::Delta(ref changed)) => {
+                    let mut cells = oldest;
+                    for &(i, state) in changed {
+                        cells[i as usize] = state;
+                    }
+                    Some(cells)
+                }
+                _ => None,
+            };
+            if let Some(cells) = replacement {
+                self.frames[0] = Frame::Key(cells);
+            }
+        }
+    }
+
+    fn state_at(&self, index: usize, generation: usize) -> Option<u8> {
+        if generation < self.first_generation {
+            return None;
+        }
+        let last = generation - self.first_generation;
+        if last >= self.frames.len() {
+            return None;
+        }
+
+        let mut key = last;
+        loop {
+            match self.frames[key] {
+                Frame::Key(_) => break,
+                Frame::Delta(_) => key -= 1,
+            }
+        }
+
+        let mut state = match self.frames[key] {
+            Frame::Key(ref cells) => cells[index],
+            Frame::Delta(_) => unreachable!(),
+        };
+        for i in (key + 1)..(last + 1) {
+            if let Frame::Delta(ref changed) = self.frames[i] {
+                if let Ok(found) = changed.binary_search_by_key(&(index as u32), |&(i, _)| i) {
+                    state = changed[found].1;
+                }
+            }
+        }
+
+        Some(state)
+    }
+}
+
+pub struct History {
+    retention: usize,
+    parts: Vec<HistoryPart>,
+}
+
+impl History {
+    // The halo is how wide the joining cells around each section are
+    pub fn new(retention: usize,
+               halo: u32,
+               registerers: &[(Rectangle, Sender<Sender<Box<[Box<[Cell]>]>>>)])
+               -> History {
+        let mut parts = Vec::with_capacity(registerers.len());
+
+        for &(covered, ref sender) in registerers.iter() {
+            let (tx, rx) = channel();
+
+            match sender.send(tx) {
+                Ok(_) => parts.push(HistoryPart::new(covered, halo, rx)),
+                Err(_) => {
+                    warn!("Section no longer taking state senders, it won't be in the history")
+                }
+            }
+        }
+
+        History {
+            retention: if retention > 0 {
+                retention
+            } else {
+                1
+            },
+            parts: parts,
+        }
+    }
+
+    pub fn get_retention(&self) -> usize {
+        self.retention
+    }
+
+    // Read any new states from the sections, needs to be called regularly as sections
+    // only send their current state and each cell only remembers one previous generation
+    pub fn update(&mut self) {
+        let retention = self.retention;
+
+        for part in &mut self.parts {
+            loop {
+                match part.receiver.try_recv() {
+                    Ok(cells) => part.record(&cells, retention),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => break,
+                }
+            }
+        }
+    }
+
+    // Oldest generation every section still has
+    pub fn get_oldest_generation(&self) -> Option<usize> {
+        if self.parts.iter().any(|p| p.size() > 0 && p.frames.is_empty()) {
+            return None;
+        }
+
+        self.parts.iter().filter(|p| p.size() > 0).map(|p| p.first_generation).max()
+    }
+
+    // Newest generation every section has completed
+    pub fn get_newest_generation(&self) -> Option<usize> {
+        if self.parts.iter().any(|p| p.size() > 0 && p.frames.is_empty()) {
+            return None;
+        }
+
+        self.parts.iter().filter_map(|p| p.newest_generation()).min()
+    }
+
+    pub fn cell_at(&self, x: u32, y: u32, generation: usize) -> Option<bool> {
+        self.state_at(x, y, generation).map(|state| state == 1)
+    }
+
+    pub fn state_at(&self, x: u32, y: u32, generation: usize) -> Option<u8> {
+        self.parts
+            .iter()
+            .find(|p| p.owns(x, y))
+            .and_then(|p| {
+                let index = p.index(x - p.covered.get_start_x() - p.halo,
+                                    y - p.covered.get_start_y() - p.halo);
+                p.state_at(index, generation)
+            })
+    }
+
+    // Calls back with every cell known at the generation, returns false if any section
+    // doesn't have the generation (anymore)
+    pub fn foreach_cell_at(&self, generation: usize, callback: &mut FnMut(u8, u32, u32)) -> bool {
+        let mut all = true;
+
+        // Parts with no cells of their own have nothing to remember
+        for part in self.parts.iter().filter(|p| p.size() > 0) {
+            if part.state_at(0, generation).is_none() {
+                all = false;
+                continue;
+            }
+
+            for x in 0..part.inner_width() {
+                for y in 0..part.inner_height() {
+                    let state = part.state_at(part.index(x, y), generation).unwrap();
+
+                    callback(state,
+                             part.covered.get_start_x() + x + part.halo,
+                             part.covered.get_start_y() + y + part.halo);
+                }
+            }
+        }
+
+        all
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+    use board::Cell;
+    use view::Rectangle;
+    use super::*;
+
+    fn send_board(tx: &::std::sync::mpsc::Sender<Box<[Box<[Cell]>]>>, cells: [[Cell; 3]; 3]) {
+        let board: Vec<Box<[Cell]>> = cells.iter().map(|c| c.to_vec().into_boxed_slice()).collect();
+        tx.send(board.into_boxed_slice()).unwrap();
+    }
+
+    fn single_cell_history(retention: usize)
+                           -> (History, ::std::sync::mpsc::Sender<Box<[Box<[Cell]>]>>) {
+        let (registerer, registrations) = channel();
+        let history = History::new(retention, 1, &[(Rectangle::new(0, 0, 3, 3), registerer)]);
+        let tx = registrations.recv().unwrap();
+
+        (history, tx)
+    }
+
+    #[test]
+    fn history_remembers_past_generations() {
+        let (mut history, tx) = single_cell_history(10);
+
+        for i in 1..6 {
+            let alive = i % 2 == 0;
+            let cell = Cell::new(alive, i, !alive);
+            send_board(&tx, [[cell; 3]; 3]);
+        }
+        history.update();
+
+        assert_eq!(history.get_oldest_generation(), Some(0));
+        assert_eq!(history.get_newest_generation(), Some(5));
+        assert_eq!(history.cell_at(1, 1, 0), Some(true));
+        assert_eq!(history.cell_at(1, 1, 3), Some(false));
+        assert_eq!(history.cell_at(1, 1, 4), Some(true));
+        assert_eq!(history.cell_at(1, 1, 6), None);
+        assert_eq!(history.cell_at(0, 0, 4), None);
+    }
+
+    #[test]
+    fn history_skips_parts_with_no_cells_of_their_own() {
+        let (registerer, registrations) = channel();
+        let (empty_registerer, _empty_registrations) = channel();
+        let mut history = History::new(10,
+                                       1,
+                                       &[(Rectangle::new(0, 0, 3, 3), registerer),
+                                         (Rectangle::new(3, 0, 2, 2), empty_registerer)]);
+        let tx = registrations.recv().unwrap();
+        send_board(&tx, [[Cell::new(true, 1, false); 3]; 3]);
+        history.update();
+
+        let mut cells = Vec::new();
+        assert!(history.foreach_cell_at(1, &mut |state, x, y| cells.push((state, x, y))));
+        assert_eq!(cells, vec![(1, 1, 1)]);
+        assert_eq!(history.get_newest_generation(), Some(1));
+    }
+
+    #[test]
+    fn history_drops_generations_outside_retention() {
+        let (mut history, tx) = single_cell_history(3);
+
+        for i in 1..50 {
+            let alive = i % 3 == 0;
+            let was_alive = (i - 1) % 3 == 0;
+            send_board(&tx, [[Cell::new(alive, i, was_alive); 3]; 3]);
+        }
+        history.update();
+
+        assert_eq!(history.get_oldest_generation(), Some(47));
+        assert_eq!(history.cell_at(1, 1, 46), None);
+        assert_eq!(history.cell_at(1, 1, 47), Some(false));
+        assert_eq!(history.cell_at(1, 1, 48), Some(true));
+    }
+}
@@ -5,4 +5,28 @@ extern crate threadpool;
 pub mod board;
 pub mod section;
 pub mod whole;
-pub mod view;
\ No newline at end of file
+pub mod view;
+pub mod history;
+pub mod checkpoint;
+pub mod snapshot;
+pub mod metrics;
+pub mod population;
+pub mod periodicity;
+pub mod canonical;
+pub mod headless;
+pub mod evolve;
+pub mod census;
+pub mod tracking;
+pub mod soup;
+pub mod library;
+pub mod predecessor;
+pub mod search;
+pub mod collision;
+pub mod rule;
+pub mod hensel;
+pub mod table;
+pub mod random;
+pub mod rle;
+
+#[cfg(test)]
+mod test_support;
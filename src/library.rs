@@ -0,0 +1,270 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use evolve;
+use rle;
+use rle::RlePattern;
+use rule::Rule;
+
+// Patterns everyone starts with, by name, as RLE
+pub const BUILT_INS: &'static [(&'static str, &'static str)] =
+    &[("glider", "x = 3, y = 3, rule = B3/S23\nbob$2bo$3o!"),
+      ("lwss", "x = 5, y = 4, rule = B3/S23\nbo2bo$o4b$o3bo$4o!"),
+      ("r-pentomino", "x = 3, y = 3, rule = B3/S23\nb2o$2o$bo!"),
+      ("acorn", "x = 7, y = 3, rule = B3/S23\nbo$3bo$2o2b3o!"),
+      ("pulsar",
+       "x = 13, y = 13, rule = B3/S23\n2b3o3b3o2$o4bobo4bo$o4bobo4bo$o4bobo4bo$2b3o3b3o2$\
+        2b3o3b3o$o4bobo4bo$o4bobo4bo$o4bobo4bo2$2b3o3b3o!"),
+      ("gosper-gun",
+       "x = 36, y = 9, rule = B3/S23\n24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$\
+        2o8bo5bo3b2o$2o8bo3bob2o4bobo$10bo5bo7bo$11bo3bo$12b2o!")];
+
+// One of the built in patterns by name
+pub fn built_in(name: &str) -> Option<RlePattern> {
+    BUILT_INS.iter()
+        .find(|&&(built_in, _)| built_in == name)
+        .map(|&(_, text)| rle::read(text).expect("Built in patterns should be valid RLE"))
+}
+
+// Named patterns, the built ins and any read from RLE files
+#[derive(Clone, Debug, Default)]
+pub struct PatternLibrary {
+    patterns: BTreeMap<String, RlePattern>,
+}
+
+impl PatternLibrary {
+    pub fn new() -> PatternLibrary {
+        PatternLibrary::default()
+    }
+
+    pub fn with_built_ins() -> PatternLibrary {
+        let mut library = PatternLibrary::new();
+        for &(name, _) in BUILT_INS {
+            library.add(name, built_in(name).unwrap());
+        }
+
+        library
+    }
+
+    // Replaces any pattern already with the name
+    pub fn add(&mut self, name: &str, pattern: RlePattern) {
+        self.patterns.insert(name.to_lowercase(), pattern);
+    }
+
+    // Adds every .rle file in the directory named after the file without its extension, and
+    // returns how many there were. Stops at the first one that can't be read.
+    pub fn load_directory(&mut self, path: &Path) -> Result<usize, String> {
+        let entries = try!(fs::read_dir(path)
+            .map_err(|e| format!("Failed to read pattern directory [{}]: {}", path.display(), e)));
+        let mut loaded = 0;
+
+        for entry in entries {
+            let file = try!(entry.map_err(|e| e.to_string())).path();
+            let name = match (file.extension(), file.file_stem()) {
+                (Some(extension), Some(stem)) if extension == "rle" => {
+                    stem.to_string_lossy().into_owned()
+                }
+                _ => continue,
+            };
+
+            let mut text = String::new();
+            try!(fs::File::open(&file)
+                .and_then(|mut f| f.read_to_string(&mut text))
+                .map_err(|e| format!("Failed to read pattern [{}]: {}", file.display(), e)));
+            let pattern = try!(rle::read(&text)
+                .map_err(|e| format!("Failed to read pattern [{}]: {}", file.display(), e)));
+
+            self.add(&name, pattern);
+            loaded += 1;
+        }
+
+        Ok(loaded)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RlePattern> {
+        self.patterns.get(&name.to_lowercase())
+    }
+
+    pub fn get_names(&self) -> Vec<&str> {
+        self.patterns.keys().map(|name| name.as_ref()).collect()
+    }
+}
+
+// Where and which way round a pattern goes on the whole board. The pattern is reflected left to
+// right first, then turned clockwise, and the top left of what that gives goes at the position.
+// Advancing the phase runs it on from there, so a spaceship will have moved on from the position.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Placement {
+    position: (u32, u32),
+    quarter_turns: u8,
+    reflected: bool,
+    phase: usize,
+}
+
+impl Placement {
+    pub fn at(x: u32, y: u32) -> Placement {
+        Placement {
+            position: (x, y),
+            quarter_turns: 0,
+            reflected: false,
+            phase: 0,
+        }
+    }
+
+    pub fn with_rotation(mut self, quarter_turns: u8) -> Placement {
+        self.quarter_turns = quarter_turns % 4;
+        self
+    }
+
+    pub fn with_reflection(mut self) -> Placement {
+        self.reflected = !self.reflected;
+        self
+    }
+
+    pub fn with_phase(mut self, generations: usize) -> Placement {
+        self.phase = generations;
+        self
+    }
+
+    pub fn get_position(&self) -> (u32, u32) {
+        self.position
+    }
+
+    pub fn get_quarter_turns(&self) -> u8 {
+        self.quarter_turns
+    }
+
+    pub fn is_reflected(&self) -> bool {
+        self.reflected
+    }
+
+    pub fn get_phase(&self) -> usize {
+        self.phase
+    }
+}
+
+// The pattern's cells where the placement puts them on the whole board, ready for
+// Whole::create_sections_with which shares them out between the sections. Err if the pattern has
+// states the rule doesn't, cells of the rule can't be run on their own to get to the phase (see
+// evolve::check) or the phase has taken any of them off the top or left. The phase is run where the
+// pattern is placed, so rules that go by odd and even rows and columns do what they would there.
+pub fn place(rule: &Rule,
+             pattern: &RlePattern,
+             placement: &Placement)
+             -> Result<HashMap<(u32, u32), u8>, String> {
+    try!(pattern.check_states(rule));
+    let mut cells: HashMap<(i64, i64), u8> = pattern.get_cells()
+        .iter()
+        .map(|(&(x, y), &state)| {
+            let x = if placement.reflected { -(x as i64) } else { x as i64 };
+            ((x, y as i64), state)
+        })
+        .collect();
+    for _ in 0..placement.quarter_turns {
+        cells = cells.into_iter().map(|((x, y), state)| ((-y, x), state)).collect();
+    }
+
+    let (turned, _) = evolve::normalise(&cells);
+    let (x, y) = (placement.position.0 as i64, placement.position.1 as i64);
+    let mut advanced: HashMap<(i64, i64), u8> = turned.into_iter()
+        .map(|((cx, cy), state)| ((cx + x, cy + y), state))
+        .collect();
+    for generation in 0..placement.phase {
+        advanced = try!(evolve::step(rule, &advanced, generation));
+    }
+
+    let mut placed = HashMap::new();
+    for ((cx, cy), state) in advanced {
+        if cx < 0 || cy < 0 {
+            return Err(format!("Placing the pattern at [{}, {}] takes it off the board", x, y));
+        }
+
+        placed.insert((cx as u32, cy as u32), state);
+    }
+
+    Ok(placed)
+}
+
+// Every pattern placed on the same board, later ones over earlier ones where they overlap
+pub fn place_all(rule: &Rule,
+                 patterns: &[(&RlePattern, Placement)])
+                 -> Result<HashMap<(u32, u32), u8>, String> {
+    let mut states = HashMap::new();
+    for &(pattern, ref placement) in patterns {
+        states.extend(try!(place(rule, pattern, placement)));
+    }
+
+    Ok(states)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use std::process;
+    use census;
+    use rule::Rule;
+    use test_support::cells;
+    use super::*;
+
+    fn apgcode(cells: &HashMap<(u32, u32), u8>) -> String {
+        let moved = cells.iter().map(|(&(x, y), &state)| ((x as i64, y as i64), state)).collect();
+
+        census::apgcode(&Rule::conway(), &moved, 0).unwrap()
+    }
+
+    #[test]
+    fn library_built_ins_are_what_they_say() {
+        let library = PatternLibrary::with_built_ins();
+        let rule = Rule::conway();
+        let place = |name| place(&rule, library.get(name).unwrap(), &Placement::at(0, 0)).unwrap();
+
+        assert_eq!(apgcode(&place("glider")), "xq4_153");
+        assert_eq!(apgcode(&place("lwss")), "xq4_6frc");
+        assert_eq!(apgcode(&place("pulsar")), "xp3_co9nas0san9oczgoldlo0oldlogz1047210127401");
+        assert_eq!(place("gosper-gun").len(), 36);
+        assert_eq!(library.get_names(),
+                   vec!["acorn", "glider", "gosper-gun", "lwss", "pulsar", "r-pentomino"]);
+    }
+
+    #[test]
+    fn library_placement_turns_and_advances() {
+        let rule = Rule::conway();
+        let glider = built_in("glider").unwrap();
+
+        assert_eq!(place(&rule, &glider, &Placement::at(3, 3)).unwrap(),
+                   cells(&[(4, 3), (5, 4), (3, 5), (4, 5), (5, 5)]));
+        // Reflected it heads down and left, turned a quarter it heads down and left too
+        let reflected = place(&rule, &glider, &Placement::at(0, 0).with_reflection()).unwrap();
+        let turned = place(&rule, &glider, &Placement::at(0, 0).with_rotation(1)).unwrap();
+        assert_eq!(reflected, cells(&[(1, 0), (0, 1), (0, 2), (1, 2), (2, 2)]));
+        assert_eq!(turned, cells(&[(0, 0), (0, 1), (2, 1), (0, 2), (1, 2)]));
+        // Four generations on it has moved one down and one right
+        let advanced = place(&rule, &glider, &Placement::at(3, 3).with_phase(4)).unwrap();
+        assert_eq!(advanced, cells(&[(5, 4), (6, 5), (4, 6), (5, 6), (6, 6)]));
+        assert!(place(&rule, &glider, &Placement::at(0, 0).with_rotation(2).with_phase(4))
+            .is_err());
+    }
+
+    #[test]
+    fn library_loads_directory() {
+        let directory = env::temp_dir()
+            .join(format!("rust_game_of_life_library_test_{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::File::create(directory.join("Blinker.rle"))
+            .and_then(|mut file| file.write_all(b"x = 3, y = 1\n3o!\n"))
+            .unwrap();
+        fs::File::create(directory.join("notes.txt")).unwrap();
+
+        let mut library = PatternLibrary::with_built_ins();
+        let loaded = library.load_directory(&directory);
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(loaded, Ok(1));
+        assert_eq!(library.get("blinker").unwrap().get_cells().len(), 3);
+    }
+}
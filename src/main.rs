@@ -13,9 +13,10 @@ use graphics::Graphics;
 use graphics::grid::Grid;
 use graphics::line::Line;
 use graphics::math::Matrix2d;
-use graphics::rectangle;
+use graphics::polygon;
 use std::collections::HashMap;
 use piston::window::WindowSettings;
+use piston::window::AdvancedWindow;
 use sdl2_window::Sdl2Window as Window;
 use piston::input::*;
 use piston::event_loop::*;
@@ -23,24 +24,61 @@ use graphics::clear;
 use rust_game_of_life::section::*;
 use rust_game_of_life::board::*;
 use rust_game_of_life::whole::*;
+use rust_game_of_life::headless::HeadlessRun;
+use rust_game_of_life::periodicity::PeriodDetector;
+use rust_game_of_life::periodicity::DEFAULT_MAX_PERIOD;
+use rust_game_of_life::periodicity::Periodicity;
+use rust_game_of_life::canonical;
+use rust_game_of_life::canonical::Invariance;
+use rust_game_of_life::tracking::Tracker;
+use rust_game_of_life::census::Census;
+use rust_game_of_life::soup::SoupSearch;
+use rust_game_of_life::collision::CollisionSearch;
+use rust_game_of_life::library;
+use rust_game_of_life::library::PatternLibrary;
+use rust_game_of_life::library::Placement;
+use rust_game_of_life::predecessor;
+use rust_game_of_life::predecessor::Predecessor;
+use rust_game_of_life::search;
+use rust_game_of_life::search::ObjectSearch;
+use rust_game_of_life::search::Symmetry;
 use rust_game_of_life::view::Rectangle;
 use rust_game_of_life::view::BoardView;
+use rust_game_of_life::view::LinkStatus;
+use rust_game_of_life::history::History;
+use rust_game_of_life::checkpoint::Checkpoint;
+use rust_game_of_life::rule::Rule;
+use rust_game_of_life::rule::Tiling;
+use rust_game_of_life::rule::space_column;
+use rust_game_of_life::rule::space_position;
+use rust_game_of_life::rle;
+use rust_game_of_life::rle::RlePattern;
 use std::cmp;
 use std::sync::mpsc::SyncSender;
 use std::sync::mpsc::TrySendError;
 use std::sync::Arc;
+use std::env;
+use std::fs::File;
+use std::path::Path;
+use std::str::FromStr;
+use std::fmt;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
 
-fn do_life(senders: &HashMap<BoardSectionSide, Vec<SyncSender<Arc<Vec<Cell>>>>>,
+fn do_life(senders: &HashMap<BoardSectionSide, Vec<SyncSender<HaloMessage>>>,
            width: u32,
            height: u32,
+           halo: u32,
+           halo_rows: u32,
            whole: &mut Whole,
            iteration: usize) {
     let cell = Cell::new(false, iteration, false);
 
-    let cells = Arc::new(vec![cell; width as usize]);
+    let cells = Arc::new(vec![cell; (width * halo_rows) as usize]);
     for top_senders in senders.get(&BoardSectionSide::Top) {
         for sender in top_senders {
-            match sender.try_send(cells.clone()) {
+            match sender.try_send(HaloMessage::Cells(cells.clone())) {
                 Ok(_) => {}
                 Err(TrySendError::Full(_)) => {}
                 Err(TrySendError::Disconnected(_)) => {
@@ -51,7 +89,7 @@ fn do_life(senders: &HashMap<BoardSectionSide, Vec<SyncSender<Arc<Vec<Cell>>>>>,
     }
     for bottom_senders in senders.get(&BoardSectionSide::Bottom) {
         for sender in bottom_senders {
-            match sender.try_send(cells.clone()) {
+            match sender.try_send(HaloMessage::Cells(cells.clone())) {
                 Ok(_) => {}
                 Err(TrySendError::Full(_)) => {}
                 Err(TrySendError::Disconnected(_)) => {
@@ -62,10 +100,10 @@ fn do_life(senders: &HashMap<BoardSectionSide, Vec<SyncSender<Arc<Vec<Cell>>>>>,
     }
 
 
-    let cells = Arc::new(vec![cell; height as usize]);
+    let cells = Arc::new(vec![cell; (height * halo) as usize]);
     for left_senders in senders.get(&BoardSectionSide::Left) {
         for sender in left_senders {
-            match sender.try_send(cells.clone()) {
+            match sender.try_send(HaloMessage::Cells(cells.clone())) {
                 Ok(_) => {}
                 Err(TrySendError::Full(_)) => {}
                 Err(TrySendError::Disconnected(_)) => {
@@ -76,7 +114,7 @@ fn do_life(senders: &HashMap<BoardSectionSide, Vec<SyncSender<Arc<Vec<Cell>>>>>,
     }
     for right_senders in senders.get(&BoardSectionSide::Right) {
         for sender in right_senders {
-            match sender.try_send(cells.clone()) {
+            match sender.try_send(HaloMessage::Cells(cells.clone())) {
                 Ok(_) => {}
                 Err(TrySendError::Full(_)) => {}
                 Err(TrySendError::Disconnected(_)) => {
@@ -96,26 +134,119 @@ fn do_life(senders: &HashMap<BoardSectionSide, Vec<SyncSender<Arc<Vec<Cell>>>>>,
     }
 }
 
+// Colour for how many generations behind something is, going from yellow through red to purple
+// the further behind it is. Nothing is drawn for something that isn't behind.
+fn lag_colour(lag: usize) -> [f32; 4] {
+    const LAG_SCALE_MAX: f32 = 20.0;
+
+    if lag == 0 {
+        return [0.0, 0.0, 0.0, 0.0];
+    }
+
+    let scale = f32::min(lag as f32 / LAG_SCALE_MAX, 1.0);
+    if scale < 0.5 {
+        [1.0, 1.0 - scale * 2.0, 0.0, 0.3 + 0.5 * scale]
+    } else {
+        [1.5 - scale, 0.0, (scale - 0.5) * 2.0, 0.3 + 0.5 * scale]
+    }
+}
+
+// Colour of each state, alive is green and dying states fade out from it. Colour rules and rule
+// tables have a colour per state instead.
+fn state_colours(rule: &Rule) -> Vec<[f32; 4]> {
+    let states = rule.get_states() as usize;
+    let mut colours = vec![[1.0, 1.0, 1.0, 0.0], [0.0, 1.0, 0.0, 1.0]];
+
+    // Each species of the colour rules, and each state of a rule table, gets its own colour
+    if rule.get_species() > 1 || rule.is_table() {
+        let species = [[1.0, 0.0, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0], [1.0, 0.8, 0.0, 1.0]];
+        colours.extend(species.iter().cycle().take(states - 2));
+
+        return colours;
+    }
+
+    for state in 2..states {
+        let fade = (state - 1) as f32 / (states - 1) as f32;
+        colours.push([0.0, 1.0, 0.0, 1.0 - 0.8 * fade]);
+    }
+
+    colours
+}
+
+// How far along a row each cell is from the last, triangles overlap by half
+fn column_width(tiling: Tiling, cell_size: f64) -> f64 {
+    match tiling {
+        Tiling::Triangular => cell_size / 2.0,
+        _ => cell_size,
+    }
+}
+
+// Corners of the cell on screen. Hexagons are stretched to fit rows a cell apart.
+fn cell_shape(tiling: Tiling, x: u32, y: u32, cell_size: f64) -> Vec<[f64; 2]> {
+    let left = x as f64 * column_width(tiling, cell_size);
+    let top = y as f64 * cell_size;
+    let right = left + cell_size;
+    let bottom = top + cell_size;
+
+    match tiling {
+        Tiling::Square => vec![[left, top], [right, top], [right, bottom], [left, bottom]],
+        Tiling::Hexagonal => {
+            let shift = (y % 2) as f64 * cell_size / 2.0;
+            let (left, right) = (left + shift, right + shift);
+            let middle = left + cell_size / 2.0;
+            let third = cell_size / 3.0;
+
+            vec![[middle, top - third / 2.0],
+                 [right, top + third / 2.0],
+                 [right, bottom - third / 2.0],
+                 [middle, bottom + third / 2.0],
+                 [left, bottom - third / 2.0],
+                 [left, top + third / 2.0]]
+        }
+        Tiling::Triangular => {
+            let middle = left + cell_size / 2.0;
+
+            if (x + y) % 2 == 0 {
+                vec![[middle, top], [right, bottom], [left, bottom]]
+            } else {
+                vec![[left, top], [right, top], [middle, bottom]]
+            }
+        }
+    }
+}
+
+// Which cell the position on screen is in, near enough for the slanted sides
+fn cell_at_position(tiling: Tiling, position: [f64; 2], cell_size: f64) -> (u32, u32) {
+    let y = (position[1] / cell_size) as u32;
+    let x = match tiling {
+        Tiling::Square => position[0] / cell_size,
+        Tiling::Hexagonal => position[0] / cell_size - (y % 2) as f64 / 2.0,
+        Tiling::Triangular => position[0] / column_width(tiling, cell_size) - 0.5,
+    };
+
+    (f64::max(x, 0.0) as u32, y)
+}
+
 fn draw_cell<G>(cell_op: Option<Cell>,
                 x: u32,
                 y: u32,
                 iteration: usize,
-                grid: &Grid,
+                colours: &[[f32; 4]],
+                lag_overlay: bool,
+                tiling: Tiling,
                 cell_size: f64,
                 transform: Matrix2d,
                 g: &mut G)
     where G: Graphics
 {
-    let cell_rectangle = [grid.x_pos((x, y)), grid.y_pos((x, y)), cell_size, cell_size];
+    let shape = cell_shape(tiling, x, y, cell_size);
 
     match cell_op {
         Some(cell) => {
-        	let colour = [0.0, 1.0, 0.0, 1.0]; // green
-        	
-            let alive = if iteration >= cell.get_iteration() {
-                cell.alive
+            let state = if iteration >= cell.get_iteration() {
+                cell.get_state()
             } else if iteration + 1 == cell.get_iteration() {
-                cell.get_previous_alive()
+                cell.get_previous_state()
             } else {
                 panic!("Asked to draw iteration [{}] but cell's iteration is [{}], cell too far \
                         ahead to draw. Cell's co-ords are [{}, {}]",
@@ -125,11 +256,15 @@ fn draw_cell<G>(cell_op: Option<Cell>,
                        y);
             };
 
-            if alive {
-                rectangle(colour, cell_rectangle, transform, g);
+            if state != 0 {
+                polygon(colours[state as usize], &shape, transform, g);
             }
 
-            {
+            if lag_overlay {
+                let cell_age = iteration.checked_sub(cell.get_iteration()).unwrap_or(0);
+
+                polygon(lag_colour(cell_age), &shape, transform, g);
+            } else {
                 trace!("On iteration [{}], cell's iteration is [{}] ({}, {})",
                        iteration,
                        cell.get_iteration(),
@@ -150,41 +285,682 @@ fn draw_cell<G>(cell_op: Option<Cell>,
                        AGE_DARK_INCREMENT);
                 let age_colour = [0.0, 0.0, 0.0, age_dark];
 
-                rectangle(age_colour, cell_rectangle, transform, g);
+                polygon(age_colour, &shape, transform, g);
             }
         }
         None => {
         	let colour = [1.0, 1.0, 1.0, 1.0]; // black
-        	rectangle(colour, cell_rectangle, transform, g);
+        	polygon(colour, &shape, transform, g);
+        }
+    }
+
+
+}
+
+// One dimensional rules are drawn with each generation of the line as a row, oldest at the top,
+// scrolling up once there are more generations than rows
+fn draw_space_time<G>(history: &History,
+                      newest: usize,
+                      rows: usize,
+                      line: u32,
+                      colours: &[[f32; 4]],
+                      cell_size: f64,
+                      transform: Matrix2d,
+                      g: &mut G)
+    where G: Graphics
+{
+    let oldest = history.get_oldest_generation().unwrap_or(newest);
+    let first = cmp::max(oldest, (newest + 1).checked_sub(rows).unwrap_or(0));
+
+    for generation in first..newest + 1 {
+        let row = (generation - first) as u32;
+
+        history.foreach_cell_at(generation, &mut |state, x, y| {
+            if y == line {
+                draw_cell(Some(Cell::with_state(state, generation, 0)),
+                          x,
+                          row,
+                          generation,
+                          colours,
+                          false,
+                          Tiling::Square,
+                          cell_size,
+                          transform,
+                          g);
+            }
+        });
+    }
+}
+
+// Which column across the screen a column of the board is drawn in. For 3D rules the slice is the
+// z shown and the depth, and only that z's columns are drawn.
+fn screen_column(column: u32, slice: Option<(u32, u32)>) -> Option<u32> {
+    match slice {
+        Some((z, depth)) => {
+            space_position(column, depth).and_then(|(x, at)| if at == z { Some(x) } else { None })
+        }
+        None => Some(column),
+    }
+}
+
+// Column of the board drawn at the column across the screen, see screen_column
+fn board_column(x: u32, slice: Option<(u32, u32)>) -> u32 {
+    match slice {
+        Some((z, depth)) => space_column(x, z, depth),
+        None => x,
+    }
+}
+
+// How many columns across the screen the left edge of a column of the board is
+fn screen_left(column: u32, slice: Option<(u32, u32)>) -> f64 {
+    match slice {
+        Some((_, depth)) => column.checked_sub(1).unwrap_or(0) as f64 / depth as f64,
+        None => column as f64,
+    }
+}
+
+// Outlines each section's own cells, coloured by how far behind the section's oldest cell is
+fn draw_sections<G>(view: &BoardView,
+                    iteration: usize,
+                    slice: Option<(u32, u32)>,
+                    tiling: Tiling,
+                    cell_size: f64,
+                    draw_state: &graphics::DrawState,
+                    transform: Matrix2d,
+                    g: &mut G)
+    where G: Graphics
+{
+    for (index, area) in view.get_section_areas().iter().enumerate() {
+        let colour = match view.get_section_generation(index) {
+            Some(generation) => {
+                let lag = iteration.checked_sub(generation).unwrap_or(0);
+                if lag > 0 {
+                    let mut colour = lag_colour(lag);
+                    colour[3] = 1.0;
+                    colour
+                } else {
+                    [0.0, 0.0, 1.0, 1.0] // blue
+                }
+            }
+            None => [0.0, 0.0, 0.0, 1.0],
+        };
+        let border = Line::new(colour, 1.5);
+
+        let halo = view.get_halo();
+        let column_width = column_width(tiling, cell_size);
+        let left = screen_left(area.get_start_x() + halo, slice) * column_width;
+        let right = screen_left(area.get_end_x() - halo, slice) * column_width;
+        let top = (area.get_start_y() + halo) as f64 * cell_size;
+        let bottom = (area.get_end_y() - halo) as f64 * cell_size;
+
+        border.draw([left, top, right, top], draw_state, transform, g);
+        border.draw([left, bottom, right, bottom], draw_state, transform, g);
+        border.draw([left, top, left, bottom], draw_state, transform, g);
+        border.draw([right, top, right, bottom], draw_state, transform, g);
+
+        // Mark links that are falling behind with a thicker line on that side
+        let sides = [(BoardSectionSide::Top, [left, top, right, top]),
+                     (BoardSectionSide::Bottom, [left, bottom, right, bottom]),
+                     (BoardSectionSide::Left, [left, top, left, bottom]),
+                     (BoardSectionSide::Right, [right, top, right, bottom])];
+        for &(side, coords) in sides.iter() {
+            if let LinkStatus::Behind(behind) = view.get_link_status(index, side) {
+                if behind > 1 {
+                    let mut colour = lag_colour(behind);
+                    colour[3] = 1.0;
+                    Line::new(colour, 3.0).draw(coords, draw_state, transform, g);
+                }
+            }
+        }
+    }
+}
+
+
+// Size of each section and how many sections across and down the board is
+const SECTION_SIZE: u32 = 10;
+const WHOLE_SIZE: usize = 6;
+// One dimensional rules have a single row of sections
+const LINE_SECTIONS: usize = 12;
+// Three dimensional rules have sections with this many x and y of the space of their own
+const SPACE_SECTION_SIZE: u32 = 8;
+// Soups run in sections this wide, this many across, for at most this many generations
+const SOUP_SECTION_SIZE: u32 = 32;
+const SOUP_SECTIONS: usize = 4;
+const SOUP_GENERATIONS: usize = 10000;
+const DEFAULT_SOUP_THREADS: usize = 4;
+// Collisions are between gliders unless told otherwise, this far apart at most each way
+const DEFAULT_COLLIDE: &'static str = "glider";
+const DEFAULT_COLLISION_OFFSET: i64 = 6;
+// How many generations back the viewer can be rewound
+const HISTORY_RETENTION: usize = 500;
+const CHECKPOINT_FILE: &'static str = "checkpoint.txt";
+const USAGE: &'static str = "Usage: rust_game_of_life [--rule RULE] \
+                             [--pattern NAME|FILE.rle [--rotate TURNS] [--reflect] \
+                             [--phase GENERATIONS]] [--patterns DIRECTORY] \
+                             [--headless GENERATIONS [--until-stable] [--track]] \
+                             [--soups SOUPS [--threads THREADS]] [--predecessor] \
+                             [--search WIDTH,HEIGHT,PERIOD [--displacement DX,DY] \
+                             [--symmetry SYMMETRY]] [--collisions OBJECTS [--collide NAME,NAME] \
+                             [--offset OFFSET] [--threads THREADS]] [CHECKPOINT]";
+const DEFAULT_PATTERN: &'static str = "glider";
+
+struct Options {
+    rule: Option<Rule>,
+    pattern: Option<RlePattern>,
+    // Where the pattern goes on a two dimensional board
+    placement: Placement,
+    checkpoint: Option<Checkpoint>,
+    // Run this many generations without a window
+    headless: Option<usize>,
+    until_stable: bool,
+    track: bool,
+    // Run this many random soups and write out what they settle into
+    soups: Option<u64>,
+    threads: usize,
+    // Look for what the pattern could have come from instead of running it
+    predecessor: bool,
+    // Search for objects of the period in a box of the size instead of running anything
+    search: Option<(u32, u32, usize)>,
+    displacement: (i64, i64),
+    symmetry: Symmetry,
+    // Collide this many of the objects in every way
+    collisions: Option<usize>,
+    collide: Vec<(String, RlePattern)>,
+    offset: i64,
+}
+
+// Numbers split by commas, as many as the names say
+fn parse_numbers<T>(text: &str, names: &str, count: usize) -> Vec<T>
+    where T: FromStr,
+          T::Err: fmt::Display
+{
+    let numbers: Vec<T> = text.split(',')
+        .map(|n| n.trim().parse().unwrap_or_else(|e| panic!("Bad [{}]: {}", text, e)))
+        .collect();
+    if numbers.len() != count {
+        panic!("Bad [{}], expected {}", text, names);
+    }
+
+    numbers
+}
+
+fn parse_args() -> Options {
+    let mut options = Options {
+        rule: None,
+        pattern: None,
+        placement: Placement::at(3, 3),
+        checkpoint: None,
+        headless: None,
+        until_stable: false,
+        track: false,
+        soups: None,
+        threads: DEFAULT_SOUP_THREADS,
+        predecessor: false,
+        search: None,
+        displacement: (0, 0),
+        symmetry: Symmetry::None,
+        collisions: None,
+        collide: Vec::new(),
+        offset: DEFAULT_COLLISION_OFFSET,
+    };
+
+    let mut pattern = None;
+    let mut collide = DEFAULT_COLLIDE.to_string();
+    let mut library = PatternLibrary::with_built_ins();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--rule" => {
+                let text = args.next().unwrap_or_else(|| panic!("{}", USAGE));
+                let rule = Rule::parse(&text)
+                    .unwrap_or_else(|e| panic!("Bad rule [{}]: {}", text, e));
+                if rule.is_reversible() == Some(false) {
+                    warn!("Block rule [{}] isn't reversible, some blocks become the same block",
+                          rule);
+                }
+
+                options.rule = Some(rule);
+            }
+            "--pattern" => pattern = Some(args.next().unwrap_or_else(|| panic!("{}", USAGE))),
+            "--patterns" => {
+                let path = args.next().unwrap_or_else(|| panic!("{}", USAGE));
+                let loaded = library.load_directory(Path::new(&path))
+                    .unwrap_or_else(|e| panic!("{}", e));
+
+                info!("Loaded [{}] patterns from [{}]", loaded, path);
+            }
+            "--rotate" => {
+                let text = args.next().unwrap_or_else(|| panic!("{}", USAGE));
+                let turns = text.parse()
+                    .unwrap_or_else(|e| panic!("Bad quarter turns [{}]: {}", text, e));
+
+                options.placement = options.placement.with_rotation(turns);
+            }
+            "--reflect" => options.placement = options.placement.with_reflection(),
+            "--phase" => {
+                let text = args.next().unwrap_or_else(|| panic!("{}", USAGE));
+                let phase = text.parse()
+                    .unwrap_or_else(|e| panic!("Bad phase [{}]: {}", text, e));
+
+                options.placement = options.placement.with_phase(phase);
+            }
+            "--headless" => {
+                let text = args.next().unwrap_or_else(|| panic!("{}", USAGE));
+
+                options.headless = Some(text.parse()
+                    .unwrap_or_else(|e| panic!("Bad generations [{}]: {}", text, e)));
+            }
+            "--until-stable" => options.until_stable = true,
+            "--predecessor" => options.predecessor = true,
+            "--search" => {
+                let text = args.next().unwrap_or_else(|| panic!("{}", USAGE));
+                let numbers: Vec<usize> = parse_numbers(&text, "WIDTH,HEIGHT,PERIOD", 3);
+
+                options.search = Some((numbers[0] as u32, numbers[1] as u32, numbers[2]));
+            }
+            "--displacement" => {
+                let text = args.next().unwrap_or_else(|| panic!("{}", USAGE));
+                let numbers: Vec<i64> = parse_numbers(&text, "DX,DY", 2);
+
+                options.displacement = (numbers[0], numbers[1]);
+            }
+            "--collisions" => {
+                let text = args.next().unwrap_or_else(|| panic!("{}", USAGE));
+
+                options.collisions = Some(text.parse()
+                    .unwrap_or_else(|e| panic!("Bad collisions [{}]: {}", text, e)));
+            }
+            "--collide" => collide = args.next().unwrap_or_else(|| panic!("{}", USAGE)),
+            "--offset" => {
+                let text = args.next().unwrap_or_else(|| panic!("{}", USAGE));
+
+                options.offset = text.parse()
+                    .unwrap_or_else(|e| panic!("Bad offset [{}]: {}", text, e));
+            }
+            "--symmetry" => {
+                let text = args.next().unwrap_or_else(|| panic!("{}", USAGE));
+
+                options.symmetry = Symmetry::parse(&text).unwrap_or_else(|e| panic!("{}", e));
+            }
+            "--track" => options.track = true,
+            "--soups" => {
+                let text = args.next().unwrap_or_else(|| panic!("{}", USAGE));
+
+                options.soups = Some(text.parse()
+                    .unwrap_or_else(|e| panic!("Bad soups [{}]: {}", text, e)));
+            }
+            "--threads" => {
+                let text = args.next().unwrap_or_else(|| panic!("{}", USAGE));
+
+                options.threads = text.parse()
+                    .unwrap_or_else(|e| panic!("Bad threads [{}]: {}", text, e));
+            }
+            path => {
+                // Carry on from a checkpoint
+                let file = File::open(path)
+                    .unwrap_or_else(|e| panic!("Failed to open checkpoint [{}]: {}", path, e));
+
+                options.checkpoint = Some(Checkpoint::read(&mut BufReader::new(file))
+                    .unwrap_or_else(|e| panic!("Failed to read checkpoint [{}]: {}", path, e)));
+            }
+        }
+    }
+
+    options.collide = collide.split(',')
+        .map(|name| {
+            let pattern = library.get(name)
+                .unwrap_or_else(|| panic!("No pattern [{}] in the library", name));
+
+            (name.to_string(), pattern.clone())
+        })
+        .collect();
+
+    // A name from the library, or failing that an RLE file
+    options.pattern = pattern.map(|name| match library.get(&name) {
+        Some(pattern) => pattern.clone(),
+        None => {
+            let mut text = String::new();
+            File::open(&name)
+                .and_then(|mut file| file.read_to_string(&mut text))
+                .unwrap_or_else(|e| panic!("Failed to read pattern [{}]: {}", name, e));
+
+            rle::read(&text).unwrap_or_else(|e| panic!("Failed to read pattern [{}]: {}", name, e))
+        }
+    });
+
+    options
+}
+const METRICS_PROMETHEUS_FILE: &'static str = "metrics.prom";
+const METRICS_CSV_FILE: &'static str = "metrics.csv";
+const POPULATION_CSV_FILE: &'static str = "population.csv";
+const CENSUS_CSV_FILE: &'static str = "census.csv";
+const SOUP_REPORT_FILE: &'static str = "soup_report.csv";
+const PREDECESSOR_FILE: &'static str = "predecessor.rle";
+const COLLISIONS_FILE: &'static str = "collisions.csv";
+// Most objects a search writes out, to search_1.rle and on
+const SEARCH_RESULTS: usize = 10;
+
+fn write_metrics(whole: &Whole) {
+    let metrics = whole.metrics();
+    info!("Generations range from [{}] to [{}]",
+          metrics.get_min_generation(),
+          metrics.get_max_generation());
+
+    for &(path, ref text) in [(METRICS_PROMETHEUS_FILE, metrics.to_prometheus()),
+                          (METRICS_CSV_FILE, metrics.to_csv())]
+        .iter() {
+        match File::create(path).and_then(|mut file| file.write_all(text.as_bytes())) {
+            Ok(_) => info!("Wrote metrics to [{}]", path),
+            Err(e) => error!("Failed to write metrics to [{}]: {}", path, e),
+        }
+    }
+}
+
+fn write_population(whole: &Whole) {
+    let population = whole.get_population();
+    for last in population.get_last() {
+        info!("Generation [{}] has [{}] live cells", last.get_generation(), last.get_live());
+    }
+
+    let text = population.to_csv();
+    match File::create(POPULATION_CSV_FILE).and_then(|mut file| file.write_all(text.as_bytes())) {
+        Ok(_) => info!("Wrote population to [{}]", POPULATION_CSV_FILE),
+        Err(e) => error!("Failed to write population to [{}]: {}", POPULATION_CSV_FILE, e),
+    }
+}
+
+fn write_census(run: &HeadlessRun, found: &Periodicity) {
+    let census = match Census::of_board(run.get_rule(),
+                                        &run.get_cells(),
+                                        run.get_generation(),
+                                        found) {
+        Ok(census) => census,
+        Err(e) => return error!("Failed to take a census: {}", e),
+    };
+    let text = census.to_csv();
+
+    match File::create(CENSUS_CSV_FILE).and_then(|mut file| file.write_all(text.as_bytes())) {
+        Ok(_) => info!("Wrote census to [{}]", CENSUS_CSV_FILE),
+        Err(e) => error!("Failed to write census to [{}]: {}", CENSUS_CSV_FILE, e),
+    }
+}
+
+// Runs without a window up to the generation, or only until the board repeats itself. Tracking
+// prints what the spaceships do each generation.
+fn run_headless(run: &mut HeadlessRun, generations: usize, until_stable: bool, track: bool) {
+    let mut detector = if until_stable {
+        Some(PeriodDetector::new(DEFAULT_MAX_PERIOD))
+    } else {
+        None
+    };
+    let mut tracker = if track {
+        match Tracker::new(run.get_whole(), run.get_rule().clone()) {
+            Ok(tracker) => Some(tracker),
+            Err(e) => {
+                error!("Not tracking spaceships: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    loop {
+        let generation = run.get_generation();
+
+        if detector.is_some() || tracker.is_some() {
+            let cells = run.get_cells();
+
+            for tracker in tracker.iter_mut() {
+                for event in tracker.update(generation, &cells) {
+                    println!("Generation {}: {}", generation, event);
+                }
+            }
+
+            if let Some(found) = detector.as_mut().and_then(|d| d.add(generation, &cells)) {
+                println!("Stable from generation {} with period {} moving {:?}",
+                         found.get_stabilised_at(),
+                         found.get_period(),
+                         found.get_displacement());
+                write_census(run, &found);
+                break;
+            }
+        }
+
+        if generation >= generations {
+            if until_stable {
+                println!("Not stable by generation {}", generation);
+            }
+            break;
+        }
+
+        if let Err(e) = run.step() {
+            error!("Failed to run on from generation {}: {}", generation, e);
+            break;
+        }
+    }
+
+    // The same whichever way the board is split into sections, for comparing runs
+    println!("Generation {} hashes to {:016x}",
+             run.get_generation(),
+             canonical::hash(&run.get_cells(), Invariance::Translation));
+    write_population(run.get_whole());
+}
+
+// Runs the soups with seeds from 0 over the threads and writes what they settled into
+fn run_soups(rule: Rule, soups: u64, threads: usize) {
+    let section_size = cmp::max(SOUP_SECTION_SIZE, 4 * rule.get_range() + 2);
+    let search = SoupSearch::new(rule, section_size, SOUP_SECTIONS, SOUP_GENERATIONS);
+    let report = match search.run(0..soups, cmp::max(threads, 1)) {
+        Ok(report) => report,
+        Err(e) => return error!("{}", e),
+    };
+    println!("Ran {} soups", report.get_soups());
+
+    let text = report.to_csv();
+    match File::create(SOUP_REPORT_FILE).and_then(|mut file| file.write_all(text.as_bytes())) {
+        Ok(_) => info!("Wrote soup report to [{}]", SOUP_REPORT_FILE),
+        Err(e) => error!("Failed to write soup report to [{}]: {}", SOUP_REPORT_FILE, e),
+    }
+}
+
+// Writes a pattern that becomes this one a generation later, if there is one
+fn run_predecessor(rule: Rule, pattern: &RlePattern) {
+    match predecessor::find(&rule, pattern, predecessor::DEFAULT_MAX_NODES) {
+        Ok(Predecessor::Found(found)) => {
+            let text = rle::write(&found);
+
+            match File::create(PREDECESSOR_FILE)
+                .and_then(|mut file| file.write_all(text.as_bytes())) {
+                Ok(_) => println!("Wrote predecessor to [{}]", PREDECESSOR_FILE),
+                Err(e) => error!("Failed to write predecessor to [{}]: {}", PREDECESSOR_FILE, e),
+            }
         }
+        Ok(Predecessor::GardenOfEden) => println!("Pattern is a Garden of Eden"),
+        Ok(Predecessor::GaveUp) => println!("Gave up looking for a predecessor"),
+        Err(e) => error!("{}", e),
     }
+}
 
+// Collides the objects in every way within the offset and writes what came of them
+fn run_collisions(rule: Rule,
+                  objects: Vec<(String, RlePattern)>,
+                  count: usize,
+                  max_offset: i64,
+                  threads: usize) {
+    let section_size = cmp::max(SOUP_SECTION_SIZE, 4 * rule.get_range() + 2);
+    let engine = SoupSearch::new(rule, section_size, SOUP_SECTIONS, SOUP_GENERATIONS);
+    let search = CollisionSearch::new(engine, objects, max_offset);
+
+    let index = match search.enumerate(count)
+        .and_then(|collisions| search.run(collisions, cmp::max(threads, 1))) {
+        Ok(index) => index,
+        Err(e) => return error!("{}", e),
+    };
+    println!("Ran {} collisions", index.get_collisions());
 
+    let text = index.to_csv();
+    match File::create(COLLISIONS_FILE).and_then(|mut file| file.write_all(text.as_bytes())) {
+        Ok(_) => info!("Wrote collisions to [{}]", COLLISIONS_FILE),
+        Err(e) => error!("Failed to write collisions to [{}]: {}", COLLISIONS_FILE, e),
+    }
 }
 
+// Writes each object found to its own RLE file
+fn run_search(search: &ObjectSearch) {
+    let report = match search.run(SEARCH_RESULTS, search::DEFAULT_MAX_NODES) {
+        Ok(report) => report,
+        Err(e) => return error!("{}", e),
+    };
+
+    for (i, &(ref apgcode, ref pattern)) in report.get_found().iter().enumerate() {
+        let path = format!("search_{}.rle", i + 1);
+        let text = format!("#N {}\n{}", apgcode, rle::write(pattern));
+
+        match File::create(&path).and_then(|mut file| file.write_all(text.as_bytes())) {
+            Ok(_) => println!("Found {}, wrote it to [{}]", apgcode, path),
+            Err(e) => error!("Failed to write search result to [{}]: {}", path, e),
+        }
+    }
+    println!("Searched {} cells, {}",
+             report.get_nodes(),
+             if report.is_complete() { "nothing else fits" } else { "stopped early" });
+}
+
+fn write_checkpoint(checkpoint: &Checkpoint) {
+    let written = File::create(CHECKPOINT_FILE).and_then(|mut file| checkpoint.write(&mut file));
+
+    match written {
+        Ok(_) => {
+            info!("Wrote checkpoint of generation [{}] to [{}]",
+                  checkpoint.get_generation(),
+                  CHECKPOINT_FILE)
+        }
+        Err(e) => error!("Failed to write checkpoint to [{}]: {}", CHECKPOINT_FILE, e),
+    }
+}
 
 fn main() {
     env_logger::init().unwrap();
 
     info!("starting up");
-    let section_width = 10;
-    let section_height = 10;
-    let whole_size = 6;
+    let options = parse_args();
+    let restore_from = options.checkpoint;
+    if let Some(soups) = options.soups {
+        let rule = options.rule
+            .or_else(|| options.pattern.as_ref().and_then(|p| p.get_rule().cloned()))
+            .unwrap_or(Rule::conway());
+
+        return run_soups(rule, soups, options.threads);
+    }
+    if let Some(count) = options.collisions {
+        let rule = options.rule.unwrap_or(Rule::conway());
+
+        return run_collisions(rule, options.collide, count, options.offset, options.threads);
+    }
+    if let Some((width, height, period)) = options.search {
+        let rule = options.rule.unwrap_or(Rule::conway());
+        let search = ObjectSearch::new(rule, width, height, period)
+            .with_displacement(options.displacement.0, options.displacement.1)
+            .with_symmetry(options.symmetry);
+
+        return run_search(&search);
+    }
+    if options.predecessor {
+        let pattern = options.pattern.unwrap_or_else(|| panic!("{}", USAGE));
+        let rule = options.rule
+            .or_else(|| pattern.get_rule().cloned())
+            .unwrap_or(Rule::conway());
+
+        return run_predecessor(rule, &pattern);
+    }
+
+    let (sections, edge_senders, registerers) = match restore_from {
+        Some(ref checkpoint) => Whole::restore_sections(checkpoint),
+        None => {
+            let pattern = options.pattern
+                .or_else(|| library::built_in(DEFAULT_PATTERN))
+                .expect("Default pattern should be built in");
+            let rule = options.rule
+                .or_else(|| pattern.get_rule().cloned())
+                .unwrap_or(Rule::conway());
+            pattern.check_states(&rule)
+                .unwrap_or_else(|e| panic!("Pattern doesn't fit the rule: {}", e));
+            // Sections need room for their own cells between the halos
+            let section_size = cmp::max(SECTION_SIZE, 4 * rule.get_range() + 2);
+
+            if rule.is_three_dimensional() {
+                // The pattern goes in the middle slice, away from the outside edge
+                let halo = rule.get_range();
+                let z = rule.get_depth() / 2;
+                let states = pattern.get_cells()
+                    .iter()
+                    .map(|(&(x, y), &state)| ((x + 3, y + halo + 2, z), state))
+                    .collect();
+
+                Whole::create_space_sections(SPACE_SECTION_SIZE,
+                                             SPACE_SECTION_SIZE,
+                                             WHOLE_SIZE,
+                                             rule,
+                                             &states)
+            } else if rule.is_one_dimensional() {
+                // The pattern's top row is the starting line, in the middle
+                let overlap = 2 * rule.get_range();
+                let length = LINE_SECTIONS as u32 * (section_size - overlap) + overlap;
+                let start = length.checked_sub(pattern.get_width()).unwrap_or(0) / 2;
+                let line = pattern.get_cells()
+                    .iter()
+                    .filter(|&(&(_, y), _)| y == 0)
+                    .map(|(&(x, _), &state)| (start + x, state))
+                    .collect();
+
+                Whole::create_line_sections(section_size, LINE_SECTIONS, rule, &line)
+            } else {
+                // Away from the outside edge unless placed otherwise
+                let states = library::place(&rule, &pattern, &options.placement)
+                    .unwrap_or_else(|e| panic!("Failed to place pattern: {}", e));
 
-    let (sections, edge_senders, registerers) =
-        Whole::create_sections(section_width, section_height, whole_size);
+                Whole::create_sections_with(section_size, section_size, WHOLE_SIZE, rule, &states)
+            }
+        }
+    };
+    if let Some(generations) = options.headless {
+        let mut run = HeadlessRun::new((sections, edge_senders, registerers));
+
+        return run_headless(&mut run, generations, options.until_stable, options.track);
+    }
+
+    let (section_width, section_height, halo, halo_rows) = {
+        let board = sections[0][0].get_board();
+
+        (board.get_width(), board.get_height(), board.get_halo(), board.get_halo_rows())
+    };
     let view_rectangle = Rectangle::new(0,
                                         0,
-                                        section_width * (whole_size as u32),
-                                        section_height * (whole_size as u32));
-    let mut view = BoardView::new(view_rectangle, registerers);
+                                        section_width * (sections.len() as u32),
+                                        section_height * (sections[0].len() as u32));
+    let mut history = History::new(HISTORY_RETENTION, halo, &registerers);
+    let mut view = BoardView::new(view_rectangle, halo, registerers);
     let mut whole = Whole::new(sections);
+    let colours = state_colours(whole.get_section(0, 0).get_board().get_rule());
+    let tiling = whole.get_section(0, 0).get_board().get_rule().get_tiling();
+    let one_dimensional = whole.get_section(0, 0).get_board().get_rule().is_one_dimensional();
+    let three_dimensional =
+        whole.get_section(0, 0).get_board().get_rule().is_three_dimensional();
+    let depth = whole.get_section(0, 0).get_board().get_rule().get_depth();
 
     let total_rows = whole.rows_count() as u32;
     let total_columns = whole.columns_count() as u32;
     debug!("Total rows is [{}], total columns is [{}]",
            total_rows,
            total_columns);
+    // 3D rules show one z slice of the space at a time, a column for each x
+    let screen_columns = if three_dimensional {
+        space_position(total_columns - 1, depth).map(|(x, _)| x + 1).unwrap_or(0)
+    } else {
+        total_columns
+    };
 
     let window_width = 500;
     let window_height = 500;
@@ -195,55 +971,245 @@ fn main() {
         .unwrap_or_else(|e| panic!("Failed to build PistonWindow: {}", e));
     let ref mut gl = GlGraphics::new(opengl);
 
-    let max_cell_size_x = window_width as f64 / total_rows as f64;
-    let max_cell_size_y = window_height as f64 / total_columns as f64;
+    // Triangles overlap and odd rows of hexagons stick out by half a cell
+    let columns_across = match tiling {
+        Tiling::Square => screen_columns as f64,
+        Tiling::Hexagonal => screen_columns as f64 + 0.5,
+        Tiling::Triangular => (screen_columns + 1) as f64 / 2.0,
+    };
+    let max_cell_size_x = window_width as f64 / columns_across;
+    let max_cell_size_y = window_height as f64 / total_rows as f64;
 
-    let cell_size = f64::min(max_cell_size_x, max_cell_size_y);
+    // The space time diagram fills the window down with as many generations as fit
+    let cell_size = if one_dimensional {
+        max_cell_size_x
+    } else {
+        f64::min(max_cell_size_x, max_cell_size_y)
+    };
+    let space_time_rows = (window_height as f64 / cell_size) as usize;
     //    let grid_width = (window_width as f64 / cell_size).floor() as u32;
     //    let grid_height = (window_height as f64 / cell_size).floor() as u32;
     let grid = Grid {
         rows: total_rows,
-        cols: total_columns,
+        cols: screen_columns,
         units: cell_size,
     };
     let grid_line = Line::new([0.0, 0.0, 0.0, 1.0], 1.0);
 
     let mut events = window.events().max_fps(24);
-    let mut iteration = 0;
+    let mut iteration = restore_from.map(|c| c.get_generation()).unwrap_or(0);
     let mut tick = 0;
+    // Generation of the checkpoint being taken, the edges are held there until it is done
+    let mut checkpoint_generation: Option<usize> = None;
+    let mut snapshot_id: Option<usize> = None;
+    // Lag heatmap and section boundaries instead of darkening old cells
+    let mut overlay = false;
+    let mut hovered: Option<(u32, u32)> = None;
+    let mut tooltip = String::new();
+    // How many generations back from the newest complete one we are showing, 0 means live
+    let mut rewind: usize = 0;
+    // The z slice of the space shown for 3D rules, Up and Down move through them
+    let mut z = depth / 2;
 
     let whole = &mut whole;
 
     while let Some(e) = events.next(&mut window) {
+        if let Some(Button::Keyboard(key)) = e.press_args() {
+            match key {
+                Key::Left => rewind += 1,
+                Key::Right => rewind = rewind.checked_sub(1).unwrap_or(0),
+                Key::Space => rewind = 0,
+                Key::Up => z = cmp::min(z + 1, depth - 1),
+                Key::Down => z = z.checked_sub(1).unwrap_or(0),
+                Key::O => overlay = !overlay,
+                Key::M => write_metrics(whole),
+                Key::P => write_population(whole),
+                Key::S => {
+                    if snapshot_id.is_none() {
+                        snapshot_id = Some(whole.start_snapshot());
+                    }
+                }
+                Key::C => {
+                    if checkpoint_generation.is_none() {
+                        match whole.request_checkpoint() {
+                            Ok(generation) => checkpoint_generation = Some(generation),
+                            Err(e) => error!("Failed to request a checkpoint: {}", e),
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(position) = e.mouse_cursor_args() {
+            let (x, y) = cell_at_position(tiling, position, cell_size);
+
+            hovered = if x < screen_columns && y < total_rows {
+                Some((x, y))
+            } else {
+                None
+            };
+        }
+
+        let slice = if three_dimensional { Some((z, depth)) } else { None };
+
+        // No fonts to draw text with, so the tooltip goes in the window title
+        let hover_text = if overlay {
+            hovered.and_then(|(x, y)| view.describe_cell(board_column(x, slice), y))
+        } else {
+            None
+        };
+        let hover_text = hover_text.unwrap_or_else(|| match slice {
+            Some((z, depth)) => format!("z [{}] of [{}]", z, depth),
+            None => "Hello World!".to_string(),
+        });
+        if hover_text != tooltip {
+            window.set_title(hover_text.clone());
+            tooltip = hover_text;
+        }
+
         if let Some(args) = e.render_args() {
             gl.draw(args.viewport(), |c, g| {
                 debug!("Doing iteration [{}], tick is [{}]", iteration, tick);
 
+                let upto_iteration = match checkpoint_generation {
+                    Some(generation) => cmp::min(generation, iteration),
+                    None => iteration,
+                };
                 do_life(&edge_senders,
                         section_width,
                         section_height,
+                        halo,
+                        halo_rows,
                         whole,
-                        iteration);
+                        upto_iteration);
+                whole.update_population();
+
+                if let Some(checkpoint) = whole.try_take_checkpoint() {
+                    write_checkpoint(&checkpoint);
+                    checkpoint_generation = None;
+                }
+
+                if let Some(snapshot) = whole.try_take_snapshot() {
+                    for (x, col) in snapshot.get_sections().iter().enumerate() {
+                        for (y, section) in col.iter().enumerate() {
+                            debug!("Snapshot [{}] section [{}, {}] had [{}, {}, {}, {}] top, \
+                                    bottom, left, right messages in flight",
+                                   snapshot.get_id(),
+                                   x,
+                                   y,
+                                   section.get_channel(BoardSectionSide::Top).len(),
+                                   section.get_channel(BoardSectionSide::Bottom).len(),
+                                   section.get_channel(BoardSectionSide::Left).len(),
+                                   section.get_channel(BoardSectionSide::Right).len());
+                        }
+                    }
+
+                    write_checkpoint(&snapshot.to_checkpoint());
+                    snapshot_id = None;
+                }
 
                 clear([1.0, 1.0, 1.0, 1.0], g);
 
-                let iteration_to_draw = iteration.checked_sub(1).unwrap_or(0);
-                view.foreach_cell(&mut |cell, x, y| {
-                    draw_cell(cell,
-                              x,
-                              y,
-                              iteration_to_draw,
-                              &grid,
-                              cell_size,
-                              c.transform,
-                              g);
-                });
-
-                // Draw grid over the top of squares
-                grid.draw(&grid_line, &c.draw_state, c.transform, g);
+                history.update();
+                let rewound_to = if rewind > 0 {
+                    history.get_newest_generation().map(|newest| {
+                        let oldest = history.get_oldest_generation().unwrap_or(newest);
+                        let wanted = newest.checked_sub(rewind).unwrap_or(0);
+
+                        // Don't let the rewind run off the end of what we remember
+                        rewind = newest - cmp::max(wanted, oldest);
+                        newest - rewind
+                    })
+                } else {
+                    None
+                };
+
+                match rewound_to {
+                    _ if one_dimensional => {
+                        let newest = rewound_to.or(history.get_newest_generation());
+
+                        for &generation in newest.iter() {
+                            draw_space_time(&history,
+                                            generation,
+                                            space_time_rows,
+                                            halo,
+                                            &colours,
+                                            cell_size,
+                                            c.transform,
+                                            g);
+                        }
+                    }
+                    Some(generation) => {
+                        debug!("Drawing rewound generation [{}]", generation);
+
+                        history.foreach_cell_at(generation, &mut |state, x, y| {
+                            if let Some(x) = screen_column(x, slice) {
+                                draw_cell(Some(Cell::with_state(state, generation, 0)),
+                                          x,
+                                          y,
+                                          generation,
+                                          &colours,
+                                          false,
+                                          tiling,
+                                          cell_size,
+                                          c.transform,
+                                          g);
+                            }
+                        });
+                    }
+                    None => {
+                        let iteration_to_draw = iteration.checked_sub(1).unwrap_or(0);
+                        view.foreach_cell(&mut |cell, x, y| {
+                            if let Some(x) = screen_column(x, slice) {
+                                draw_cell(cell,
+                                          x,
+                                          y,
+                                          iteration_to_draw,
+                                          &colours,
+                                          overlay,
+                                          tiling,
+                                          cell_size,
+                                          c.transform,
+                                          g);
+                            }
+                        });
+                    }
+                }
+
+                // Draw grid over the top of squares, the other shapes are told apart by colour
+                if tiling == Tiling::Square && !one_dimensional {
+                    grid.draw(&grid_line, &c.draw_state, c.transform, g);
+                }
+
+                if overlay && rewound_to.is_none() && !one_dimensional {
+                    let iteration_to_draw = iteration.checked_sub(1).unwrap_or(0);
+                    draw_sections(&view,
+                                  iteration_to_draw,
+                                  slice,
+                                  tiling,
+                                  cell_size,
+                                  &c.draw_state,
+                                  c.transform,
+                                  g);
+
+                    for &(x, y) in hovered.iter() {
+                        let highlight = Line::new([0.0, 0.0, 0.0, 1.0], 2.0);
+                        let shape = cell_shape(tiling, x, y, cell_size);
+
+                        for (i, from) in shape.iter().enumerate() {
+                            let to = shape[(i + 1) % shape.len()];
+
+                            highlight.draw([from[0], from[1], to[0], to[1]],
+                                           &c.draw_state,
+                                           c.transform,
+                                           g);
+                        }
+                    }
+                }
 
                 tick += 1;
-                iteration += if tick % 3 == 0 {
+                iteration += if tick % 3 == 0 && checkpoint_generation.is_none() {
                     1
                 } else {
                     0
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use section::BoardSectionSide;
+
+#[derive(Clone, Debug, Default)]
+pub struct LinkMetrics {
+    messages_sent: u64,
+    messages_dropped: u64,
+    bytes_sent: u64,
+}
+
+impl LinkMetrics {
+    pub fn new() -> LinkMetrics {
+        LinkMetrics::default()
+    }
+
+    pub fn record_sent(&mut self, bytes: usize) {
+        self.messages_sent += 1;
+        self.bytes_sent += bytes as u64;
+    }
+
+    // The channel was full so the message was thrown away
+    pub fn record_dropped(&mut self) {
+        self.messages_dropped += 1;
+    }
+
+    pub fn get_messages_sent(&self) -> u64 {
+        self.messages_sent
+    }
+
+    pub fn get_messages_dropped(&self) -> u64 {
+        self.messages_dropped
+    }
+
+    pub fn get_bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SectionMetrics {
+    generations_computed: u64,
+    cells_updated: u64,
+    cells_blocked: u64,
+    time_spent: Duration,
+    links: HashMap<BoardSectionSide, LinkMetrics>,
+}
+
+impl SectionMetrics {
+    pub fn new() -> SectionMetrics {
+        SectionMetrics::default()
+    }
+
+    pub fn record_generations(&mut self, generations: usize) {
+        self.generations_computed += generations as u64;
+    }
+
+    pub fn record_cell_updated(&mut self) {
+        self.cells_updated += 1;
+    }
+
+    // A cell couldn't be updated as its neighbours were too old
+    pub fn record_cell_blocked(&mut self) {
+        self.cells_blocked += 1;
+    }
+
+    pub fn record_time(&mut self, time: Duration) {
+        self.time_spent += time;
+    }
+
+    pub fn link(&mut self, side: BoardSectionSide) -> &mut LinkMetrics {
+        self.links.entry(side).or_insert_with(|| LinkMetrics::new())
+    }
+
+    pub fn get_generations_computed(&self) -> u64 {
+        self.generations_computed
+    }
+
+    pub fn get_cells_updated(&self) -> u64 {
+        self.cells_updated
+    }
+
+    pub fn get_cells_blocked(&self) -> u64 {
+        self.cells_blocked
+    }
+
+    pub fn get_time_spent(&self) -> Duration {
+        self.time_spent
+    }
+
+    pub fn get_link(&self, side: BoardSectionSide) -> Option<&LinkMetrics> {
+        self.links.get(&side)
+    }
+}
+
+// Metrics of every section at one point in time
+pub struct Metrics {
+    sections: Vec<((usize, usize), SectionMetrics)>,
+    min_generation: usize,
+    max_generation: usize,
+}
+
+const SIDES: [(BoardSectionSide, &'static str); 4] = [(BoardSectionSide::Top, "top"),
+                                                      (BoardSectionSide::Bottom, "bottom"),
+                                                      (BoardSectionSide::Left, "left"),
+                                                      (BoardSectionSide::Right, "right")];
+
+// Adds the sample to the metric's samples, metrics stay in the order they are first seen in so
+// each metric's samples all go after its TYPE line
+fn add_sample(metrics: &mut Vec<(&'static str, Vec<String>)>, name: &'static str, sample: String) {
+    match metrics.iter().position(|&(metric, _)| metric == name) {
+        Some(i) => metrics[i].1.push(sample),
+        None => metrics.push((name, vec![sample])),
+    }
+}
+
+impl Metrics {
+    pub fn new(sections: Vec<((usize, usize), SectionMetrics)>,
+               min_generation: usize,
+               max_generation: usize)
+               -> Metrics {
+        Metrics {
+            sections: sections,
+            min_generation: min_generation,
+            max_generation: max_generation,
+        }
+    }
+
+    pub fn get_sections(&self) -> &Vec<((usize, usize), SectionMetrics)> {
+        &self.sections
+    }
+
+    pub fn get_section(&self, x: usize, y: usize) -> Option<&SectionMetrics> {
+        self.sections.iter().find(|&&(id, _)| id == (x, y)).map(|&(_, ref m)| m)
+    }
+
+    // Oldest generation of any cell on the board
+    pub fn get_min_generation(&self) -> usize {
+        self.min_generation
+    }
+
+    pub fn get_max_generation(&self) -> usize {
+        self.max_generation
+    }
+
+    fn section_values(metrics: &SectionMetrics) -> [(&'static str, f64); 4] {
+        let time = metrics.time_spent;
+        let seconds = time.as_secs() as f64 + time.subsec_nanos() as f64 / 1_000_000_000.0;
+
+        [("generations_computed", metrics.generations_computed as f64),
+         ("cells_updated", metrics.cells_updated as f64),
+         ("cells_blocked", metrics.cells_blocked as f64),
+         ("seconds_spent", seconds)]
+    }
+
+    fn link_values(metrics: &LinkMetrics) -> [(&'static str, u64); 3] {
+        [("messages_sent", metrics.messages_sent),
+         ("messages_dropped", metrics.messages_dropped),
+         ("bytes_sent", metrics.bytes_sent)]
+    }
+
+    // Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let mut sections = Vec::new();
+        let mut links = Vec::new();
+
+        for &((x, y), ref metrics) in &self.sections {
+            let labels = format!("section_x=\"{}\",section_y=\"{}\"", x, y);
+
+            for &(name, value) in Metrics::section_values(metrics).iter() {
+                add_sample(&mut sections, name, format!("{{{}}} {}", labels, value));
+            }
+
+            for &(side, side_name) in SIDES.iter() {
+                if let Some(link) = metrics.get_link(side) {
+                    for &(name, value) in Metrics::link_values(link).iter() {
+                        let sample = format!("{{{},side=\"{}\"}} {}", labels, side_name, value);
+
+                        add_sample(&mut links, name, sample);
+                    }
+                }
+            }
+        }
+
+        let mut out = String::new();
+        for &(prefix, ref metrics) in [("section", &sections), ("link", &links)].iter() {
+            for &(name, ref samples) in metrics.iter() {
+                out.push_str(&format!("# TYPE game_of_life_{}_{} counter\n", prefix, name));
+
+                for sample in samples {
+                    out.push_str(&format!("game_of_life_{}_{}{}\n", prefix, name, sample));
+                }
+            }
+        }
+
+        out.push_str("# TYPE game_of_life_generation_min gauge\n");
+        out.push_str(&format!("game_of_life_generation_min {}\n", self.min_generation));
+        out.push_str("# TYPE game_of_life_generation_max gauge\n");
+        out.push_str(&format!("game_of_life_generation_max {}\n", self.max_generation));
+
+        out
+    }
+
+    // One row per value, links have their side filled in
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("section_x,section_y,side,metric,value\n");
+
+        for &((x, y), ref metrics) in &self.sections {
+            for &(name, value) in Metrics::section_values(metrics).iter() {
+                out.push_str(&format!("{},{},,{},{}\n", x, y, name, value));
+            }
+
+            for &(side, side_name) in SIDES.iter() {
+                if let Some(link) = metrics.get_link(side) {
+                    for &(name, value) in Metrics::link_values(link).iter() {
+                        out.push_str(&format!("{},{},{},{},{}\n", x, y, side_name, name, value));
+                    }
+                }
+            }
+        }
+
+        out.push_str(&format!(",,,generation_min,{}\n", self.min_generation));
+        out.push_str(&format!(",,,generation_max,{}\n", self.max_generation));
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use section::BoardSectionSide;
+    use super::*;
+
+    fn get_test_metrics() -> Metrics {
+        let mut section = SectionMetrics::new();
+        section.record_generations(2);
+        section.record_cell_updated();
+        section.record_cell_blocked();
+        section.link(BoardSectionSide::Left).record_sent(10);
+        section.link(BoardSectionSide::Left).record_dropped();
+
+        Metrics::new(vec![((1, 0), section)], 3, 5)
+    }
+
+    #[test]
+    fn metrics_to_prometheus_has_section_and_link_values() {
+        let text = get_test_metrics().to_prometheus();
+
+        assert!(text.contains("# TYPE game_of_life_section_cells_updated counter\n\
+                               game_of_life_section_cells_updated{section_x=\"1\",\
+                               section_y=\"0\"} 1\n"));
+        assert!(text.contains("game_of_life_section_generations_computed{section_x=\"1\",\
+                               section_y=\"0\"} 2\n"));
+        assert!(text.contains("game_of_life_link_messages_dropped{section_x=\"1\",section_y=\"0\",\
+                               side=\"left\"} 1\n"));
+        assert!(text.contains("game_of_life_generation_min 3\n"));
+    }
+
+    #[test]
+    fn metrics_to_csv_has_row_per_value() {
+        let csv = get_test_metrics().to_csv();
+
+        assert!(csv.starts_with("section_x,section_y,side,metric,value\n"));
+        assert!(csv.contains("1,0,,cells_blocked,1\n"));
+        assert!(csv.contains("1,0,left,bytes_sent,10\n"));
+        assert!(csv.contains(",,,generation_max,5\n"));
+    }
+}
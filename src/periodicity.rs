@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use canonical;
+use view::Rectangle;
+
+// Longest period looked for unless another is given
+pub const DEFAULT_MAX_PERIOD: usize = 64;
+
+// The board (or region) has been repeating since a generation. Still lifes have a period of 1 and
+// an empty board counts as one, spaceships have moved by the displacement each period.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Periodicity {
+    period: usize,
+    stabilised_at: usize,
+    displacement: (i64, i64),
+}
+
+impl Periodicity {
+    pub fn get_period(&self) -> usize {
+        self.period
+    }
+
+    // First generation of the repeating cycle
+    pub fn get_stabilised_at(&self) -> usize {
+        self.stabilised_at
+    }
+
+    pub fn get_displacement(&self) -> (i64, i64) {
+        self.displacement
+    }
+
+    pub fn is_still(&self) -> bool {
+        self.period == 1 && self.displacement == (0, 0)
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.displacement != (0, 0)
+    }
+}
+
+// A generation's live cells moved so the top left of their bounding box is at 0, 0, see canonical
+struct Frame {
+    generation: usize,
+    hash: u64,
+    origin: (u32, u32),
+    cells: Vec<(u32, u32, u8)>,
+}
+
+impl Frame {
+    fn new(generation: usize, cells: &HashMap<(u32, u32), u8>) -> Frame {
+        let (moved, origin) = canonical::translate(cells);
+
+        Frame {
+            generation: generation,
+            hash: canonical::fnv(&moved),
+            origin: origin,
+            cells: moved,
+        }
+    }
+}
+
+// Is given the cells of each generation in turn and spots when they start repeating, moved or not.
+// Generations are compared by a hash of their cells first and then cell by cell, and only the
+// last max period generations are kept.
+pub struct PeriodDetector {
+    max_period: usize,
+    region: Option<Rectangle>,
+    frames: VecDeque<Frame>,
+    found: Option<Periodicity>,
+}
+
+impl PeriodDetector {
+    pub fn new(max_period: usize) -> PeriodDetector {
+        PeriodDetector {
+            max_period: max_period,
+            region: None,
+            frames: VecDeque::with_capacity(max_period + 1),
+            found: None,
+        }
+    }
+
+    // Only looks at the cells inside the region
+    pub fn with_region(max_period: usize, region: Rectangle) -> PeriodDetector {
+        let mut detector = PeriodDetector::new(max_period);
+        detector.region = Some(region);
+
+        detector
+    }
+
+    pub fn get_found(&self) -> Option<Periodicity> {
+        self.found
+    }
+
+    // The cells of the generation after the last one added, keyed by position and zero states
+    // can be left out. Returns what was found once the cells repeat an earlier generation.
+    pub fn add(&mut self,
+               generation: usize,
+               cells: &HashMap<(u32, u32), u8>)
+               -> Option<Periodicity> {
+        if self.found.is_some() {
+            return self.found;
+        }
+
+        let frame = match self.region {
+            Some(region) => {
+                let inside = cells.iter()
+                    .filter(|&(&(x, y), _)| region.contains(x, y))
+                    .map(|(&position, &state)| (position, state))
+                    .collect();
+
+                Frame::new(generation, &inside)
+            }
+            None => Frame::new(generation, cells),
+        };
+
+        // Newest first so the shortest period is found
+        for earlier in self.frames.iter().rev() {
+            if earlier.hash == frame.hash && earlier.cells == frame.cells {
+                self.found = Some(Periodicity {
+                    period: frame.generation - earlier.generation,
+                    stabilised_at: earlier.generation,
+                    displacement: (frame.origin.0 as i64 - earlier.origin.0 as i64,
+                                   frame.origin.1 as i64 - earlier.origin.1 as i64),
+                });
+
+                return self.found;
+            }
+        }
+
+        if self.frames.len() == self.max_period {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(frame);
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_support::cells;
+    use view::Rectangle;
+    use super::*;
+
+    #[test]
+    fn period_detector_finds_blinker_after_it_settles() {
+        let mut detector = PeriodDetector::new(DEFAULT_MAX_PERIOD);
+        let horizontal = cells(&[(1, 2), (2, 2), (3, 2)]);
+        let vertical = cells(&[(2, 1), (2, 2), (2, 3)]);
+
+        assert_eq!(detector.add(0, &cells(&[(1, 2), (2, 2), (3, 2), (3, 3)])), None);
+        assert_eq!(detector.add(1, &vertical), None);
+        assert_eq!(detector.add(2, &horizontal), None);
+        let found = detector.add(3, &vertical).unwrap();
+
+        assert_eq!(found.get_period(), 2);
+        assert_eq!(found.get_stabilised_at(), 1);
+        assert!(!found.is_moving() && !found.is_still());
+    }
+
+    #[test]
+    fn period_detector_finds_glider_moved() {
+        let mut detector = PeriodDetector::new(DEFAULT_MAX_PERIOD);
+        let phases = [[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)],
+                      [(0, 1), (2, 1), (1, 2), (2, 2), (1, 3)],
+                      [(2, 1), (0, 2), (2, 2), (1, 3), (2, 3)],
+                      [(1, 1), (2, 2), (3, 2), (1, 3), (2, 3)]];
+        for (generation, phase) in phases.iter().enumerate() {
+            assert_eq!(detector.add(generation, &cells(phase)), None);
+        }
+
+        let moved = cells(&[(2, 1), (3, 2), (1, 3), (2, 3), (3, 3)]);
+        let found = detector.add(4, &moved).unwrap();
+
+        assert_eq!((found.get_period(), found.get_displacement()), (4, (1, 1)));
+        assert!(found.is_moving());
+    }
+
+    #[test]
+    fn period_detector_region_ignores_outside() {
+        let mut detector = PeriodDetector::with_region(4, Rectangle::new(0, 0, 10, 10));
+
+        assert_eq!(detector.add(0, &cells(&[(1, 1), (20, 20)])), None);
+        let found = detector.add(1, &cells(&[(1, 1), (21, 20)])).unwrap();
+
+        assert!(found.is_still());
+    }
+
+    #[test]
+    fn period_detector_ignores_dead_cells_given() {
+        let mut detector = PeriodDetector::new(4);
+        let block = cells(&[(5, 5), (6, 5), (5, 6), (6, 6)]);
+        let mut with_dead = block.clone();
+        with_dead.insert((0, 0), 0);
+
+        assert_eq!(detector.add(0, &with_dead), None);
+        let found = detector.add(1, &block).unwrap();
+
+        assert!(found.is_still());
+
+        // Dead cells above and left of a glider don't move where it is either
+        let mut detector = PeriodDetector::new(DEFAULT_MAX_PERIOD);
+        let mut glider = cells(&[(11, 10), (12, 11), (10, 12), (11, 12), (12, 12)]);
+        glider.insert((0, 0), 0);
+        assert_eq!(detector.add(0, &glider), None);
+        for (generation, phase) in [[(10, 11), (12, 11), (11, 12), (12, 12), (11, 13)],
+                                    [(12, 11), (10, 12), (12, 12), (11, 13), (12, 13)],
+                                    [(11, 11), (12, 12), (13, 12), (11, 13), (12, 13)]]
+            .iter()
+            .enumerate() {
+            assert_eq!(detector.add(generation + 1, &cells(phase)), None);
+        }
+
+        let mut moved = cells(&[(12, 11), (13, 12), (11, 13), (12, 13), (13, 13)]);
+        moved.insert((3, 1), 0);
+        let found = detector.add(4, &moved).unwrap();
+
+        assert_eq!((found.get_period(), found.get_displacement()), (4, (1, 1)));
+    }
+}
@@ -0,0 +1,277 @@
+use std::cmp;
+use std::collections::BTreeMap;
+use board::Board;
+
+// How many finished generations a section keeps for the whole board to pick up
+const POPULATION_RETENTION: usize = 1000;
+
+// Smallest rectangle holding every live cell, the max values are included
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BoundingBox {
+    min_x: u32,
+    min_y: u32,
+    max_x: u32,
+    max_y: u32,
+}
+
+impl BoundingBox {
+    pub fn new(x: u32, y: u32) -> BoundingBox {
+        BoundingBox {
+            min_x: x,
+            min_y: y,
+            max_x: x,
+            max_y: y,
+        }
+    }
+
+    pub fn include(&mut self, x: u32, y: u32) {
+        self.min_x = cmp::min(self.min_x, x);
+        self.min_y = cmp::min(self.min_y, y);
+        self.max_x = cmp::max(self.max_x, x);
+        self.max_y = cmp::max(self.max_y, y);
+    }
+
+    pub fn merge(&mut self, other: &BoundingBox) {
+        self.include(other.min_x, other.min_y);
+        self.include(other.max_x, other.max_y);
+    }
+
+    pub fn offset(&self, x: u32, y: u32) -> BoundingBox {
+        BoundingBox {
+            min_x: self.min_x + x,
+            min_y: self.min_y + y,
+            max_x: self.max_x + x,
+            max_y: self.max_y + y,
+        }
+    }
+
+    pub fn get_min_x(&self) -> u32 {
+        self.min_x
+    }
+
+    pub fn get_min_y(&self) -> u32 {
+        self.min_y
+    }
+
+    pub fn get_max_x(&self) -> u32 {
+        self.max_x
+    }
+
+    pub fn get_max_y(&self) -> u32 {
+        self.max_y
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.max_x - self.min_x + 1
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.max_y - self.min_y + 1
+    }
+}
+
+// How many cells are alive at a generation and where they are, no bounds if none are alive
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Population {
+    generation: usize,
+    live: u64,
+    bounds: Option<BoundingBox>,
+}
+
+impl Population {
+    pub fn new(generation: usize) -> Population {
+        Population {
+            generation: generation,
+            live: 0,
+            bounds: None,
+        }
+    }
+
+    pub fn add_cell(&mut self, x: u32, y: u32) {
+        self.live += 1;
+
+        match self.bounds {
+            Some(ref mut bounds) => bounds.include(x, y),
+            None => self.bounds = Some(BoundingBox::new(x, y)),
+        }
+    }
+
+    // Adds in the live cells of another part of the board, its top left being at x and y
+    pub fn add(&mut self, other: &Population, x: u32, y: u32) {
+        self.live += other.live;
+
+        if let Some(other_bounds) = other.bounds.map(|b| b.offset(x, y)) {
+            match self.bounds {
+                Some(ref mut bounds) => bounds.merge(&other_bounds),
+                None => self.bounds = Some(other_bounds),
+            }
+        }
+    }
+
+    pub fn get_generation(&self) -> usize {
+        self.generation
+    }
+
+    pub fn get_live(&self) -> u64 {
+        self.live
+    }
+
+    pub fn get_bounds(&self) -> Option<BoundingBox> {
+        self.bounds
+    }
+}
+
+// The population of a section's own cells (not the joining ones), kept up to date as each cell
+// moves on a generation. As cells of a section can be at different generations there is a count
+// for each generation, finished once every cell has reached it.
+#[derive(Clone, Debug)]
+pub struct SectionPopulation {
+    cells: usize,
+    // How many cells have reached the generation so far, and the live ones of those
+    generations: BTreeMap<usize, (usize, Population)>,
+}
+
+impl SectionPopulation {
+    pub fn new(board: &Board) -> SectionPopulation {
+        let halo = board.get_halo();
+        let mut population = SectionPopulation {
+            cells: ((board.get_width() - 2 * halo) * (board.get_height() - 2 * halo)) as usize,
+            generations: BTreeMap::new(),
+        };
+
+        for x in halo..board.get_width() - halo {
+            for y in halo..board.get_height() - halo {
+                let cell = board.get_cell(x, y);
+
+                population.record(cell.get_iteration(), x, y, cell.alive);
+            }
+        }
+
+        population
+    }
+
+    // A cell has reached the generation
+    pub fn record(&mut self, generation: usize, x: u32, y: u32, alive: bool) {
+        {
+            let &mut (ref mut cells, ref mut population) = self.generations
+                .entry(generation)
+                .or_insert_with(|| (0, Population::new(generation)));
+
+            *cells += 1;
+            if alive {
+                population.add_cell(x, y);
+            }
+        }
+
+        if self.generations.len() > POPULATION_RETENTION {
+            let oldest = *self.generations.keys().next().unwrap();
+            self.generations.remove(&oldest);
+        }
+    }
+
+    // Only once every cell has reached the generation, the bounds are in the section's cells
+    pub fn get_finished(&self, generation: usize) -> Option<&Population> {
+        self.generations
+            .get(&generation)
+            .and_then(|&(cells, ref population)| if cells == self.cells {
+                Some(population)
+            } else {
+                None
+            })
+    }
+
+    pub fn get_oldest_generation(&self) -> Option<usize> {
+        self.generations.keys().next().cloned()
+    }
+}
+
+// Population of the whole board for each generation in turn
+#[derive(Clone, Debug, Default)]
+pub struct PopulationSeries {
+    populations: Vec<Population>,
+}
+
+impl PopulationSeries {
+    pub fn new() -> PopulationSeries {
+        PopulationSeries::default()
+    }
+
+    pub fn push(&mut self, population: Population) {
+        self.populations.push(population);
+    }
+
+    pub fn get_populations(&self) -> &Vec<Population> {
+        &self.populations
+    }
+
+    pub fn get(&self, generation: usize) -> Option<&Population> {
+        self.populations.iter().find(|p| p.generation == generation)
+    }
+
+    pub fn get_last(&self) -> Option<&Population> {
+        self.populations.last()
+    }
+
+    // One row per generation, the bounds are left empty when nothing is alive
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("generation,population,min_x,min_y,max_x,max_y\n");
+
+        for population in &self.populations {
+            let bounds = match population.bounds {
+                Some(b) => format!("{},{},{},{}", b.min_x, b.min_y, b.max_x, b.max_y),
+                None => ",,,".to_string(),
+            };
+
+            out.push_str(&format!("{},{},{}\n", population.generation, population.live, bounds));
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use board::Board;
+    use rule::Rule;
+    use super::*;
+
+    #[test]
+    fn section_population_finishes_once_every_cell_is_there() {
+        let mut states = HashMap::new();
+        states.insert((2, 1), 1);
+        states.insert((0, 0), 1);
+        let board = Board::with_states(4, 4, &states, Rule::conway());
+        let mut population = SectionPopulation::new(&board);
+
+        // The live cell in the joining cells isn't counted
+        let first = *population.get_finished(0).unwrap();
+        assert_eq!(first.get_live(), 1);
+        assert_eq!(first.get_bounds(), Some(BoundingBox::new(2, 1)));
+
+        population.record(1, 1, 1, true);
+        population.record(1, 2, 2, true);
+        population.record(1, 1, 2, false);
+        assert_eq!(population.get_finished(1), None);
+
+        population.record(1, 2, 1, false);
+        let second = population.get_finished(1).unwrap();
+        assert_eq!(second.get_live(), 2);
+        assert_eq!(second.get_bounds().map(|b| (b.get_width(), b.get_height())), Some((2, 2)));
+    }
+
+    #[test]
+    fn population_series_to_csv() {
+        let mut series = PopulationSeries::new();
+        let mut population = Population::new(0);
+        population.add_cell(3, 4);
+        let mut part = Population::new(0);
+        part.add_cell(1, 1);
+        population.add(&part, 5, 5);
+        series.push(population);
+        series.push(Population::new(1));
+
+        assert_eq!(series.to_csv(),
+                   "generation,population,min_x,min_y,max_x,max_y\n0,2,3,4,6,6\n1,0,,,,\n");
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use evolve;
+use rle::RlePattern;
+use rule::Rule;
+
+// How many cells the search tries setting before giving up, unless told otherwise
+pub const DEFAULT_MAX_NODES: u64 = 10000000;
+// Windows with more cells than this still to be set aren't checked, as almost anything could
+// still come of them
+const MAX_UNKNOWN: usize = 4;
+
+// What the search came to. A predecessor is two cells bigger each way than the target, its
+// cell (1, 1) becomes the target's cell (0, 0). Only those cells can change the target's, so when
+// none of them will do the target is a Garden of Eden.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Predecessor {
+    Found(RlePattern),
+    GardenOfEden,
+    // Tried as many cells as it was allowed to without finding either
+    GaveUp,
+}
+
+// Whether a cell is alive next for each arrangement of the 3x3 cells around it, bit dy * 3 + dx.
+// Worked out by running each one through evolve so it is whatever the sections would do.
+// Only two state rules whose neighbours are within one cell, the same wherever the cell is, can
+// be searched.
+pub fn transitions(rule: &Rule) -> Result<Vec<bool>, String> {
+    let neighbourhood = rule.get_neighbourhood(0, 0);
+    let stochastic = match *rule {
+        Rule::Stochastic { .. } => true,
+        _ => false,
+    };
+
+    if rule.get_states() != 2 || rule.get_range() != 1 || stochastic || rule.is_margolus() ||
+       rule.is_one_dimensional() || rule.is_three_dimensional() ||
+       neighbourhood != rule.get_neighbourhood(1, 1) {
+        return Err(format!("Rule [{}] can't be searched", rule));
+    }
+
+    let mut table = Vec::with_capacity(512);
+    for configuration in 0..512 {
+        let cells: HashMap<(i64, i64), u8> = (0..9)
+            .filter(|bit| configuration & (1 << bit) != 0)
+            .map(|bit| ((bit % 3, bit / 3), 1))
+            .collect();
+        // Only the middle cell, so a rule with cells born from nothing gets them too
+        table.push(rule.is_alive(evolve::next_state(rule, &cells, (1, 1), 0)));
+    }
+
+    Ok(table)
+}
+
+// Backtracking over the predecessor's cells a row at a time, checking each target cell around
+// the one just set once few enough of the cells it depends on are left
+struct Search<'a> {
+    table: &'a [bool],
+    target: Vec<bool>,
+    target_width: usize,
+    target_height: usize,
+    cells: Vec<Option<bool>>,
+    nodes: u64,
+    max_nodes: u64,
+}
+
+impl<'a> Search<'a> {
+    fn width(&self) -> usize {
+        self.target_width + 2
+    }
+
+    // Whether some way of setting the cells still unset around the target cell gives it
+    fn allows(&self, tx: usize, ty: usize) -> bool {
+        let mut known = 0;
+        let mut unknown = Vec::new();
+
+        for bit in 0..9 {
+            match self.cells[(ty + bit / 3) * self.width() + tx + bit % 3] {
+                Some(true) => known |= 1 << bit,
+                Some(false) => {}
+                None => unknown.push(bit),
+            }
+        }
+        if unknown.len() > MAX_UNKNOWN {
+            return true;
+        }
+
+        let wanted = self.target[ty * self.target_width + tx];
+        (0..1 << unknown.len()).any(|choice: usize| {
+            let configuration = unknown.iter()
+                .enumerate()
+                .filter(|&(i, _)| choice & (1 << i) != 0)
+                .fold(known, |configuration, (_, bit)| configuration | 1 << bit);
+
+            self.table[configuration] == wanted
+        })
+    }
+
+    fn consistent(&self, index: usize) -> bool {
+        let (px, py) = (index % self.width(), index / self.width());
+
+        for ty in py.saturating_sub(2)..py + 1 {
+            for tx in px.saturating_sub(2)..px + 1 {
+                if tx < self.target_width && ty < self.target_height && !self.allows(tx, ty) {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    // Some(true) once every cell is set, None if it ran out of nodes
+    fn extend(&mut self, index: usize) -> Option<bool> {
+        if index == self.cells.len() {
+            return Some(true);
+        }
+
+        // Dead first, so predecessors come out with as few cells as it easily can
+        for &alive in [false, true].iter() {
+            self.nodes += 1;
+            if self.nodes > self.max_nodes {
+                return None;
+            }
+
+            self.cells[index] = Some(alive);
+            if self.consistent(index) {
+                match self.extend(index + 1) {
+                    Some(false) => {}
+                    found => return found,
+                }
+            }
+        }
+        self.cells[index] = None;
+
+        Some(false)
+    }
+}
+
+// Looks for a pattern that becomes the target a generation later, within its width and height,
+// so dead cells around the edge of the target have to stay dead. Anything outside the
+// predecessor is left to be whatever it likes.
+pub fn find(rule: &Rule, target: &RlePattern, max_nodes: u64) -> Result<Predecessor, String> {
+    let table = try!(transitions(rule));
+    let (width, height) = (target.get_width() as usize, target.get_height() as usize);
+
+    let mut wanted = vec![false; width * height];
+    for (&(x, y), &state) in target.get_cells() {
+        wanted[y as usize * width + x as usize] = rule.is_alive(state);
+    }
+
+    let mut search = Search {
+        table: &table,
+        target: wanted,
+        target_width: width,
+        target_height: height,
+        cells: vec![None; (width + 2) * (height + 2)],
+        nodes: 0,
+        max_nodes: max_nodes,
+    };
+
+    Ok(match search.extend(0) {
+        Some(true) => {
+            let cells = search.cells
+                .iter()
+                .enumerate()
+                .filter(|&(_, &alive)| alive == Some(true))
+                .map(|(index, _)| {
+                    (((index % (width + 2)) as u32, (index / (width + 2)) as u32), 1)
+                })
+                .collect();
+
+            Predecessor::Found(RlePattern::new(width as u32 + 2,
+                                               height as u32 + 2,
+                                               cells,
+                                               Some(rule.clone())))
+        }
+        Some(false) => Predecessor::GardenOfEden,
+        None => Predecessor::GaveUp,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use evolve;
+    use rle;
+    use rule::Rule;
+    use super::*;
+
+    // The predecessor run on a generation, cut down to the target's window
+    fn successor(rule: &Rule, predecessor: &RlePattern, width: u32, height: u32) -> RlePattern {
+        let cells = predecessor.get_cells()
+            .iter()
+            .map(|(&(x, y), &state)| ((x as i64 - 1, y as i64 - 1), state))
+            .collect();
+        let next: HashMap<(u32, u32), u8> = evolve::step(rule, &cells, 0)
+            .unwrap()
+            .into_iter()
+            .filter(|&((x, y), _)| x >= 0 && y >= 0 && x < width as i64 && y < height as i64)
+            .map(|((x, y), state)| ((x as u32, y as u32), state))
+            .collect();
+
+        RlePattern::new(width, height, next, Some(rule.clone()))
+    }
+
+    #[test]
+    fn predecessor_found_becomes_target() {
+        let rule = Rule::conway();
+        // A blinker with a dead cell all round
+        let target = rle::read("x = 5, y = 5, rule = B3/S23\n5b$5b$b3ob$5b$5b!").unwrap();
+
+        match find(&rule, &target, DEFAULT_MAX_NODES).unwrap() {
+            Predecessor::Found(predecessor) => {
+                assert_eq!(successor(&rule, &predecessor, 5, 5), target);
+            }
+            other => panic!("Expected a predecessor, got [{:?}]", other),
+        }
+    }
+
+    #[test]
+    fn predecessor_garden_of_eden_and_giving_up() {
+        // Nothing is born and nothing survives, so only an empty board has a predecessor
+        let rule = Rule::parse("B/S").unwrap();
+        let target = rle::read("x = 3, y = 3\n3b$bob$3b!").unwrap();
+
+        assert_eq!(find(&rule, &target, DEFAULT_MAX_NODES), Ok(Predecessor::GardenOfEden));
+        assert_eq!(find(&Rule::conway(), &target, 3), Ok(Predecessor::GaveUp));
+        assert!(find(&Rule::parse("B3/S23/C3").unwrap(), &target, 3).is_err());
+    }
+}
@@ -0,0 +1,48 @@
+// Counter based random numbers, the number for a cell only depends on the seed, where the cell is
+// on the whole board, the generation and which of the cell's draws it is. However the board is
+// split into sections, and whichever thread works a section out, a cell gets the same numbers.
+
+// Chances are given in millionths
+pub const CERTAIN: u32 = 1_000_000;
+
+// SplitMix64's finaliser, every bit of the input affects every bit of the output
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+pub fn random(seed: u64, x: u32, y: u32, generation: usize, draw: u32) -> u64 {
+    let position = (x as u64) << 32 | y as u64;
+
+    mix(mix(mix(mix(seed) ^ position) ^ generation as u64) ^ draw as u64)
+}
+
+// Whether something with the chance (in millionths) happens
+pub fn happens(chance: u32, seed: u64, x: u32, y: u32, generation: usize, draw: u32) -> bool {
+    chance >= CERTAIN || (random(seed, x, y, generation, draw) % CERTAIN as u64) < chance as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_is_the_same_for_the_same_cell() {
+        assert_eq!(random(1, 5, 7, 3, 0), random(1, 5, 7, 3, 0));
+        assert!(random(1, 5, 7, 3, 0) != random(1, 7, 5, 3, 0));
+        assert!(random(1, 5, 7, 3, 0) != random(1, 5, 7, 4, 0));
+        assert!(random(1, 5, 7, 3, 0) != random(1, 5, 7, 3, 1));
+        assert!(random(1, 5, 7, 3, 0) != random(2, 5, 7, 3, 0));
+    }
+
+    #[test]
+    fn random_happens_about_as_often_as_the_chance() {
+        let count = (0..10000).filter(|&x| happens(CERTAIN / 4, 9, x, 0, 0, 0)).count();
+
+        assert!(count > 2300 && count < 2700, "happened [{}] times", count);
+        assert!((0..100).all(|x| happens(CERTAIN, 9, x, 0, 0, 0)));
+        assert!((0..100).all(|x| !happens(0, 9, x, 0, 0, 0)));
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use rule::Rule;
+
+const LINE_LENGTH: usize = 70;
+
+// A pattern read from or to be written as RLE, cells are keyed by (x, y) and only non zero
+// states are kept
+#[derive(Clone, Debug, PartialEq)]
+pub struct RlePattern {
+    width: u32,
+    height: u32,
+    cells: HashMap<(u32, u32), u8>,
+    rule: Option<Rule>,
+}
+
+impl RlePattern {
+    pub fn new(width: u32, height: u32, cells: HashMap<(u32, u32), u8>, rule: Option<Rule>) -> RlePattern {
+        RlePattern {
+            width: width,
+            height: height,
+            cells: cells.into_iter().filter(|&(_, s)| s != 0).collect(),
+            rule: rule,
+        }
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get_cells(&self) -> &HashMap<(u32, u32), u8> {
+        &self.cells
+    }
+
+    pub fn get_rule(&self) -> Option<&Rule> {
+        self.rule.as_ref()
+    }
+
+    // Err if any cell has a state the rule doesn't have
+    pub fn check_states(&self, rule: &Rule) -> Result<(), String> {
+        match self.cells.iter().find(|&(_, &state)| state >= rule.get_states()) {
+            Some((&(x, y), &state)) => {
+                Err(format!("Cell at [{}, {}] has state [{}] but rule [{}] only has [{}] states",
+                            x,
+                            y,
+                            state,
+                            rule,
+                            rule.get_states()))
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn get_state(&self, x: u32, y: u32) -> u8 {
+        self.cells.get(&(x, y)).cloned().unwrap_or(0)
+    }
+}
+
+pub fn read(text: &str) -> Result<RlePattern, String> {
+    let mut width = None;
+    let mut height = None;
+    let mut rule = None;
+    let mut body = String::new();
+
+    for line in text.lines() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        } else if width.is_none() && line.starts_with('x') {
+            // The rule comes last and Larger than Life rules have commas of their own
+            let (line, rule_setting) = match line.find("rule") {
+                Some(i) => (&line[..i], Some(&line[i..])),
+                None => (line, None),
+            };
+            if let Some(setting) = rule_setting {
+                let text = setting.splitn(2, '=').nth(1).unwrap_or("").trim();
+                rule = Some(try!(Rule::parse(text)));
+            }
+
+            for setting in line.split(',').filter(|s| !s.trim().is_empty()) {
+                let mut parts = setting.splitn(2, '=');
+                let name = parts.next().unwrap_or("").trim();
+                let value = parts.next().unwrap_or("").trim();
+
+                match name {
+                    "x" => width = Some(try!(parse_size(value))),
+                    "y" => height = Some(try!(parse_size(value))),
+                    _ => return Err(format!("Unknown RLE header setting [{}]", setting.trim())),
+                }
+            }
+        } else {
+            body.push_str(line);
+        }
+    }
+
+    let (width, height) = match (width, height) {
+        (Some(w), Some(h)) => (w, h),
+        _ => return Err("RLE is missing its x = .., y = .. header".to_string()),
+    };
+
+    let mut cells = HashMap::new();
+    let mut x = 0;
+    let mut y = 0;
+    let mut count = String::new();
+    let mut prefix = None;
+
+    for c in body.chars() {
+        let run = if count.is_empty() {
+            1
+        } else {
+            try!(count.parse::<u32>().map_err(|_| format!("Bad run length [{}]", count)))
+        };
+
+        let state = match c {
+            '0'..='9' if prefix.is_none() => {
+                count.push(c);
+                continue;
+            }
+            'p'..='y' if prefix.is_none() => {
+                prefix = Some(c);
+                continue;
+            }
+            '!' => break,
+            '$' => {
+                x = 0;
+                y += run;
+                count.clear();
+                continue;
+            }
+            'b' | '.' if prefix.is_none() => 0,
+            'o' if prefix.is_none() => 1,
+            'A'..='X' => {
+                let offset = prefix.map(|p| (p as u32 - 'p' as u32 + 1) * 24).unwrap_or(0);
+                prefix = None;
+                let state = offset + (c as u32 - 'A' as u32 + 1);
+                if state > 255 {
+                    return Err(format!("State [{}] too large", state));
+                }
+
+                state as u8
+            }
+            _ => return Err(format!("Unexpected [{}] in RLE", c)),
+        };
+
+        if state != 0 {
+            for i in 0..run {
+                cells.insert((x + i, y), state);
+            }
+        }
+        x += run;
+        count.clear();
+    }
+
+    for &(cx, cy) in cells.keys() {
+        if cx >= width || cy >= height {
+            return Err(format!("Cell at [{}, {}] is outside the RLE's size", cx, cy));
+        }
+    }
+
+    let pattern = RlePattern::new(width, height, cells, rule);
+    if let Some(ref rule) = pattern.rule {
+        try!(pattern.check_states(rule));
+    }
+
+    Ok(pattern)
+}
+
+fn parse_size(value: &str) -> Result<u32, String> {
+    value.parse().map_err(|_| format!("Bad RLE size [{}]", value))
+}
+
+// Two state patterns use b and o, anything with more states uses . and A to X (with p to y
+// prefixes for states above 24)
+pub fn write(pattern: &RlePattern) -> String {
+    let multi_state = pattern.rule.as_ref().map(|r| r.get_states() > 2).unwrap_or(false) ||
+                      pattern.cells.values().any(|&s| s > 1);
+
+    let mut out = format!("x = {}, y = {}", pattern.width, pattern.height);
+    if let Some(ref rule) = pattern.rule {
+        out.push_str(&format!(", rule = {}", rule));
+    }
+    out.push('\n');
+
+    let mut tokens = Vec::new();
+    let mut row = 0;
+    for y in 0..pattern.height {
+        let last = match (0..pattern.width).rev().find(|&x| pattern.get_state(x, y) != 0) {
+            Some(l) => l,
+            None => continue,
+        };
+
+        // Blank rows are just more ends of lines
+        if y > row {
+            tokens.push(run_token(y - row, "$".to_string()));
+        }
+        row = y;
+
+        let mut x = 0;
+        while x <= last {
+            let state = pattern.get_state(x, y);
+            let mut run = 1;
+            while x + run <= last && pattern.get_state(x + run, y) == state {
+                run += 1;
+            }
+
+            tokens.push(run_token(run, state_token(state, multi_state)));
+            x += run;
+        }
+    }
+    tokens.push("!".to_string());
+
+    let mut line = String::new();
+    for token in tokens {
+        if line.len() + token.len() > LINE_LENGTH {
+            out.push_str(&line);
+            out.push('\n');
+            line.clear();
+        }
+        line.push_str(&token);
+    }
+    out.push_str(&line);
+    out.push('\n');
+
+    out
+}
+
+fn run_token(run: u32, token: String) -> String {
+    if run > 1 {
+        format!("{}{}", run, token)
+    } else {
+        token
+    }
+}
+
+fn state_token(state: u8, multi_state: bool) -> String {
+    if !multi_state {
+        return if state == 0 { "b" } else { "o" }.to_string();
+    }
+
+    match state {
+        0 => ".".to_string(),
+        s if s <= 24 => ((b'A' + s - 1) as char).to_string(),
+        s => {
+            let prefix = (b'p' + (s - 1) / 24 - 1) as char;
+            let letter = (b'A' + (s - 1) % 24) as char;
+            format!("{}{}", prefix, letter)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use rule::Rule;
+    use super::*;
+
+    #[test]
+    fn rle_read_glider() {
+        let pattern = read("#N Glider\nx = 3, y = 3, rule = B3/S23\nbob$2bo$3o!\n").unwrap();
+
+        assert_eq!(pattern.get_width(), 3);
+        assert_eq!(pattern.get_height(), 3);
+        assert_eq!(pattern.get_rule(), Some(&Rule::conway()));
+        assert_eq!(pattern.get_cells().len(), 5);
+        assert_eq!(pattern.get_cells().get(&(1, 0)), Some(&1));
+        assert_eq!(pattern.get_cells().get(&(0, 2)), Some(&1));
+    }
+
+    #[test]
+    fn rle_write_then_read_is_same() {
+        let mut cells = HashMap::new();
+        cells.insert((0, 0), 1);
+        cells.insert((4, 0), 1);
+        cells.insert((2, 3), 1);
+        let pattern = RlePattern::new(5, 4, cells, Some(Rule::conway()));
+
+        let written = write(&pattern);
+
+        assert_eq!(written, "x = 5, y = 4, rule = B3/S23\no3bo3$2bo!\n");
+        assert_eq!(read(&written), Ok(pattern));
+    }
+
+    #[test]
+    fn rle_multi_state_round_trip() {
+        let mut cells = HashMap::new();
+        cells.insert((0, 0), 1);
+        cells.insert((1, 0), 2);
+        cells.insert((2, 1), 30);
+        let pattern = RlePattern::new(3, 2, cells, Some(Rule::parse("B2/S/C31").unwrap()));
+
+        let written = write(&pattern);
+
+        assert_eq!(written, "x = 3, y = 2, rule = B2/S/C31\nAB$2.pF!\n");
+        assert_eq!(read(&written), Ok(pattern));
+    }
+
+    #[test]
+    fn rle_write_leading_blank_row() {
+        let mut cells = HashMap::new();
+        cells.insert((1, 1), 1);
+        let pattern = RlePattern::new(2, 2, cells, None);
+
+        assert_eq!(write(&pattern), "x = 2, y = 2\n$bo!\n");
+    }
+
+    #[test]
+    fn rle_larger_than_life_rule_round_trip() {
+        let mut cells = HashMap::new();
+        cells.insert((0, 0), 1);
+        let rule = Rule::parse("R5,C0,M1,S34..58,B34..45,NM").unwrap();
+        let pattern = RlePattern::new(1, 1, cells, Some(rule));
+
+        let written = write(&pattern);
+
+        assert_eq!(written, "x = 1, y = 1, rule = R5,C0,M1,S34..58,B34..45,NM\no!\n");
+        assert_eq!(read(&written), Ok(pattern));
+    }
+
+    #[test]
+    fn rle_isotropic_rule_round_trip() {
+        let pattern = read("x = 2, y = 1, rule = B2-a/S12\n2o!\n").unwrap();
+
+        assert!(pattern.get_rule().unwrap().is_isotropic());
+        assert_eq!(write(&pattern), "x = 2, y = 1, rule = B2-a/S12\n2o!\n");
+    }
+
+    #[test]
+    fn rle_read_rejects_cells_outside_size() {
+        assert!(read("x = 1, y = 1\n2o!").is_err());
+    }
+
+    #[test]
+    fn rle_read_rejects_states_outside_rule() {
+        assert!(read("x = 2, y = 1, rule = B2/S/C3\nAB!").is_ok());
+        assert!(read("x = 2, y = 1, rule = B2/S/C3\nAC!").is_err());
+
+        let pattern = read("x = 1, y = 1\nC!").unwrap();
+        assert!(pattern.check_states(&Rule::conway()).is_err());
+    }
+}
@@ -0,0 +1,1320 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use hensel;
+use random;
+use random::CERTAIN;
+use table;
+use table::RuleTable;
+
+// Largest range accepted for Larger than Life rules, the halo between sections is this wide
+const MAX_RANGE: u32 = 50;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Neighbourhood {
+    Moore,
+    VonNeumann,
+    // Cells within a distance of range + 0.5, like Golly's NC
+    Circular,
+}
+
+// Shape of the cells. Hexagonal rows are offset, odd rows (counting across the whole board) are
+// half a cell to the right. Triangles alternate pointing up and down along a row, the one at
+// (0, 0) points up.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Tiling {
+    Square,
+    Hexagonal,
+    Triangular,
+}
+
+// Most neighbours any tiling has, the 12 triangles touching a triangle
+const MAX_NEIGHBOURS: usize = 12;
+
+// Most live colours a rule can have, QuadLife's four
+pub const MAX_SPECIES: usize = 4;
+
+// How many cells deep the space of a 3D rule is when the rule doesn't say
+pub const DEFAULT_DEPTH: u32 = 8;
+
+// 3D rules run on the flat board with depth columns side by side for each x of the space, one
+// for each z, after a first column that is only ever halo. The rows are y.
+pub fn space_column(x: u32, z: u32, depth: u32) -> u32 {
+    x * depth + z + 1
+}
+
+// Which x and z of the space the column of the board is, None for the first column
+pub fn space_position(column: u32, depth: u32) -> Option<(u32, u32)> {
+    column.checked_sub(1).map(|column| (column / depth, column % depth))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rule {
+    // Outer totalistic rule where a dying cell goes through states 2 up to states - 1 before it is
+    // dead, and only state 1 counts as alive. With 2 states it is a normal Life-like rule.
+    Generations {
+        birth: [bool; MAX_NEIGHBOURS + 1],
+        survival: [bool; MAX_NEIGHBOURS + 1],
+        states: u8,
+        tiling: Tiling,
+    },
+    // Larger than Life, counts live cells within range (including the cell itself when middle
+    // is set) and births or survives when the count is within the inclusive bounds
+    LargerThanLife {
+        range: u32,
+        neighbourhood: Neighbourhood,
+        middle: bool,
+        survival: (u16, u16),
+        birth: (u16, u16),
+        states: u8,
+    },
+    // Isotropic non-totalistic rule in Hensel notation such as B2-a/S12, which cells live next
+    // depends on where the live neighbours are rather than how many there are. The table is
+    // indexed by the neighbourhood configuration, see hensel.
+    Isotropic { table: Vec<bool>, states: u8 },
+    // Life where live cells are one of several colours, states 1 up to species are all alive.
+    // A newborn cell takes the colour most of its three parents have, or in QuadLife when all
+    // three differ the colour none of them have. Immigration has 2 species and QuadLife 4.
+    Colours { species: u8 },
+    // Golly style transition table, where the next state depends on the state of each neighbour
+    Table(Arc<RuleTable>),
+    // One dimensional rules, each row is its own line of cells and only the cells along the row
+    // count. An elementary rule (W30) looks up the next state in bit left * 4 + self * 2 + right
+    // of its number.
+    Elementary { number: u8 },
+    // One dimensional totalistic rule (T10R2), the next state is the bit of the code for how many
+    // live cells there are within range along the row, the cell itself included
+    Totalistic { code: u64, range: u32 },
+    // Generations, Larger than Life or isotropic rule where births and survivals only happen by
+    // chance, and each generation only some of the cells are updated at all. Written as the rule
+    // then ;birth=0.5;survival=0.9;update=0.5;seed=1, any of which can be left out. Chances are
+    // in millionths, see random.
+    Stochastic {
+        rule: Box<Rule>,
+        birth: u32,
+        survival: u32,
+        update: u32,
+        seed: u64,
+    },
+    // Block cellular automaton on the Margolus neighbourhood, the board is split into 2x2 blocks
+    // and each block becomes what the table says for it. Blocks start on even rows and columns
+    // of the whole board for even generations and odd ones for odd generations. The block's
+    // cells are bits, top left 1, top right 2, bottom left 4 and bottom right 8.
+    Margolus { table: [u8; 16] },
+    // Life in three dimensions on the 26 cells around each cell, in a space depth cells deep laid
+    // out on the board as space_column says. Carter Bays' notation, survival bounds then birth
+    // bounds, so 3D4,5,5,5 survives on 4 or 5 live neighbours and is born on 5.
+    Life3D {
+        survival: (u16, u16),
+        birth: (u16, u16),
+        depth: u32,
+    },
+}
+
+impl Rule {
+    // Any live cell with fewer than two live neighbours dies, as if caused by under-population.
+    // Any live cell with two or three live neighbours lives on to the next generation.
+    // Any live cell with more than three live neighbours dies, as if by over-population.
+    // Any dead cell with exactly three live neighbours becomes a live cell, as if by reproduction.
+    pub fn conway() -> Rule {
+        Rule::Generations {
+            birth: counts(&[3]),
+            survival: counts(&[2, 3]),
+            states: 2,
+            tiling: Tiling::Square,
+        }
+    }
+
+    // Accepts B3/S23 style, S/B style (23/3), Generations rules as B2/S/C3 or S/B/C (/2/3),
+    // Hensel notation such as B2-a/S12, Larger than Life rules as R5,C0,M1,S34..58,B34..45,NM
+    // the colour rules by name, Immigration and QuadLife, one dimensional rules as W30 or T10R2,
+    // Margolus rules as M then the 16 block results split by commas (or BBM, Critters or Tron)
+    // and 3D rules as 3D then the four bounds split by commas, like 3D4,5,5,5, with D and the
+    // depth of the space after another comma if it isn't the default. Bays' four digits (4555
+    // or 5766) are 3D rules too, bounds over 9 need the 3D form. Any other name is a rule
+    // table, either built in like WireWorld or read from NAME.rule (or a path ending in .rule).
+    // A H or L on the end is a hexagonal or triangular rule, triangles count 10 to 12 neighbours
+    // as a to c.
+    pub fn parse(text: &str) -> Result<Rule, String> {
+        let text = text.trim();
+        if let Some(i) = text.find(';') {
+            return parse_stochastic(&text[..i], &text[i + 1..], text);
+        }
+        match text.to_lowercase().as_ref() {
+            "immigration" => return Ok(Rule::Colours { species: 2 }),
+            "quadlife" => return Ok(Rule::Colours { species: 4 }),
+            _ => {}
+        }
+        if let Some(rule) = try!(parse_three_dimensional(text)) {
+            return Ok(rule);
+        }
+        if let Some(rule) = try!(parse_margolus(text)) {
+            return Ok(rule);
+        }
+        if let Some(rule) = try!(parse_one_dimensional(text)) {
+            return Ok(rule);
+        }
+        if let Some(table) = table::built_in(text) {
+            return Ok(Rule::Table(Arc::new(table)));
+        }
+        let rule_file = format!("{}.rule", text);
+        if text.ends_with(".rule") || is_name(text) && Path::new(&rule_file).exists() {
+            return table::load(text).map(|table| Rule::Table(Arc::new(table)));
+        }
+        if text.starts_with('R') && text.contains(',') {
+            return parse_larger_than_life(text);
+        }
+
+        let (body, tiling) = match text.chars().last().map(|c| c.to_ascii_uppercase()) {
+            Some('H') => (&text[..text.len() - 1], Tiling::Hexagonal),
+            Some('L') => (&text[..text.len() - 1], Tiling::Triangular),
+            _ => (text, Tiling::Square),
+        };
+
+        let parts: Vec<&str> = body.split('/').collect();
+        if parts.len() < 2 || parts.len() > 3 {
+            return Err(format!("Rule [{}] should have two or three parts split by /", text));
+        }
+
+        let mut birth = None;
+        let mut survival = None;
+        let mut states = None;
+
+        for (i, part) in parts.iter().enumerate() {
+            let part = part.trim();
+            let (kind, digits) = match part.chars().next() {
+                Some(c) if c.is_alphabetic() => (c.to_ascii_uppercase(), &part[c.len_utf8()..]),
+                _ => (['S', 'B', 'C'][i], part),
+            };
+
+            match kind {
+                'B' if birth.is_none() => birth = Some(digits),
+                'S' if survival.is_none() => survival = Some(digits),
+                'C' | 'G' if states.is_none() => {
+                    let count = try!(digits.parse::<u8>()
+                        .map_err(|_| format!("Bad number of states [{}] in rule [{}]", digits, text)));
+                    if count < 2 {
+                        return Err(format!("Rule [{}] needs at least 2 states", text));
+                    }
+                    states = Some(count);
+                }
+                _ => return Err(format!("Unexpected or repeated [{}] in rule [{}]", part, text)),
+            }
+        }
+
+        let (birth, survival) = match (birth, survival) {
+            (Some(birth), Some(survival)) => (birth, survival),
+            _ => return Err(format!("Rule [{}] needs both birth and survival parts", text)),
+        };
+        let states = states.unwrap_or(2);
+
+        if tiling != Tiling::Square {
+            let neighbours = if tiling == Tiling::Hexagonal { 6 } else { 12 };
+
+            return Ok(Rule::Generations {
+                birth: try!(parse_counts(birth, neighbours, text)),
+                survival: try!(parse_counts(survival, neighbours, text)),
+                states: states,
+                tiling: tiling,
+            });
+        }
+
+        let birth = try!(parse_conditions(birth, text));
+        let survival = try!(parse_conditions(survival, text));
+
+        // Letters that don't change anything, like 3cekainyqjr, still make a totalistic rule
+        if hensel::is_totalistic(&birth) && hensel::is_totalistic(&survival) {
+            Ok(Rule::Generations {
+                birth: totalistic_counts(&birth),
+                survival: totalistic_counts(&survival),
+                states: states,
+                tiling: Tiling::Square,
+            })
+        } else {
+            Ok(Rule::Isotropic {
+                table: hensel::create_table(&birth, &survival),
+                states: states,
+            })
+        }
+    }
+
+    // Whether next_state_from_configuration has to be used rather than next_state
+    pub fn is_isotropic(&self) -> bool {
+        match *self {
+            Rule::Isotropic { .. } => true,
+            Rule::Stochastic { ref rule, .. } => rule.is_isotropic(),
+            _ => false,
+        }
+    }
+
+    pub fn get_states(&self) -> u8 {
+        match *self {
+            Rule::Generations { states, .. } => states,
+            Rule::LargerThanLife { states, .. } => states,
+            Rule::Isotropic { states, .. } => states,
+            Rule::Colours { species } => species + 1,
+            Rule::Table(ref table) => table.get_states(),
+            Rule::Elementary { .. } |
+            Rule::Totalistic { .. } |
+            Rule::Margolus { .. } |
+            Rule::Life3D { .. } => 2,
+            Rule::Stochastic { ref rule, .. } => rule.get_states(),
+        }
+    }
+
+    // How many colours live cells come in, 1 for everything but the colour rules
+    pub fn get_species(&self) -> u8 {
+        match *self {
+            Rule::Colours { species } => species,
+            _ => 1,
+        }
+    }
+
+    // Whether a cell in the state counts as a live neighbour
+    pub fn is_alive(&self, state: u8) -> bool {
+        match *self {
+            Rule::Colours { .. } |
+            Rule::Table(_) => state != 0,
+            _ => state == 1,
+        }
+    }
+
+    pub fn get_tiling(&self) -> Tiling {
+        match *self {
+            Rule::Generations { tiling, .. } => tiling,
+            Rule::Stochastic { ref rule, .. } => rule.get_tiling(),
+            _ => Tiling::Square,
+        }
+    }
+
+    // How far away a cell can be and still be counted, so how wide the halo of a section is
+    pub fn get_range(&self) -> u32 {
+        match *self {
+            // Triangles touch ones two along the row
+            Rule::Generations { tiling: Tiling::Triangular, .. } => 2,
+            Rule::Generations { .. } => 1,
+            Rule::LargerThanLife { range, .. } => range,
+            Rule::Isotropic { .. } |
+            Rule::Colours { .. } |
+            Rule::Table(_) |
+            Rule::Elementary { .. } |
+            Rule::Margolus { .. } => 1,
+            // The next x of the space is depth columns along
+            Rule::Life3D { depth, .. } => depth + 1,
+            Rule::Totalistic { range, .. } => range,
+            Rule::Stochastic { ref rule, .. } => rule.get_range(),
+        }
+    }
+
+    // How far up and down the neighbourhood reaches, 3D rules only reach the next row as it is
+    // their z that is laid out across
+    pub fn get_row_range(&self) -> u32 {
+        match *self {
+            Rule::Life3D { .. } => 1,
+            _ => self.get_range(),
+        }
+    }
+
+    // Whether the board is a space laid out as space_column says
+    pub fn is_three_dimensional(&self) -> bool {
+        match *self {
+            Rule::Life3D { .. } => true,
+            _ => false,
+        }
+    }
+
+    // How many cells deep the space is, 1 for flat rules
+    pub fn get_depth(&self) -> u32 {
+        match *self {
+            Rule::Life3D { depth, .. } => depth,
+            _ => 1,
+        }
+    }
+
+    // After how many columns and rows of the whole board get_neighbourhood repeats
+    pub fn get_neighbourhood_period(&self) -> (u32, u32) {
+        match *self {
+            Rule::Life3D { depth, .. } => (depth, 1),
+            _ => (2, 2),
+        }
+    }
+
+    // Whether only cells along the row count, so it can be run on a single row of sections
+    pub fn is_one_dimensional(&self) -> bool {
+        match *self {
+            Rule::Elementary { .. } |
+            Rule::Totalistic { .. } => true,
+            _ => false,
+        }
+    }
+
+    // Offsets of the cells that are counted, including (0, 0) if the cell counts itself. Only
+    // hexagons, triangles and 3D rules depend on where the cell is, which is its position across
+    // the whole board and only matters up to get_neighbourhood_period.
+    pub fn get_neighbourhood(&self, x: u32, y: u32) -> Vec<(i32, i32)> {
+        match self.get_tiling() {
+            Tiling::Hexagonal => {
+                let shift = (y % 2) as i32;
+
+                return vec![(-1, 0),
+                            (1, 0),
+                            (shift - 1, -1),
+                            (shift, -1),
+                            (shift - 1, 1),
+                            (shift, 1)];
+            }
+            Tiling::Triangular => {
+                // Whichever row the flat side is on has five touching triangles, the other three
+                let (flat, point) = if (x + y) % 2 == 0 { (1, -1) } else { (-1, 1) };
+                let mut offsets = Vec::with_capacity(MAX_NEIGHBOURS);
+
+                for dx in -2..3 {
+                    if dx != 0 {
+                        offsets.push((dx, 0));
+                    }
+                    offsets.push((dx, flat));
+                }
+                for dx in -1..2 {
+                    offsets.push((dx, point));
+                }
+
+                return offsets;
+            }
+            Tiling::Square => {}
+        }
+        match *self {
+            Rule::Table(ref table) => return table.get_offsets(),
+            Rule::Stochastic { ref rule, .. } => return rule.get_neighbourhood(x, y),
+            Rule::Elementary { .. } => return vec![(-1, 0), (1, 0)],
+            Rule::Life3D { depth, .. } => {
+                // The column's z, see space_column. Nothing is below 0 or at the depth or past.
+                let z = (x + depth - 1) % depth;
+                let mut offsets = Vec::with_capacity(26);
+
+                for dz in -1..2i32 {
+                    if z as i32 + dz < 0 || z as i32 + dz >= depth as i32 {
+                        continue;
+                    }
+                    for dy in -1..2 {
+                        for dx in -1..2i32 {
+                            if (dx, dy, dz) != (0, 0, 0) {
+                                offsets.push((dx * depth as i32 + dz, dy));
+                            }
+                        }
+                    }
+                }
+
+                return offsets;
+            }
+            Rule::Totalistic { range, .. } => {
+                let range = range as i32;
+
+                return (-range..range + 1).filter(|&x| x != 0).map(|x| (x, 0)).collect();
+            }
+            _ => {}
+        }
+
+        let (range, neighbourhood, middle) = match *self {
+            Rule::Generations { .. } |
+            Rule::Isotropic { .. } |
+            Rule::Colours { .. } |
+            Rule::Table(_) |
+            Rule::Elementary { .. } |
+            Rule::Totalistic { .. } |
+            Rule::Stochastic { .. } |
+            Rule::Margolus { .. } |
+            Rule::Life3D { .. } => (1, Neighbourhood::Moore, false),
+            Rule::LargerThanLife { range, neighbourhood, middle, .. } => {
+                (range as i32, neighbourhood, middle)
+            }
+        };
+
+        let mut offsets = Vec::new();
+        for x in -range..range + 1 {
+            for y in -range..range + 1 {
+                let within = match neighbourhood {
+                    Neighbourhood::Moore => true,
+                    Neighbourhood::VonNeumann => x.abs() + y.abs() <= range,
+                    Neighbourhood::Circular => x * x + y * y <= range * range + range,
+                };
+
+                if within && (middle || (x, y) != (0, 0)) {
+                    offsets.push((x, y));
+                }
+            }
+        }
+
+        offsets
+    }
+
+    pub fn next_state(&self, state: u8, alive_neighbours: u16) -> u8 {
+        match *self {
+            Rule::Generations { ref birth, ref survival, states, .. } => {
+                let alive_neighbours = alive_neighbours as usize;
+
+                next_state(state,
+                           birth.get(alive_neighbours).cloned().unwrap_or(false),
+                           survival.get(alive_neighbours).cloned().unwrap_or(false),
+                           states)
+            }
+            Rule::LargerThanLife { survival, birth, states, .. } => {
+                next_state(state,
+                           birth.0 <= alive_neighbours && alive_neighbours <= birth.1,
+                           survival.0 <= alive_neighbours && alive_neighbours <= survival.1,
+                           states)
+            }
+            Rule::Isotropic { .. } => {
+                panic!("Isotropic rules need the neighbour configuration, not just the count")
+            }
+            Rule::Colours { .. } => {
+                panic!("Colour rules need how many neighbours there are of each species")
+            }
+            Rule::Table(_) => panic!("Rule tables need the state of each neighbour"),
+            Rule::Elementary { .. } => {
+                panic!("Elementary rules need which side each live neighbour is on")
+            }
+            Rule::Totalistic { code, .. } => {
+                let total = alive_neighbours as u32 + (state == 1) as u32;
+
+                (code.checked_shr(total).unwrap_or(0) & 1) as u8
+            }
+            // Leaving chance to next_state_by_chance
+            Rule::Stochastic { ref rule, .. } => rule.next_state(state, alive_neighbours),
+            Rule::Margolus { .. } => panic!("Margolus rules need the cell's block"),
+            Rule::Life3D { survival, birth, .. } => {
+                next_state(state,
+                           birth.0 <= alive_neighbours && alive_neighbours <= birth.1,
+                           survival.0 <= alive_neighbours && alive_neighbours <= survival.1,
+                           2)
+            }
+        }
+    }
+
+    // Whether next_state_from_block has to be used rather than next_state
+    pub fn is_margolus(&self) -> bool {
+        match *self {
+            Rule::Margolus { .. } => true,
+            _ => false,
+        }
+    }
+
+    // The block has a bit for each live cell in it and position is which of those is the cell
+    pub fn next_state_from_block(&self, block: u8, position: u8) -> u8 {
+        match *self {
+            Rule::Margolus { ref table } => (table[block as usize] >> position) & 1,
+            _ => panic!("Only Margolus rules work on blocks"),
+        }
+    }
+
+    // A Margolus rule can be run backwards if no two blocks become the same block, None for
+    // other rules
+    pub fn is_reversible(&self) -> Option<bool> {
+        match *self {
+            Rule::Margolus { ref table } => {
+                let mut seen = [false; 16];
+                for &block in table.iter() {
+                    seen[block as usize] = true;
+                }
+
+                Some(seen.iter().all(|&s| s))
+            }
+            _ => None,
+        }
+    }
+
+    // Stochastic rules only let the next state's births and survivals happen by chance, and
+    // only update some cells. x and y are on the whole board. Other rules keep the next state.
+    pub fn next_state_by_chance(&self,
+                                state: u8,
+                                next: u8,
+                                x: u32,
+                                y: u32,
+                                generation: usize)
+                                -> u8 {
+        let (states, birth, survival, update, seed) = match *self {
+            Rule::Stochastic { ref rule, birth, survival, update, seed } => {
+                (rule.get_states(), birth, survival, update, seed)
+            }
+            _ => return next,
+        };
+
+        if !random::happens(update, seed, x, y, generation, 0) {
+            return state;
+        }
+
+        match (state, next) {
+            (0, 1) if !random::happens(birth, seed, x, y, generation, 1) => 0,
+            (1, 1) if !random::happens(survival, seed, x, y, generation, 2) => {
+                next_state(1, false, false, states)
+            }
+            _ => next,
+        }
+    }
+
+    pub fn is_table(&self) -> bool {
+        match *self {
+            Rule::Table(_) => true,
+            _ => false,
+        }
+    }
+
+    // Whether next_state_from_neighbours has to be used rather than next_state
+    pub fn needs_neighbour_states(&self) -> bool {
+        match *self {
+            Rule::Table(_) |
+            Rule::Elementary { .. } => true,
+            _ => false,
+        }
+    }
+
+    // The neighbours are in the order of get_neighbourhood
+    pub fn next_state_from_neighbours(&self, state: u8, neighbours: &[u8]) -> u8 {
+        match *self {
+            Rule::Table(ref table) => table.next_state(state, neighbours),
+            Rule::Elementary { number } => {
+                let pattern = (neighbours[0] == 1) as u8 * 4 + (state == 1) as u8 * 2 +
+                              (neighbours[1] == 1) as u8;
+
+                (number >> pattern) & 1
+            }
+            _ => {
+                let alive_neighbours = neighbours.iter().filter(|&&s| self.is_alive(s)).count();
+
+                self.next_state(state, alive_neighbours as u16)
+            }
+        }
+    }
+
+    // The counts are how many live neighbours are in each state, index 0 isn't used
+    pub fn next_state_from_species(&self, state: u8, counts: &[u16; MAX_SPECIES + 1]) -> u8 {
+        let species = match *self {
+            Rule::Colours { species } => species as usize,
+            _ => return self.next_state(state, counts[1]),
+        };
+        let alive_neighbours: u16 = counts[1..].iter().sum();
+
+        if state != 0 {
+            if alive_neighbours == 2 || alive_neighbours == 3 {
+                state
+            } else {
+                0
+            }
+        } else if alive_neighbours == 3 {
+            let majority = (1..species + 1).find(|&s| counts[s] >= 2);
+            let missing = (1..species + 1).find(|&s| counts[s] == 0);
+
+            majority.or(missing).unwrap_or(1) as u8
+        } else {
+            0
+        }
+    }
+
+    // The configuration has a bit for each of the 3x3 cells that is alive, see hensel
+    pub fn next_state_from_configuration(&self, state: u8, configuration: u16) -> u8 {
+        match *self {
+            Rule::Isotropic { ref table, states } => {
+                let alive = table[configuration as usize];
+
+                next_state(state, alive, alive, states)
+            }
+            Rule::Stochastic { ref rule, .. } => {
+                rule.next_state_from_configuration(state, configuration)
+            }
+            _ => {
+                let middle = (configuration >> 4) & 1;
+
+                self.next_state(state, (configuration.count_ones() - middle as u32) as u16)
+            }
+        }
+    }
+}
+
+fn next_state(state: u8, born: bool, survives: bool, states: u8) -> u8 {
+    match state {
+        0 => born as u8,
+        1 if survives => 1,
+        // Dying, or dead if there aren't any dying states
+        1 => if states > 2 { 2 } else { 0 },
+        s if s + 1 < states => s + 1,
+        _ => 0,
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Rule::Generations { ref birth, ref survival, states, tiling } => {
+                try!(write!(f, "B{}/S{}", format_counts(birth), format_counts(survival)));
+                if states > 2 {
+                    try!(write!(f, "/C{}", states));
+                }
+
+                match tiling {
+                    Tiling::Square => Ok(()),
+                    Tiling::Hexagonal => write!(f, "H"),
+                    Tiling::Triangular => write!(f, "L"),
+                }
+            }
+            Rule::LargerThanLife { range, neighbourhood, middle, survival, birth, states } => {
+                write!(f,
+                       "R{},C{},M{},S{}..{},B{}..{},N{}",
+                       range,
+                       if states > 2 { states } else { 0 },
+                       middle as u8,
+                       survival.0,
+                       survival.1,
+                       birth.0,
+                       birth.1,
+                       match neighbourhood {
+                           Neighbourhood::Moore => 'M',
+                           Neighbourhood::VonNeumann => 'N',
+                           Neighbourhood::Circular => 'C',
+                       })
+            }
+            Rule::Isotropic { ref table, states } => {
+                try!(write!(f,
+                            "B{}/S{}",
+                            hensel::format_conditions(&hensel::table_conditions(table, false)),
+                            hensel::format_conditions(&hensel::table_conditions(table, true))));
+                if states > 2 {
+                    try!(write!(f, "/C{}", states));
+                }
+
+                Ok(())
+            }
+            // Only 2 and 4 species can be parsed
+            Rule::Colours { species: 2 } => write!(f, "Immigration"),
+            Rule::Colours { .. } => write!(f, "QuadLife"),
+            // Read again from wherever it came from, the built ins by name
+            Rule::Table(ref table) => write!(f, "{}", table.get_path().unwrap_or(table.get_name())),
+            Rule::Elementary { number } => write!(f, "W{}", number),
+            Rule::Totalistic { code, range } => write!(f, "T{}R{}", code, range),
+            Rule::Margolus { ref table } => {
+                let blocks: Vec<String> = table.iter().map(|b| b.to_string()).collect();
+
+                write!(f, "M{}", blocks.join(","))
+            }
+            Rule::Life3D { survival, birth, depth } => {
+                try!(write!(f, "3D{},{},{},{}", survival.0, survival.1, birth.0, birth.1));
+                if depth != DEFAULT_DEPTH {
+                    try!(write!(f, ",D{}", depth));
+                }
+
+                Ok(())
+            }
+            Rule::Stochastic { ref rule, birth, survival, update, seed } => {
+                try!(write!(f, "{}", rule));
+                let chances = [("birth", birth), ("survival", survival), ("update", update)];
+                for &(name, chance) in chances.iter() {
+                    if chance != CERTAIN {
+                        try!(write!(f, ";{}={}", name, chance as f64 / CERTAIN as f64));
+                    }
+                }
+                if seed != 0 {
+                    try!(write!(f, ";seed={}", seed));
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+// The options after the rule are name=value split by ;
+fn parse_stochastic(rule: &str, options: &str, text: &str) -> Result<Rule, String> {
+    let rule = try!(Rule::parse(rule));
+    match rule {
+        Rule::Generations { .. } |
+        Rule::LargerThanLife { .. } |
+        Rule::Isotropic { .. } => {}
+        _ => return Err(format!("Only Generations, Larger than Life and isotropic rules can be \
+                                 stochastic, not [{}]",
+                                rule)),
+    }
+
+    let mut chances = [CERTAIN; 3];
+    let mut seed = 0;
+    for option in options.split(';') {
+        let mut parts = option.splitn(2, '=');
+        let name = parts.next().unwrap_or("").trim();
+        let value = parts.next().unwrap_or("").trim();
+        let bad = || format!("Bad [{}] in rule [{}]", option, text);
+
+        let index = match name {
+            "birth" => 0,
+            "survival" => 1,
+            "update" => 2,
+            "seed" => {
+                seed = try!(value.parse().map_err(|_| bad()));
+                continue;
+            }
+            _ => return Err(format!("Unknown option [{}] in rule [{}]", name, text)),
+        };
+
+        let chance = try!(value.parse::<f64>().map_err(|_| bad()));
+        if chance < 0.0 || chance > 1.0 {
+            return Err(bad());
+        }
+        chances[index] = (chance * CERTAIN as f64).round() as u32;
+    }
+
+    Ok(Rule::Stochastic {
+        rule: Box::new(rule),
+        birth: chances[0],
+        survival: chances[1],
+        update: chances[2],
+        seed: seed,
+    })
+}
+
+// 3D then four numbers split by commas and maybe the depth, or four digits as Bays writes them,
+// see Rule::parse. None if it is neither.
+fn parse_three_dimensional(text: &str) -> Result<Option<Rule>, String> {
+    let mut parts: Vec<&str> = if text.len() == 4 && text.chars().all(|c| c.is_digit(10)) {
+        (0..4).map(|i| &text[i..i + 1]).collect()
+    } else if text.to_uppercase().starts_with("3D") {
+        text[2..].split(',').map(|part| part.trim()).collect()
+    } else {
+        return Ok(None);
+    };
+    let depth = if parts.len() == 5 && parts[4].to_uppercase().starts_with('D') {
+        // Its range is one more than the depth
+        let depth = &parts.pop().unwrap()[1..];
+        match depth.parse::<u32>() {
+            Ok(depth) if depth > 0 && depth < MAX_RANGE => depth,
+            _ => {
+                return Err(format!("Depth [{}] in rule [{}] should be 1 to {}",
+                                   depth,
+                                   text,
+                                   MAX_RANGE - 1))
+            }
+        }
+    } else {
+        DEFAULT_DEPTH
+    };
+
+    let mut numbers = Vec::with_capacity(4);
+    for bound in parts {
+        match bound.parse::<u16>() {
+            Ok(n) if n <= 26 => numbers.push(n),
+            _ => return Err(format!("Bad bound [{}] in rule [{}]", bound, text)),
+        }
+    }
+    if numbers.len() != 4 || numbers[0] > numbers[1] || numbers[2] > numbers[3] {
+        return Err(format!("Rule [{}] needs a lower and upper bound for survival and birth", text));
+    }
+
+    Ok(Some(Rule::Life3D {
+        survival: (numbers[0], numbers[1]),
+        birth: (numbers[2], numbers[3]),
+        depth: depth,
+    }))
+}
+
+// M then the 16 block results, or one of the well known rules by name. None if it isn't either.
+fn parse_margolus(text: &str) -> Result<Option<Rule>, String> {
+    let numbers = match text.to_lowercase().as_ref() {
+        "bbm" => "0,8,4,3,2,5,9,7,1,6,10,11,12,13,14,15".to_string(),
+        "critters" => "15,14,13,3,11,5,6,1,7,9,10,2,12,4,8,0".to_string(),
+        "tron" => "15,1,2,3,4,5,6,7,8,9,10,11,12,13,14,0".to_string(),
+        _ if text.starts_with('M') && text.contains(',') => text[1..].to_string(),
+        _ => return Ok(None),
+    };
+
+    let blocks: Vec<&str> = numbers.split(',').map(|b| b.trim()).collect();
+    if blocks.len() != 16 {
+        return Err(format!("Margolus rule [{}] needs 16 blocks", text));
+    }
+
+    let mut table = [0; 16];
+    for (i, block) in blocks.iter().enumerate() {
+        table[i] = match block.parse::<u8>() {
+            Ok(b) if b < 16 => b,
+            _ => return Err(format!("Bad block [{}] in rule [{}]", block, text)),
+        };
+    }
+
+    Ok(Some(Rule::Margolus { table: table }))
+}
+
+// W then the rule number, or T then the code and R then the range. None if it isn't either.
+fn parse_one_dimensional(text: &str) -> Result<Option<Rule>, String> {
+    let upper = text.to_uppercase();
+    let bad = || format!("Bad one dimensional rule [{}]", text);
+    let is_number = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_digit(10));
+
+    if upper.starts_with('W') && is_number(&upper[1..]) {
+        let number = try!(upper[1..].parse::<u8>().map_err(|_| bad()));
+
+        return Ok(Some(Rule::Elementary { number: number }));
+    }
+
+    if upper.starts_with('T') {
+        let mut parts = upper[1..].splitn(2, 'R');
+        let code = parts.next().unwrap_or("");
+        let range = parts.next().unwrap_or("");
+        if !is_number(code) || !is_number(range) {
+            return Ok(None);
+        }
+
+        let code = try!(code.parse::<u64>().map_err(|_| bad()));
+        let range = try!(range.parse::<u32>().map_err(|_| bad()));
+        // Totals go up to 2 * range + 1, so there are 2 * range + 2 bits of code
+        let bits = 2 * range + 2;
+        if range == 0 || range > MAX_RANGE || bits > 64 || bits < 64 && code >> bits != 0 {
+            return Err(bad());
+        }
+
+        return Ok(Some(Rule::Totalistic {
+            code: code,
+            range: range,
+        }));
+    }
+
+    Ok(None)
+}
+
+// Rule table names are like Golly's, letters, digits, - and _
+fn is_name(text: &str) -> bool {
+    text.chars().next().map(|c| c.is_alphabetic()).unwrap_or(false) &&
+    text.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+// Every part is a letter followed by its value, C, M and N can be left out
+fn parse_larger_than_life(text: &str) -> Result<Rule, String> {
+    let mut range = None;
+    let mut states = None;
+    let mut middle = None;
+    let mut survival = None;
+    let mut birth = None;
+    let mut neighbourhood = None;
+
+    for part in text.split(',') {
+        let part = part.trim();
+        let value = part.get(1..).unwrap_or("");
+        let bad = || format!("Bad [{}] in rule [{}]", part, text);
+
+        match part.chars().next() {
+            Some('R') if range.is_none() => {
+                let r = try!(value.parse::<u32>().map_err(|_| bad()));
+                if r < 1 || r > MAX_RANGE {
+                    return Err(format!("Range of rule [{}] should be from 1 to {}",
+                                       text,
+                                       MAX_RANGE));
+                }
+                range = Some(r);
+            }
+            // C0 and C1 both mean the usual two states
+            Some('C') if states.is_none() => {
+                let count = try!(value.parse::<u8>().map_err(|_| bad()));
+                states = Some(if count < 2 { 2 } else { count });
+            }
+            Some('M') if middle.is_none() => {
+                middle = Some(match value {
+                    "0" => false,
+                    "1" => true,
+                    _ => return Err(bad()),
+                });
+            }
+            Some('S') if survival.is_none() => {
+                survival = Some(try!(parse_bounds(value).ok_or_else(bad)));
+            }
+            Some('B') if birth.is_none() => birth = Some(try!(parse_bounds(value).ok_or_else(bad))),
+            Some('N') if neighbourhood.is_none() => {
+                neighbourhood = Some(match value {
+                    "M" => Neighbourhood::Moore,
+                    "N" => Neighbourhood::VonNeumann,
+                    "C" => Neighbourhood::Circular,
+                    _ => return Err(bad()),
+                });
+            }
+            _ => return Err(format!("Unexpected or repeated [{}] in rule [{}]", part, text)),
+        }
+    }
+
+    match (range, survival, birth) {
+        (Some(range), Some(survival), Some(birth)) => {
+            Ok(Rule::LargerThanLife {
+                range: range,
+                neighbourhood: neighbourhood.unwrap_or(Neighbourhood::Moore),
+                middle: middle.unwrap_or(false),
+                survival: survival,
+                birth: birth,
+                states: states.unwrap_or(2),
+            })
+        }
+        _ => Err(format!("Rule [{}] needs range, survival and birth parts", text)),
+    }
+}
+
+// Either min..max or a single count
+fn parse_bounds(value: &str) -> Option<(u16, u16)> {
+    let mut parts = value.splitn(2, "..");
+    let min = parts.next().and_then(|m| m.parse().ok());
+    let max = match parts.next() {
+        Some(m) => m.parse().ok(),
+        None => min,
+    };
+
+    match (min, max) {
+        (Some(min), Some(max)) if min <= max => Some((min, max)),
+        _ => None,
+    }
+}
+
+fn counts(values: &[usize]) -> [bool; MAX_NEIGHBOURS + 1] {
+    let mut counts = [false; MAX_NEIGHBOURS + 1];
+    for &v in values {
+        counts[v] = true;
+    }
+
+    counts
+}
+
+fn parse_conditions(digits: &str, rule: &str) -> Result<[u16; 9], String> {
+    hensel::parse_conditions(digits).map_err(|e| format!("{} in rule [{}]", e, rule))
+}
+
+// Counts for hexagonal and triangular rules, which don't have letters
+fn parse_counts(digits: &str,
+                neighbours: u32,
+                rule: &str)
+                -> Result<[bool; MAX_NEIGHBOURS + 1], String> {
+    let mut counts = [false; MAX_NEIGHBOURS + 1];
+
+    for c in digits.chars() {
+        match c.to_digit(16) {
+            Some(d) if d <= neighbours => counts[d as usize] = true,
+            _ => return Err(format!("Bad neighbour count [{}] in rule [{}]", c, rule)),
+        }
+    }
+
+    Ok(counts)
+}
+
+fn totalistic_counts(conditions: &[u16; 9]) -> [bool; MAX_NEIGHBOURS + 1] {
+    let mut counts = [false; MAX_NEIGHBOURS + 1];
+    for (count, &mask) in conditions.iter().enumerate() {
+        counts[count] = mask != 0;
+    }
+
+    counts
+}
+
+fn format_counts(counts: &[bool; MAX_NEIGHBOURS + 1]) -> String {
+    counts.iter()
+        .enumerate()
+        .filter(|&(_, &c)| c)
+        .map(|(i, _)| format!("{:x}", i))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rule_parse_conway_notations() {
+        assert_eq!(Rule::parse("B3/S23"), Ok(Rule::conway()));
+        assert_eq!(Rule::parse("b3/s23"), Ok(Rule::conway()));
+        assert_eq!(Rule::parse("23/3"), Ok(Rule::conway()));
+    }
+
+    #[test]
+    fn rule_parse_generations() {
+        let brians_brain = Rule::parse("B2/S/C3").unwrap();
+        assert_eq!(brians_brain.get_states(), 3);
+        assert_eq!(Rule::parse("/2/3"), Ok(brians_brain));
+
+        let star_wars = Rule::parse("345/2/4").unwrap();
+        assert_eq!(star_wars.to_string(), "B2/S345/C4");
+    }
+
+    #[test]
+    fn rule_parse_rejects_bad_rules() {
+        assert!(Rule::parse("B9/S23").is_err());
+        assert!(Rule::parse("B3").is_err());
+        assert!(Rule::parse("B3/S23/C1").is_err());
+        assert!(Rule::parse("B3/B2").is_err());
+        assert!(Rule::parse("É3/S23").is_err());
+    }
+
+    #[test]
+    fn rule_display_round_trips() {
+        for text in ["B3/S23", "B36/S23", "B2/S/C3", "B2/S345/C4"].iter() {
+            assert_eq!(Rule::parse(text).unwrap().to_string(), *text);
+        }
+    }
+
+    #[test]
+    fn rule_next_state_generations_decay() {
+        let rule = Rule::parse("B2/S/C4").unwrap();
+
+        assert_eq!(rule.next_state(0, 2), 1);
+        assert_eq!(rule.next_state(0, 3), 0);
+        assert_eq!(rule.next_state(1, 2), 2);
+        assert_eq!(rule.next_state(2, 2), 3);
+        assert_eq!(rule.next_state(3, 2), 0);
+    }
+
+    #[test]
+    fn rule_parse_isotropic() {
+        let rule = Rule::parse("B2-a/S12").unwrap();
+
+        assert!(rule.is_isotropic());
+        assert_eq!(rule.to_string(), "B2-a/S12");
+        assert_eq!(Rule::parse("b3/s2-i34q").unwrap().to_string(), "B3/S2-i34q");
+        assert_eq!(Rule::parse("B3cekainyqjr/S23"), Ok(Rule::conway()));
+        assert!(Rule::parse("B2x/S12").is_err());
+    }
+
+    #[test]
+    fn rule_next_state_isotropic() {
+        let rule = Rule::parse("B2-a/S12").unwrap();
+
+        // Top left and top middle are adjacent (2a), top left and top right aren't (2c)
+        assert_eq!(rule.next_state_from_configuration(0, 0b000000011), 0);
+        assert_eq!(rule.next_state_from_configuration(0, 0b000000101), 1);
+        assert_eq!(rule.next_state_from_configuration(1, 0b000010011), 1);
+        assert_eq!(rule.next_state_from_configuration(1, 0b000010111), 0);
+        assert_eq!(Rule::conway().next_state_from_configuration(0, 0b000000111), 1);
+    }
+
+    #[test]
+    fn rule_parse_colours() {
+        let immigration = Rule::parse("Immigration").unwrap();
+        let quad_life = Rule::parse("quadlife").unwrap();
+
+        assert_eq!(immigration.get_species(), 2);
+        assert_eq!(immigration.get_states(), 3);
+        assert!(immigration.is_alive(2));
+        assert!(!Rule::parse("B2/S/C3").unwrap().is_alive(2));
+        assert_eq!(immigration.to_string(), "Immigration");
+        assert_eq!(quad_life.to_string(), "QuadLife");
+    }
+
+    #[test]
+    fn rule_parse_table() {
+        let wireworld = Rule::parse("wireworld").unwrap();
+
+        assert!(wireworld.is_table());
+        assert_eq!(wireworld.get_states(), 4);
+        assert_eq!(wireworld.to_string(), "WireWorld");
+        assert_eq!(wireworld.get_neighbourhood(0, 0).len(), 8);
+        assert_eq!(wireworld.next_state_from_neighbours(3, &[1, 1, 0, 0, 0, 0, 0, 0]), 1);
+        assert!(Rule::parse("NoSuchRule").is_err());
+    }
+
+    #[test]
+    fn rule_parse_one_dimensional() {
+        let rule_30 = Rule::parse("W30").unwrap();
+        let totalistic = Rule::parse("t10r2").unwrap();
+
+        assert!(rule_30.is_one_dimensional());
+        assert_eq!(rule_30.to_string(), "W30");
+        assert_eq!(totalistic.to_string(), "T10R2");
+        assert_eq!(totalistic.get_range(), 2);
+        assert_eq!(totalistic.get_neighbourhood(0, 0), vec![(-2, 0), (-1, 0), (1, 0), (2, 0)]);
+        assert!(Rule::parse("W256").is_err());
+        assert!(Rule::parse("T64R2").is_err());
+        assert!(Rule::parse("T1R0").is_err());
+    }
+
+    #[test]
+    fn rule_next_state_one_dimensional() {
+        let rule_30 = Rule::parse("W30").unwrap();
+        let totalistic = Rule::parse("T10R1").unwrap();
+
+        // 30 is 00011110, so 100, 011, 010 and 001 give a live cell
+        assert_eq!(rule_30.next_state_from_neighbours(0, &[1, 0]), 1);
+        assert_eq!(rule_30.next_state_from_neighbours(1, &[1, 0]), 0);
+        assert_eq!(rule_30.next_state_from_neighbours(1, &[0, 1]), 1);
+        assert_eq!(rule_30.next_state_from_neighbours(0, &[0, 0]), 0);
+        // 10 is 1010, totals of 1 and 3
+        assert_eq!(totalistic.next_state(0, 1), 1);
+        assert_eq!(totalistic.next_state(1, 1), 0);
+        assert_eq!(totalistic.next_state(1, 2), 1);
+    }
+
+    #[test]
+    fn rule_parse_stochastic() {
+        let rule = Rule::parse("B3/S23;survival=0.95;birth=0.5;seed=7").unwrap();
+
+        assert_eq!(rule.to_string(), "B3/S23;birth=0.5;survival=0.95;seed=7");
+        assert_eq!(rule.get_range(), 1);
+        assert_eq!(Rule::parse("B2/S34H;update=0.25").unwrap().get_tiling(), Tiling::Hexagonal);
+        assert!(Rule::parse("B3/S23;birth=2").is_err());
+        assert!(Rule::parse("B3/S23;death=0.5").is_err());
+        assert!(Rule::parse("WireWorld;birth=0.5").is_err());
+    }
+
+    #[test]
+    fn rule_next_state_by_chance() {
+        let never_born = Rule::parse("B3/S23;birth=0").unwrap();
+        let never_updated = Rule::parse("B2/S/C3;update=0").unwrap();
+        let never_survives = Rule::parse("B2/S1/C3;survival=0").unwrap();
+
+        assert_eq!(never_born.next_state(0, 3), 1);
+        assert_eq!(never_born.next_state_by_chance(0, 1, 4, 5, 6), 0);
+        assert_eq!(never_born.next_state_by_chance(1, 1, 4, 5, 6), 1);
+        assert_eq!(never_updated.next_state_by_chance(1, 2, 4, 5, 6), 1);
+        assert_eq!(never_survives.next_state_by_chance(1, 1, 4, 5, 6), 2);
+        assert_eq!(Rule::conway().next_state_by_chance(0, 1, 4, 5, 6), 1);
+    }
+
+    #[test]
+    fn rule_parse_margolus() {
+        let billiard_balls = Rule::parse("BBM").unwrap();
+
+        assert!(billiard_balls.is_margolus());
+        assert_eq!(billiard_balls.to_string(), "M0,8,4,3,2,5,9,7,1,6,10,11,12,13,14,15");
+        assert_eq!(Rule::parse(&billiard_balls.to_string()), Ok(billiard_balls.clone()));
+        assert_eq!(billiard_balls.is_reversible(), Some(true));
+        assert_eq!(Rule::parse("Critters").unwrap().is_reversible(), Some(true));
+        assert_eq!(Rule::parse("M0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,15").unwrap().is_reversible(),
+                   Some(false));
+        assert_eq!(Rule::conway().is_reversible(), None);
+        assert!(Rule::parse("M0,1,2").is_err());
+        assert!(Rule::parse("M0,1,2,3,4,5,6,7,8,9,10,11,12,13,14,16").is_err());
+
+        // A single ball crosses to the opposite corner
+        assert_eq!(billiard_balls.next_state_from_block(1, 3), 1);
+        assert_eq!(billiard_balls.next_state_from_block(1, 0), 0);
+    }
+
+    #[test]
+    fn rule_parse_three_dimensional() {
+        let rule = Rule::parse("3D4,5,5,5").unwrap();
+
+        assert!(rule.is_three_dimensional());
+        assert_eq!(rule.to_string(), "3D4,5,5,5");
+        assert_eq!(rule.get_depth(), DEFAULT_DEPTH);
+        assert_eq!(rule.get_range(), DEFAULT_DEPTH + 1);
+        assert_eq!(Rule::parse("3d4,5,5,5,d8"), Ok(rule.clone()));
+        assert_eq!(Rule::parse("3D10,21,5,6,D12").unwrap().to_string(), "3D10,21,5,6,D12");
+        // Bays' four digits are the same rules
+        assert_eq!(Rule::parse("4555"), Ok(rule.clone()));
+        assert_eq!(Rule::parse("5766").unwrap().to_string(), "3D5,7,6,6");
+        assert!(Rule::parse("5466").is_err());
+        assert!(Rule::parse("3D5,4,5,5").is_err());
+        assert!(Rule::parse("3D1,2,3").is_err());
+        assert!(Rule::parse("3D4,5,5,5,D0").is_err());
+        assert!(Rule::parse("3D4,5,5,5,D50").is_err());
+
+        assert_eq!(rule.next_state(0, 5), 1);
+        assert_eq!(rule.next_state(0, 4), 0);
+        assert_eq!(rule.next_state(1, 4), 1);
+        assert_eq!(rule.next_state(1, 6), 0);
+    }
+
+    #[test]
+    fn rule_three_dimensional_neighbourhood_stays_in_the_space() {
+        let rule = Rule::parse("3D4,5,5,5,D4").unwrap();
+
+        assert_eq!(rule.get_neighbourhood_period(), (4, 1));
+        assert_eq!(space_column(2, 3, 4), 12);
+        assert_eq!(space_position(12, 4), Some((2, 3)));
+        assert_eq!(space_position(0, 4), None);
+        // z of 1 and 2 have cells both ways, 0 and 3 only one way
+        for column in 0..8 {
+            let neighbourhood = rule.get_neighbourhood(column, 0);
+            let z = space_position(column + 4, 4).unwrap().1;
+
+            assert_eq!(neighbourhood.len(), if z == 0 || z == 3 { 17 } else { 26 });
+            assert!(neighbourhood.iter().all(|&(dx, _)| dx.abs() <= 5));
+        }
+        assert!(rule.get_neighbourhood(1, 0).contains(&(1, 0)));
+        assert!(!rule.get_neighbourhood(1, 0).contains(&(-1, 0)));
+    }
+
+    #[test]
+    fn rule_next_state_colours() {
+        let immigration = Rule::parse("Immigration").unwrap();
+        let quad_life = Rule::parse("QuadLife").unwrap();
+
+        assert_eq!(immigration.next_state_from_species(0, &[0, 1, 2, 0, 0]), 2);
+        assert_eq!(immigration.next_state_from_species(0, &[0, 2, 2, 0, 0]), 0);
+        assert_eq!(immigration.next_state_from_species(1, &[0, 0, 2, 0, 0]), 1);
+        assert_eq!(immigration.next_state_from_species(2, &[0, 1, 0, 0, 0]), 0);
+        assert_eq!(quad_life.next_state_from_species(0, &[0, 0, 1, 2, 0]), 3);
+        assert_eq!(quad_life.next_state_from_species(0, &[0, 1, 1, 0, 1]), 3);
+    }
+
+    #[test]
+    fn rule_parse_hexagonal_and_triangular() {
+        let hexagonal = Rule::parse("B2/S34H").unwrap();
+        assert_eq!(hexagonal.get_tiling(), Tiling::Hexagonal);
+        assert_eq!(hexagonal.to_string(), "B2/S34H");
+        assert_eq!(Rule::parse("B2/S/C3H").unwrap().to_string(), "B2/S/C3H");
+
+        let triangular = Rule::parse("B4a/S3456L").unwrap();
+        assert_eq!(triangular.get_tiling(), Tiling::Triangular);
+        assert_eq!(triangular.get_range(), 2);
+        assert_eq!(triangular.next_state(0, 10), 1);
+        assert_eq!(triangular.to_string(), "B4a/S3456L");
+
+        assert!(Rule::parse("B7/S2H").is_err());
+        assert!(Rule::parse("B2-a/S2H").is_err());
+        assert!(Rule::parse("B3/S23").unwrap().get_tiling() == Tiling::Square);
+    }
+
+    #[test]
+    fn rule_hexagonal_and_triangular_neighbourhoods() {
+        let hexagonal = Rule::parse("B2/S34H").unwrap();
+        assert_eq!(hexagonal.get_neighbourhood(0, 0),
+                   vec![(-1, 0), (1, 0), (-1, -1), (0, -1), (-1, 1), (0, 1)]);
+        assert_eq!(hexagonal.get_neighbourhood(0, 1),
+                   vec![(-1, 0), (1, 0), (0, -1), (1, -1), (0, 1), (1, 1)]);
+
+        let triangular = Rule::parse("B4/S345L").unwrap();
+        let up = triangular.get_neighbourhood(0, 0);
+        let down = triangular.get_neighbourhood(1, 0);
+        assert_eq!(up.len(), 12);
+        assert_eq!(up.iter().filter(|&&(_, dy)| dy == 1).count(), 5);
+        assert_eq!(down.iter().filter(|&&(_, dy)| dy == -1).count(), 5);
+        assert!(!up.contains(&(0, 0)));
+    }
+
+    #[test]
+    fn rule_parse_larger_than_life() {
+        let bosco = Rule::parse("R5,C0,M1,S34..58,B34..45,NM").unwrap();
+
+        assert_eq!(bosco,
+                   Rule::LargerThanLife {
+                       range: 5,
+                       neighbourhood: Neighbourhood::Moore,
+                       middle: true,
+                       survival: (34, 58),
+                       birth: (34, 45),
+                       states: 2,
+                   });
+        assert_eq!(bosco.get_range(), 5);
+        assert_eq!(bosco.to_string(), "R5,C0,M1,S34..58,B34..45,NM");
+        assert_eq!(Rule::parse("R2,C3,M0,S5,B4..6,NC").unwrap().to_string(),
+                   "R2,C3,M0,S5..5,B4..6,NC");
+        assert!(Rule::parse("R0,C0,M0,S1..2,B3..3,NM").is_err());
+        assert!(Rule::parse("R2,C0,M0,S3..1,B3..3,NM").is_err());
+        assert!(Rule::parse("R2,C0,M0,S1..2,NM").is_err());
+    }
+
+    #[test]
+    fn rule_neighbourhood_sizes() {
+        let size = |text: &str| Rule::parse(text).unwrap().get_neighbourhood(0, 0).len();
+
+        assert_eq!(Rule::conway().get_neighbourhood(0, 0).len(), 8);
+        assert_eq!(size("R5,C0,M1,S34..58,B34..45,NM"), 121);
+        assert_eq!(size("R2,C0,M0,S1..2,B3..3,NN"), 12);
+        assert_eq!(size("R2,C0,M0,S1..2,B3..3,NC"), 20);
+    }
+
+    #[test]
+    fn rule_next_state_larger_than_life() {
+        let rule = Rule::parse("R2,C3,M0,S5..7,B4..4,NM").unwrap();
+
+        assert_eq!(rule.next_state(0, 4), 1);
+        assert_eq!(rule.next_state(0, 5), 0);
+        assert_eq!(rule.next_state(1, 7), 1);
+        assert_eq!(rule.next_state(1, 8), 2);
+        assert_eq!(rule.next_state(2, 5), 0);
+    }
+}
@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use census;
+use evolve;
+use predecessor;
+use rle::RlePattern;
+use rule::Rule;
+
+// How many cells the search tries setting before giving up, unless told otherwise
+pub const DEFAULT_MAX_NODES: u64 = 100000000;
+// Cells whose next state depends on more unset cells than this aren't checked yet
+const MAX_UNKNOWN: usize = 5;
+
+// Which cells have to be the same as which others, in every generation
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Symmetry {
+    None,
+    // Left to right, so across the middle column
+    MirrorX,
+    // Top to bottom
+    MirrorY,
+    // Half a turn about the middle
+    Rotate180,
+}
+
+impl Symmetry {
+    pub fn parse(text: &str) -> Result<Symmetry, String> {
+        match text {
+            "none" => Ok(Symmetry::None),
+            "mirror-x" => Ok(Symmetry::MirrorX),
+            "mirror-y" => Ok(Symmetry::MirrorY),
+            "rotate-180" => Ok(Symmetry::Rotate180),
+            _ => Err(format!("Unknown symmetry [{}], expected none, mirror-x, mirror-y or \
+                              rotate-180",
+                             text)),
+        }
+    }
+
+    // The cell and the ones it has to match in a box of the size
+    fn images(&self, x: i64, y: i64, width: i64, height: i64) -> Vec<(i64, i64)> {
+        let other = match *self {
+            Symmetry::None => (x, y),
+            Symmetry::MirrorX => (width - 1 - x, y),
+            Symmetry::MirrorY => (x, height - 1 - y),
+            Symmetry::Rotate180 => (width - 1 - x, height - 1 - y),
+        };
+
+        if other == (x, y) { vec![(x, y)] } else { vec![(x, y), other] }
+    }
+}
+
+// What a search found, each object once by its apgcode in the first phase it was found in
+#[derive(Clone, Debug)]
+pub struct SearchReport {
+    found: Vec<(String, RlePattern)>,
+    complete: bool,
+    nodes: u64,
+}
+
+impl SearchReport {
+    pub fn get_found(&self) -> &[(String, RlePattern)] {
+        &self.found
+    }
+
+    // Whether every way of setting the cells was ruled out or found, rather than the search
+    // stopping at the most results or nodes
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    pub fn get_nodes(&self) -> u64 {
+        self.nodes
+    }
+}
+
+// Looks for oscillators and spaceships in the manner of lifesrc. Every cell in the box in every
+// generation of the period is a variable, cells outside the box are dead, and after the period
+// the cells have to be back as they were moved by the displacement. Cells are set a row at a
+// time, dead first, backing up as soon as a cell can't become what the next generation says it
+// is whatever the cells still unset around it are.
+#[derive(Clone, Debug)]
+pub struct ObjectSearch {
+    rule: Rule,
+    width: u32,
+    height: u32,
+    period: usize,
+    displacement: (i64, i64),
+    symmetry: Symmetry,
+}
+
+impl ObjectSearch {
+    // Oscillators of the period, unless a displacement is given
+    pub fn new(rule: Rule, width: u32, height: u32, period: usize) -> ObjectSearch {
+        ObjectSearch {
+            rule: rule,
+            width: width,
+            height: height,
+            period: period,
+            displacement: (0, 0),
+            symmetry: Symmetry::None,
+        }
+    }
+
+    // Spaceships that move this far right and down each period
+    pub fn with_displacement(mut self, dx: i64, dy: i64) -> ObjectSearch {
+        self.displacement = (dx, dy);
+        self
+    }
+
+    pub fn with_symmetry(mut self, symmetry: Symmetry) -> ObjectSearch {
+        self.symmetry = symmetry;
+        self
+    }
+
+    pub fn run(&self, max_results: usize, max_nodes: u64) -> Result<SearchReport, String> {
+        let table = try!(predecessor::transitions(&self.rule));
+        try!(evolve::check(&self.rule));
+        if self.period == 0 {
+            return Err("Period has to be at least 1".to_string());
+        }
+
+        let (width, height) = (self.width as i64, self.height as i64);
+        let mut variables = Vec::new();
+        let mut seen = HashSet::new();
+        for y in 0..height {
+            for x in 0..width {
+                if seen.insert((x, y)) {
+                    let images = self.symmetry.images(x, y, width, height);
+                    seen.extend(images.iter().cloned());
+                    variables.push(images);
+                }
+            }
+        }
+
+        let mut search = Search {
+            search: self,
+            table: &table,
+            variables: variables,
+            cells: vec![None; (width * height) as usize * self.period],
+            found: Vec::new(),
+            apgcodes: HashSet::new(),
+            max_results: max_results,
+            nodes: 0,
+            max_nodes: max_nodes,
+        };
+        let complete = search.extend(0);
+
+        Ok(SearchReport {
+            found: search.found,
+            complete: complete,
+            nodes: search.nodes,
+        })
+    }
+}
+
+struct Search<'a> {
+    search: &'a ObjectSearch,
+    table: &'a [bool],
+    // The cells set together, one set for each variable
+    variables: Vec<Vec<(i64, i64)>>,
+    cells: Vec<Option<bool>>,
+    found: Vec<(String, RlePattern)>,
+    apgcodes: HashSet<String>,
+    max_results: usize,
+    nodes: u64,
+    max_nodes: u64,
+}
+
+impl<'a> Search<'a> {
+    fn index(&self, x: i64, y: i64, generation: usize) -> usize {
+        (y * self.search.width as i64 + x) as usize * self.search.period + generation
+    }
+
+    // Outside the box is dead, and the generation after the last is the first moved on
+    fn get(&self, x: i64, y: i64, generation: usize) -> Option<bool> {
+        let (x, y, generation) = if generation == self.search.period {
+            (x - self.search.displacement.0, y - self.search.displacement.1, 0)
+        } else {
+            (x, y, generation)
+        };
+
+        if x < 0 || y < 0 || x >= self.search.width as i64 || y >= self.search.height as i64 {
+            Some(false)
+        } else {
+            self.cells[self.index(x, y, generation)]
+        }
+    }
+
+    // Whether the cells around can still give the cell what it is next generation
+    fn allows(&self, x: i64, y: i64, generation: usize) -> bool {
+        let next = match self.get(x, y, generation + 1) {
+            Some(next) => next,
+            None => return true,
+        };
+
+        let mut known = 0;
+        let mut unknown = Vec::new();
+        for bit in 0..9 {
+            match self.get(x + bit as i64 % 3 - 1, y + bit as i64 / 3 - 1, generation) {
+                Some(true) => known |= 1 << bit,
+                Some(false) => {}
+                None => unknown.push(bit),
+            }
+        }
+        if unknown.len() > MAX_UNKNOWN {
+            return true;
+        }
+
+        (0..1 << unknown.len()).any(|choice: usize| {
+            let configuration = unknown.iter()
+                .enumerate()
+                .filter(|&(i, _)| choice & (1 << i) != 0)
+                .fold(known, |configuration, (_, bit)| configuration | 1 << bit);
+
+            self.table[configuration] == next
+        })
+    }
+
+    // Every cell whose next state the cell just set has a part in
+    fn consistent(&self, x: i64, y: i64, generation: usize) -> bool {
+        let period = self.search.period;
+        let (dx, dy) = self.search.displacement;
+
+        for cy in y - 1..y + 2 {
+            for cx in x - 1..x + 2 {
+                if !self.allows(cx, cy, generation) {
+                    return false;
+                }
+            }
+        }
+
+        // It is the next state of the cell itself in the generation before
+        if generation > 0 {
+            self.allows(x, y, generation - 1)
+        } else {
+            self.allows(x + dx, y + dy, period - 1)
+        }
+    }
+
+    // Takes a full set of cells if it is what was asked for, and not a smaller period
+    fn record(&mut self) {
+        let rule = &self.search.rule;
+        let mut cells = HashMap::new();
+        for y in 0..self.search.height as i64 {
+            for x in 0..self.search.width as i64 {
+                if self.get(x, y, 0) == Some(true) {
+                    cells.insert((x, y), 1);
+                }
+            }
+        }
+
+        // The rule was checked before the search started
+        let period = census::period_of(rule, &cells, 0).unwrap_or(None);
+        if cells.is_empty() || period != Some((self.search.period, self.search.displacement)) {
+            return;
+        }
+
+        let apgcode = census::apgcode(rule, &cells, 0)
+            .unwrap_or_else(|_| census::PATHOLOGICAL.to_string());
+        if self.apgcodes.insert(apgcode.clone()) {
+            let pattern = RlePattern::new(self.search.width,
+                                          self.search.height,
+                                          cells.into_iter()
+                                              .map(|((x, y), state)| ((x as u32, y as u32), state))
+                                              .collect(),
+                                          Some(rule.clone()));
+
+            self.found.push((apgcode, pattern));
+        }
+    }
+
+    // Whether everything from here was tried, false if it stopped early with some left to try.
+    // Having found the most results only stops it when there is another cell to set, so a search
+    // whose last result was the last thing to try is still complete.
+    fn extend(&mut self, variable: usize) -> bool {
+        let period = self.search.period;
+        if variable == self.variables.len() * period {
+            self.record();
+            return true;
+        }
+
+        let images = self.variables[variable / period].clone();
+        let generation = variable % period;
+
+        for &alive in [false, true].iter() {
+            if self.found.len() >= self.max_results {
+                return false;
+            }
+            self.nodes += 1;
+            if self.nodes > self.max_nodes {
+                return false;
+            }
+
+            for &(x, y) in &images {
+                let index = self.index(x, y, generation);
+                self.cells[index] = Some(alive);
+            }
+            if images.iter().all(|&(x, y)| self.consistent(x, y, generation)) &&
+               !self.extend(variable + 1) {
+                return false;
+            }
+        }
+
+        for &(x, y) in &images {
+            let index = self.index(x, y, generation);
+            self.cells[index] = None;
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rule::Rule;
+    use super::*;
+
+    fn apgcodes(report: &SearchReport) -> Vec<&str> {
+        report.get_found().iter().map(|&(ref apgcode, _)| apgcode.as_ref()).collect()
+    }
+
+    #[test]
+    fn object_search_finds_blinker_and_glider() {
+        let blinkers = ObjectSearch::new(Rule::conway(), 3, 3, 2)
+            .with_symmetry(Symmetry::MirrorX)
+            .run(10, DEFAULT_MAX_NODES)
+            .unwrap();
+        assert_eq!(apgcodes(&blinkers), vec!["xp2_7"]);
+        assert!(blinkers.is_complete());
+
+        let gliders = ObjectSearch::new(Rule::conway(), 4, 4, 4)
+            .with_displacement(1, 1)
+            .run(1, DEFAULT_MAX_NODES)
+            .unwrap();
+        assert_eq!(apgcodes(&gliders), vec!["xq4_153"]);
+        let (_, ref glider) = gliders.get_found()[0];
+        assert_eq!(glider.get_cells().len(), 5);
+    }
+
+    #[test]
+    fn object_search_rules_out_too_small_box() {
+        let report = ObjectSearch::new(Rule::conway(), 3, 3, 3).run(1, DEFAULT_MAX_NODES).unwrap();
+
+        assert!(report.get_found().is_empty());
+        assert!(report.is_complete());
+        // The block is all the cells alive, the last thing tried, so finding it finishes too
+        let blocks = ObjectSearch::new(Rule::conway(), 2, 2, 1).run(1, DEFAULT_MAX_NODES).unwrap();
+        assert_eq!(apgcodes(&blocks), vec!["xs4_33"]);
+        assert!(blocks.is_complete());
+        let blinkers = ObjectSearch::new(Rule::conway(), 3, 3, 2).run(1, DEFAULT_MAX_NODES);
+        assert!(!blinkers.unwrap().is_complete());
+        assert!(ObjectSearch::new(Rule::conway(), 3, 3, 0).run(1, 10).is_err());
+        assert_eq!(Symmetry::parse("rotate-180"), Ok(Symmetry::Rotate180));
+    }
+}
@@ -8,25 +8,43 @@ use std::sync::mpsc::Sender;
 use std::sync::mpsc::TryRecvError;
 use std::sync::mpsc::TrySendError;
 use std::sync::Arc;
+use std::cmp;
+use std::mem;
+use std::time::Instant;
 
 use board::Cell;
 use board::Board;
+use snapshot::SectionSnapshot;
+use snapshot::SnapshotRecorder;
+use metrics::SectionMetrics;
+use population::SectionPopulation;
+
+#[derive(Clone)]
+pub enum HaloMessage {
+    Cells(Arc<Vec<Cell>>),
+    // Snapshot marker, everything sent before it on the channel is before the snapshot
+    Marker(usize),
+}
 
 pub struct CellStateCallback {
     id: (usize, usize),
-    sender: SyncSender<Arc<Vec<Cell>>>,
+    sender: SyncSender<HaloMessage>,
 }
 
 impl CellStateCallback {
-    pub fn new(id: (usize, usize), sender: SyncSender<Arc<Vec<Cell>>>) -> CellStateCallback {
+    pub fn new(id: (usize, usize), sender: SyncSender<HaloMessage>) -> CellStateCallback {
         CellStateCallback {
             id: id,
             sender: sender,
         }
     }
 
-    pub fn try_send(&self, cells: Arc<Vec<Cell>>) -> Result<(), TrySendError<Arc<Vec<Cell>>>> {
-        self.sender.try_send(cells)
+    pub fn get_id(&self) -> (usize, usize) {
+        self.id
+    }
+
+    pub fn try_send(&self, message: HaloMessage) -> Result<(), TrySendError<HaloMessage>> {
+        self.sender.try_send(message)
     }
 }
 
@@ -48,7 +66,7 @@ impl PartialEq for CellStateCallback {
 
 impl Eq for CellStateCallback {}
 
-#[derive(PartialEq, Eq, Hash, Copy, Clone)]
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
 pub enum BoardSectionSide {
     Top,
     Bottom,
@@ -60,9 +78,23 @@ pub trait BoardSection {
     fn subscribe(&mut self, side: BoardSectionSide, callback: CellStateCallback);
     fn unsubscribe(&mut self, side: BoardSectionSide, callback: &CellStateCallback);
 
-    fn add_receiver(&mut self, side: BoardSectionSide, rx: Receiver<Arc<Vec<Cell>>>);
+    fn add_receiver(&mut self, side: BoardSectionSide, rx: Receiver<HaloMessage>);
     fn try_iteration(&mut self, upto_iteration: usize);
     fn get_board(&self) -> &Board;
+
+    // Asks the section to hold its cells at the generation until it has captured them, returns
+    // false if the section has already gone past the generation
+    fn request_checkpoint(&mut self, generation: usize) -> bool;
+    // Lets the cells go on past a requested checkpoint without capturing it
+    fn cancel_checkpoint(&mut self);
+    fn take_checkpoint(&mut self) -> Option<Board>;
+
+    // Records the section's state and sends markers to its neighbours, they then do the same
+    fn start_snapshot(&mut self, id: usize);
+    fn take_snapshot(&mut self) -> Option<SectionSnapshot>;
+
+    fn get_metrics(&self) -> &SectionMetrics;
+    fn get_population(&self) -> &SectionPopulation;
 }
 
 pub struct LocalBoardSection {
@@ -70,16 +102,27 @@ pub struct LocalBoardSection {
 
     subscribes: HashMap<BoardSectionSide, HashSet<CellStateCallback>>,
 
-    receivers: HashMap<BoardSectionSide, Receiver<Arc<Vec<Cell>>>>,
+    receivers: HashMap<BoardSectionSide, Receiver<HaloMessage>>,
 
     state_sender_registerer: Receiver<Sender<Box<[Box<[Cell]>]>>>,
 
     state_senders: Vec<Sender<Box<[Box<[Cell]>]>>>,
+
+    checkpoint_generation: Option<usize>,
+
+    checkpoint: Option<Board>,
+
+    snapshot: SnapshotRecorder,
+
+    metrics: SectionMetrics,
+
+    population: SectionPopulation,
 }
 
 impl LocalBoardSection {
     pub fn create(board: Board) -> (LocalBoardSection, Sender<Sender<Box<[Box<[Cell]>]>>>) {
         let (tx, rx) = channel();
+        let population = SectionPopulation::new(&board);
 
         let section = LocalBoardSection {
             board: board,
@@ -87,44 +130,200 @@ impl LocalBoardSection {
             receivers: HashMap::new(),
             state_sender_registerer: rx,
             state_senders: Vec::new(),
+            checkpoint_generation: None,
+            checkpoint: None,
+            snapshot: SnapshotRecorder::new(),
+            metrics: SectionMetrics::new(),
+            population: population,
         };
 
         (section, tx)
     }
 
-    fn update(board: &mut Board, side: BoardSectionSide, cells: Arc<Vec<Cell>>) {
-        // TODO: actual implementation to update state
+    // The cells are the halo rows (or columns) for the side, nearest the outside edge first. Top
+    // and bottom can have just the rows nearest the section's own cells, see Board::get_halo_rows.
+    pub fn update(board: &mut Board, side: BoardSectionSide, cells: Arc<Vec<Cell>>) {
         // TODO: check the cells array has right length?
+        let width = board.get_width();
+        let height = board.get_height();
+        let skipped = board.get_halo().saturating_sub(cells.len() as u32 / width);
+
         match side {
             BoardSectionSide::Top => {
-                for (x, &update_cell) in cells.iter().enumerate() {
-                    let stable_coord = 0;
+                for (i, &update_cell) in cells.iter().enumerate() {
+                    let i = i as u32;
 
-                    board.set_cell(x as u32, stable_coord, update_cell);
+                    board.set_cell(i % width, skipped + i / width, update_cell);
                 }
             }
             BoardSectionSide::Bottom => {
-                for (x, &update_cell) in cells.iter().enumerate() {
-                    let stable_coord = board.get_height() - 1;
+                for (i, &update_cell) in cells.iter().enumerate() {
+                    let i = i as u32;
 
-                    board.set_cell(x as u32, stable_coord, update_cell);
+                    board.set_cell(i % width, height - 1 - skipped - i / width, update_cell);
                 }
             }
             BoardSectionSide::Left => {
-                for (y, &update_cell) in cells.iter().enumerate() {
-                    let stable_coord = 0;
+                for (i, &update_cell) in cells.iter().enumerate() {
+                    let i = i as u32;
 
-                    board.set_cell(stable_coord, y as u32, update_cell);
+                    board.set_cell(i / height, i % height, update_cell);
                 }
             }
             BoardSectionSide::Right => {
-                for (y, &update_cell) in cells.iter().enumerate() {
-                    let stable_coord = board.get_width() - 1;
+                for (i, &update_cell) in cells.iter().enumerate() {
+                    let i = i as u32;
+
+                    board.set_cell(width - 1 - i / height, i % height, update_cell);
+                }
+            }
+        }
+    }
 
-                    board.set_cell(stable_coord, y as u32, update_cell);
+    // The cells to send to the neighbour on the side, our own cells that are its halo in the
+    // order update expects them. Only the rows the rule reaches go up and down.
+    fn halo_cells(board: &Board, side: BoardSectionSide) -> Arc<Vec<Cell>> {
+        let halo = board.get_halo();
+        let rows = board.get_halo_rows();
+        let width = board.get_width();
+        let height = board.get_height();
+        let mut cells = Vec::with_capacity((halo * cmp::max(width, height)) as usize);
+
+        for i in 0..halo {
+            match side {
+                BoardSectionSide::Top if i < rows => {
+                    for x in 0..width {
+                        cells.push(*board.get_cell(x, halo + rows - 1 - i));
+                    }
+                }
+                BoardSectionSide::Bottom if i < rows => {
+                    for x in 0..width {
+                        cells.push(*board.get_cell(x, height - halo - rows + i));
+                    }
+                }
+                BoardSectionSide::Top | BoardSectionSide::Bottom => {}
+                BoardSectionSide::Left => {
+                    for y in 0..height {
+                        cells.push(*board.get_cell(2 * halo - 1 - i, y));
+                    }
+                }
+                BoardSectionSide::Right => {
+                    for y in 0..height {
+                        cells.push(*board.get_cell(width - 2 * halo + i, y));
+                    }
                 }
             }
         }
+
+        Arc::new(cells)
+    }
+
+    // Sides a neighbouring section sends us halo updates on, as opposed to the edges of the board
+    fn incoming_sides(subscribes: &HashMap<BoardSectionSide, HashSet<CellStateCallback>>)
+                      -> HashSet<BoardSectionSide> {
+        subscribes.iter().filter(|&(_, c)| !c.is_empty()).map(|(&side, _)| side).collect()
+    }
+
+    fn outgoing_callbacks(subscribes: &HashMap<BoardSectionSide, HashSet<CellStateCallback>>)
+                          -> Vec<(BoardSectionSide, (usize, usize))> {
+        let mut outgoing = Vec::new();
+        for (&side, callbacks) in subscribes.iter() {
+            for callback in callbacks {
+                outgoing.push((side, callback.get_id()));
+            }
+        }
+
+        outgoing
+    }
+
+    fn send(snapshot: &mut SnapshotRecorder,
+            metrics: &mut SectionMetrics,
+            side: BoardSectionSide,
+            callbacks: &HashSet<CellStateCallback>,
+            cells: Arc<Vec<Cell>>) {
+        let bytes = cells.len() * mem::size_of::<Cell>();
+
+        for sender in callbacks {
+            // A marker has to go before any more cells, which are dropped until it does
+            if let Some(id) = snapshot.marker_to_send(side, sender.get_id()) {
+                match sender.try_send(HaloMessage::Marker(id)) {
+                    Ok(_) => snapshot.marker_sent(side, sender.get_id()),
+                    Err(TrySendError::Full(_)) => {
+                        metrics.link(side).record_dropped();
+                        continue;
+                    }
+                    Err(TrySendError::Disconnected(_)) => {
+                        snapshot.marker_sent(side, sender.get_id());
+                        continue;
+                    }
+                }
+            }
+
+            match sender.try_send(HaloMessage::Cells(cells.clone())) {
+                Ok(_) => metrics.link(side).record_sent(bytes),
+                Err(TrySendError::Full(_)) => metrics.link(side).record_dropped(),
+                Err(TrySendError::Disconnected(_)) => {
+                    // TODO: unsubscribe
+                }
+            }
+        }
+    }
+
+    fn oldest_generation(board: &Board) -> usize {
+        let halo = board.get_halo();
+        let mut oldest = None;
+        for x in halo..board.get_width() - halo {
+            for y in halo..board.get_height() - halo {
+                let iteration = board.get_cell(x, y).get_iteration();
+                oldest = Some(oldest.map(|o| cmp::min(o, iteration)).unwrap_or(iteration));
+            }
+        }
+
+        oldest.unwrap_or(0)
+    }
+
+    // Copy of the board with every cell at the generation, or None if some cells aren't there
+    // yet. The joining cells are included so that what was in flight from the neighbours is kept.
+    fn capture(board: &Board, generation: usize) -> Option<Board> {
+        let width = board.get_width();
+        let height = board.get_height();
+        let halo = board.get_halo();
+        let unreached = halo - board.get_halo_rows();
+        let mut captured = board.clone();
+
+        for x in 0..width {
+            for y in 0..height {
+                let cell = board.get_cell(x, y);
+                let joining = x < halo || y < halo || x >= width - halo || y >= height - halo;
+
+                let state = if y < unreached || y >= height - unreached {
+                    // Never sent, see Board::get_halo_rows
+                    0
+                } else if cell.get_iteration() == generation {
+                    cell.get_state()
+                } else if joining && cell.get_iteration() == generation + 1 {
+                    cell.get_previous_state()
+                } else if joining && cell.get_iteration() > generation + 1 {
+                    // Only the outside edges of the whole board get this far ahead, they never
+                    // change so the current state is the state at the generation
+                    cell.get_state()
+                } else {
+                    return None;
+                };
+
+                let previous_state = if cell.get_iteration() == generation {
+                    cell.get_previous_state()
+                } else {
+                    // Unknown, but nothing will need it as all neighbours are at the generation
+                    0
+                };
+
+                let rule = board.get_rule();
+                captured.set_cell(x, y, Cell::for_rule(rule, state, generation, previous_state));
+            }
+        }
+
+        Some(captured)
     }
 }
 
@@ -145,7 +344,7 @@ impl BoardSection for LocalBoardSection {
         };
     }
 
-    fn add_receiver(&mut self, side: BoardSectionSide, rx: Receiver<Arc<Vec<Cell>>>) {
+    fn add_receiver(&mut self, side: BoardSectionSide, rx: Receiver<HaloMessage>) {
         match self.receivers.insert(side, rx) {
             Some(_) => {
                 panic!("Should never call multiple times, at least not for now. Maybe in future \
@@ -155,14 +354,75 @@ impl BoardSection for LocalBoardSection {
         };
     }
 
+    fn request_checkpoint(&mut self, generation: usize) -> bool {
+        let halo = self.board.get_halo();
+        for x in halo..self.board.get_width() - halo {
+            for y in halo..self.board.get_height() - halo {
+                if self.board.get_cell(x, y).get_iteration() > generation {
+                    return false;
+                }
+            }
+        }
+
+        self.checkpoint_generation = Some(generation);
+        self.checkpoint = None;
+        true
+    }
+
+    fn cancel_checkpoint(&mut self) {
+        self.checkpoint_generation = None;
+        self.checkpoint = None;
+    }
+
+    fn take_checkpoint(&mut self) -> Option<Board> {
+        self.checkpoint.take()
+    }
+
+    fn start_snapshot(&mut self, id: usize) {
+        let incoming = LocalBoardSection::incoming_sides(&self.subscribes);
+        let outgoing = LocalBoardSection::outgoing_callbacks(&self.subscribes);
+
+        self.snapshot.start(id, &self.board, incoming, outgoing);
+    }
+
+    fn take_snapshot(&mut self) -> Option<SectionSnapshot> {
+        self.snapshot.take()
+    }
+
+    fn get_metrics(&self) -> &SectionMetrics {
+        &self.metrics
+    }
+
+    fn get_population(&self) -> &SectionPopulation {
+        &self.population
+    }
+
     fn try_iteration(&mut self, upto_iteration: usize) {
+        let started = Instant::now();
+        let oldest_before = LocalBoardSection::oldest_generation(&self.board);
+
+        // Hold cells at a requested checkpoint until it has been captured
+        let upto_iteration = match self.checkpoint_generation {
+            Some(generation) => cmp::min(generation, upto_iteration),
+            None => upto_iteration,
+        };
+
         // Read updates from other sections we are subscribed to
         {
             let mut board = &mut self.board;
 
             for (side, rx) in self.receivers.iter() {
                 match rx.try_recv() {
-                    Ok(cells) => LocalBoardSection::update(board, *side, cells),
+                    Ok(HaloMessage::Cells(cells)) => {
+                        self.snapshot.on_cells(*side, &cells);
+                        LocalBoardSection::update(board, *side, cells);
+                    }
+                    Ok(HaloMessage::Marker(id)) => {
+                        let incoming = LocalBoardSection::incoming_sides(&self.subscribes);
+                        let outgoing = LocalBoardSection::outgoing_callbacks(&self.subscribes);
+
+                        self.snapshot.on_marker(id, *side, board, incoming, outgoing);
+                    }
                     Err(TryRecvError::Empty) => {}
                     Err(TryRecvError::Disconnected) => {} //TODO: do something??
                 }
@@ -171,14 +431,20 @@ impl BoardSection for LocalBoardSection {
         }
 
         // update each cell if possible, ordering is important?
-        for x in 1..self.board.get_width() - 1 {
-            for y in 1..self.board.get_height() - 1 {
+        let halo = self.board.get_halo();
+        for x in halo..self.board.get_width() - halo {
+            for y in halo..self.board.get_height() - halo {
                 let &current = self.board.get_cell(x, y);
 
                 if current.get_iteration() < upto_iteration {
                     match self.board.next_cell(x, y, &current) {
-                        Some(next) => self.board.set_cell(x, y, next),
+                        Some(next) => {
+                            self.board.set_cell(x, y, next);
+                            self.metrics.record_cell_updated();
+                            self.population.record(next.get_iteration(), x, y, next.alive);
+                        }
                         None => {
+                            self.metrics.record_cell_blocked();
                             debug!("Unable to update a cell due to old neighbours. Cell at [{}] \
                                     x and [{}] y is [{:?}]",
                                    x,
@@ -196,74 +462,31 @@ impl BoardSection for LocalBoardSection {
             }
         }
 
-        // callback subscribers
-        for callbacks in self.subscribes.get(&BoardSectionSide::Top) {
-            let mut cells = Vec::with_capacity(self.board.get_width() as usize);
-            for x in 0..self.board.get_width() {
-                cells.push(*self.board.get_cell(x, 1))
-            }
-
-            let cells = Arc::new(cells);
+        let oldest_after = LocalBoardSection::oldest_generation(&self.board);
+        self.metrics.record_generations(oldest_after - oldest_before);
 
-            for sender in callbacks {
-                match sender.try_send(cells.clone()) {
-                    Ok(_) => {}
-                    Err(TrySendError::Full(_)) => {}
-                    Err(TrySendError::Disconnected(_)) => {
-                        // TODO: unsubscribe
-                    }
-                }
+        if let Some(generation) = self.checkpoint_generation {
+            if let Some(captured) = LocalBoardSection::capture(&self.board, generation) {
+                debug!("Captured checkpoint of generation [{}]", generation);
+                self.checkpoint = Some(captured);
+                self.checkpoint_generation = None;
             }
         }
-        for callbacks in self.subscribes.get(&BoardSectionSide::Bottom) {
-            let mut cells = Vec::with_capacity(self.board.get_width() as usize);
-            for x in 0..self.board.get_width() {
-                cells.push(*self.board.get_cell(x, self.board.get_height() - 2))
-            }
-            let cells = Arc::new(cells);
 
-            for sender in callbacks {
-                match sender.try_send(cells.clone()) {
-                    Ok(_) => {}
-                    Err(TrySendError::Full(_)) => {}
-                    Err(TrySendError::Disconnected(_)) => {
-                        // TODO: unsubscribe
-                    }
-                }
-            }
-        }
-        for callbacks in self.subscribes.get(&BoardSectionSide::Left) {
-            let mut cells = Vec::with_capacity(self.board.get_height() as usize);
-            for y in 0..self.board.get_height() {
-                cells.push(*self.board.get_cell(1, y))
-            }
-            let cells = Arc::new(cells);
-
-            for sender in callbacks {
-                match sender.try_send(cells.clone()) {
-                    Ok(_) => {}
-                    Err(TrySendError::Full(_)) => {}
-                    Err(TrySendError::Disconnected(_)) => {
-                        // TODO: unsubscribe
-                    }
-                }
-            }
-        }
-        for callbacks in self.subscribes.get(&BoardSectionSide::Right) {
-            let mut cells = Vec::with_capacity(self.board.get_height() as usize);
-            for y in 0..self.board.get_height() {
-                cells.push(*self.board.get_cell(self.board.get_width() - 2, y))
-            }
-            let cells = Arc::new(cells);
-
-            for sender in callbacks {
-                match sender.try_send(cells.clone()) {
-                    Ok(_) => {}
-                    Err(TrySendError::Full(_)) => {}
-                    Err(TrySendError::Disconnected(_)) => {
-                        // TODO: unsubscribe
-                    }
-                }
+        // callback subscribers
+        for &side in [BoardSectionSide::Top,
+                      BoardSectionSide::Bottom,
+                      BoardSectionSide::Left,
+                      BoardSectionSide::Right]
+            .iter() {
+            for callbacks in self.subscribes.get(&side) {
+                let cells = LocalBoardSection::halo_cells(&self.board, side);
+
+                LocalBoardSection::send(&mut self.snapshot,
+                                        &mut self.metrics,
+                                        side,
+                                        callbacks,
+                                        cells);
             }
         }
 
@@ -310,5 +533,7 @@ impl BoardSection for LocalBoardSection {
                 }
             }
         }
+
+        self.metrics.record_time(started.elapsed());
     }
 }
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::cmp;
+use std::sync::Arc;
+use board::Board;
+use board::Cell;
+use section::BoardSectionSide;
+use section::LocalBoardSection;
+use checkpoint::Checkpoint;
+
+// What one section recorded for a snapshot, its own board when it recorded and the halo
+// messages that arrived from each neighbour after that and before the neighbour's marker
+pub struct SectionSnapshot {
+    board: Board,
+    channels: HashMap<BoardSectionSide, Vec<Arc<Vec<Cell>>>>,
+}
+
+impl SectionSnapshot {
+    pub fn get_board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn get_channel(&self, side: BoardSectionSide) -> &[Arc<Vec<Cell>>] {
+        self.channels.get(&side).map(|m| &m[..]).unwrap_or(&[])
+    }
+
+    // The board as it would be once the in flight messages had been received
+    pub fn restored_board(&self) -> Board {
+        let mut board = self.board.clone();
+
+        for (side, messages) in self.channels.iter() {
+            for cells in messages {
+                LocalBoardSection::update(&mut board, *side, cells.clone());
+            }
+        }
+
+        board
+    }
+}
+
+// Chandy-Lamport marker handling for one section. Incoming channels are the sides a neighbouring
+// section sends on, outgoing ones are each subscriber (side and callback id) we send to.
+pub struct SnapshotRecorder {
+    id: Option<usize>,
+    last_id: Option<usize>,
+    board: Option<Board>,
+    channels: HashMap<BoardSectionSide, Vec<Arc<Vec<Cell>>>>,
+    waiting: HashSet<BoardSectionSide>,
+    markers_to_send: HashSet<(BoardSectionSide, (usize, usize))>,
+    completed: Option<SectionSnapshot>,
+}
+
+impl SnapshotRecorder {
+    pub fn new() -> SnapshotRecorder {
+        SnapshotRecorder {
+            id: None,
+            last_id: None,
+            board: None,
+            channels: HashMap::new(),
+            waiting: HashSet::new(),
+            markers_to_send: HashSet::new(),
+            completed: None,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.board.is_some()
+    }
+
+    pub fn start(&mut self,
+                 id: usize,
+                 board: &Board,
+                 incoming: HashSet<BoardSectionSide>,
+                 outgoing: Vec<(BoardSectionSide, (usize, usize))>) {
+        if self.is_recording() || self.last_id.map(|last| id <= last).unwrap_or(false) {
+            debug!("Ignoring snapshot [{}], already recording or seen", id);
+            return;
+        }
+
+        debug!("Recording state for snapshot [{}]", id);
+        self.id = Some(id);
+        self.last_id = Some(id);
+        self.board = Some(board.clone());
+        self.channels = incoming.iter().map(|&side| (side, Vec::new())).collect();
+        self.waiting = incoming;
+        self.markers_to_send = outgoing.into_iter().collect();
+        self.completed = None;
+
+        self.check_complete();
+    }
+
+    pub fn on_cells(&mut self, side: BoardSectionSide, cells: &Arc<Vec<Cell>>) {
+        if self.is_recording() && self.waiting.contains(&side) {
+            self.channels.entry(side).or_insert_with(|| Vec::new()).push(cells.clone());
+        }
+    }
+
+    pub fn on_marker(&mut self,
+                     id: usize,
+                     side: BoardSectionSide,
+                     board: &Board,
+                     incoming: HashSet<BoardSectionSide>,
+                     outgoing: Vec<(BoardSectionSide, (usize, usize))>) {
+        if !self.is_recording() {
+            self.start(id, board, incoming, outgoing);
+        }
+
+        if self.id == Some(id) {
+            // Nothing more is in flight on this side for the snapshot
+            self.waiting.remove(&side);
+            self.check_complete();
+        }
+    }
+
+    // Id of the marker still to send to a subscriber, cells must not be sent to it until then
+    pub fn marker_to_send(&self,
+                          side: BoardSectionSide,
+                          callback_id: (usize, usize))
+                          -> Option<usize> {
+        if self.markers_to_send.contains(&(side, callback_id)) {
+            self.last_id
+        } else {
+            None
+        }
+    }
+
+    pub fn marker_sent(&mut self, side: BoardSectionSide, callback_id: (usize, usize)) {
+        self.markers_to_send.remove(&(side, callback_id));
+    }
+
+    pub fn take(&mut self) -> Option<SectionSnapshot> {
+        self.completed.take()
+    }
+
+    fn check_complete(&mut self) {
+        if self.waiting.is_empty() {
+            if let Some(board) = self.board.take() {
+                debug!("Finished recording snapshot [{:?}]", self.id);
+                let channels = self.channels.drain().collect();
+
+                self.completed = Some(SectionSnapshot {
+                    board: board,
+                    channels: channels,
+                });
+                self.id = None;
+            }
+        }
+    }
+}
+
+// Every section's part of the same snapshot, together a consistent cut of the whole board
+pub struct GlobalSnapshot {
+    id: usize,
+    sections: Vec<Vec<SectionSnapshot>>,
+}
+
+impl GlobalSnapshot {
+    pub fn new(id: usize, sections: Vec<Vec<SectionSnapshot>>) -> GlobalSnapshot {
+        GlobalSnapshot {
+            id: id,
+            sections: sections,
+        }
+    }
+
+    pub fn get_id(&self) -> usize {
+        self.id
+    }
+
+    pub fn get_section(&self, x: usize, y: usize) -> &SectionSnapshot {
+        &self.sections[x][y]
+    }
+
+    pub fn get_sections(&self) -> &Vec<Vec<SectionSnapshot>> {
+        &self.sections
+    }
+
+    // Cells will be at different generations, the checkpoint's generation is the furthest along
+    // any section got so the edges can be carried on from there
+    pub fn to_checkpoint(&self) -> Checkpoint {
+        let mut generation = 0;
+        let boards: Vec<Vec<Board>> = self.sections
+            .iter()
+            .map(|col| col.iter().map(|s| s.restored_board()).collect())
+            .collect();
+
+        for col in &boards {
+            for board in col {
+                let halo = board.get_halo();
+
+                for x in halo..board.get_width() - halo {
+                    for y in halo..board.get_height() - halo {
+                        generation = cmp::max(generation, board.get_cell(x, y).get_iteration());
+                    }
+                }
+            }
+        }
+
+        Checkpoint::new(generation, boards)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use board::Board;
+    use board::Cell;
+    use section::BoardSectionSide;
+    use super::*;
+
+    #[test]
+    fn recorder_keeps_messages_until_marker() {
+        let board = Board::new(3, 3, &HashMap::new());
+        let mut incoming = HashSet::new();
+        incoming.insert(BoardSectionSide::Top);
+        let outgoing = vec![(BoardSectionSide::Top, (0, 0))];
+        let mut recorder = SnapshotRecorder::new();
+
+        recorder.start(1, &board, incoming.clone(), outgoing.clone());
+        assert_eq!(recorder.marker_to_send(BoardSectionSide::Top, (0, 0)), Some(1));
+
+        let cells = Arc::new(vec![Cell::new(true, 1, false); 3]);
+        recorder.on_cells(BoardSectionSide::Top, &cells);
+        assert!(recorder.take().is_none());
+
+        recorder.on_marker(1, BoardSectionSide::Top, &board, incoming, outgoing);
+        let snapshot = recorder.take().expect("Snapshot should be complete after the marker");
+
+        assert_eq!(snapshot.get_channel(BoardSectionSide::Top).len(), 1);
+        assert_eq!(snapshot.get_board().get_cell(1, 0).alive, false);
+        assert_eq!(snapshot.restored_board().get_cell(1, 0).alive, true);
+    }
+}
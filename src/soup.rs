@@ -0,0 +1,314 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ops::Range;
+use std::sync::mpsc::channel;
+use threadpool::ThreadPool;
+use census;
+use census::Census;
+use evolve;
+use headless::HeadlessRun;
+use periodicity::PeriodDetector;
+use periodicity::DEFAULT_MAX_PERIOD;
+use random;
+use rule::Rule;
+use tracking::TrackEvent;
+use tracking::Tracker;
+use whole::Whole;
+
+// Each soup is a square of random cells this wide, as apgsearch uses
+pub const SOUP_SIZE: u32 = 16;
+// How many seeds are kept for each object, the lowest ones
+pub const MAX_SAMPLES: usize = 10;
+// What soups that haven't settled by the last generation are counted as
+pub const UNSTABLE: &'static str = "UNSTABLE";
+
+// The soup for a seed, the same every time. Half the cells are alive, each picked by the counter
+// based random numbers so no state is carried from one cell (or soup) to the next.
+pub fn soup(seed: u64, size: u32) -> HashMap<(u32, u32), u8> {
+    let mut cells = HashMap::new();
+
+    for x in 0..size {
+        for y in 0..size {
+            if random::random(seed, x, y, 0, 0) % 2 == 1 {
+                cells.insert((x, y), 1);
+            }
+        }
+    }
+
+    cells
+}
+
+// Leaves out anything within the margin of the outside edge, that is what is left of spaceships
+// that ran into it
+fn away_from_edge(cells: &HashMap<(u32, u32), u8>,
+                  size: (u32, u32),
+                  margin: u32)
+                  -> HashMap<(u32, u32), u8> {
+    let live: HashSet<(i64, i64)> = cells.keys().map(|&(x, y)| (x as i64, y as i64)).collect();
+    let mut kept = HashMap::new();
+
+    for object in census::components(&live, 2) {
+        let touches = object.iter().any(|&(x, y)| {
+            x < margin as i64 || y < margin as i64 || x + margin as i64 >= size.0 as i64 ||
+            y + margin as i64 >= size.1 as i64
+        });
+
+        if !touches {
+            for &(x, y) in &object {
+                let position = (x as u32, y as u32);
+                kept.insert(position, cells[&position]);
+            }
+        }
+    }
+
+    kept
+}
+
+// Runs soups through the sections until they settle and counts what they settle into
+#[derive(Clone, Debug)]
+pub struct SoupSearch {
+    rule: Rule,
+    section_size: u32,
+    // Sections across and down, the soup goes in the middle
+    sections: usize,
+    max_generations: usize,
+}
+
+impl SoupSearch {
+    pub fn new(rule: Rule,
+               section_size: u32,
+               sections: usize,
+               max_generations: usize)
+               -> SoupSearch {
+        SoupSearch {
+            rule: rule,
+            section_size: section_size,
+            sections: sections,
+            max_generations: max_generations,
+        }
+    }
+
+    pub fn get_rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    // Across (and down) the whole board
+    pub fn get_width(&self) -> u32 {
+        let halo = self.rule.get_range();
+
+        self.sections as u32 * (self.section_size - 2 * halo) + 2 * halo
+    }
+
+    // Census of the one soup, None if it didn't settle
+    pub fn run_soup(&self, seed: u64) -> Result<Option<Census>, String> {
+        let start = self.get_width().saturating_sub(SOUP_SIZE) / 2;
+        let states = soup(seed, SOUP_SIZE)
+            .into_iter()
+            .map(|((x, y), state)| ((start + x, start + y), state))
+            .collect();
+
+        self.settle(&states)
+    }
+
+    // Census of what the cells settle into, None if they didn't. Spaceships are counted as they
+    // leave the board rather than what they leave at the edge. Err if the rule can't run objects on
+    // their own, see evolve::check.
+    pub fn settle(&self, states: &HashMap<(u32, u32), u8>) -> Result<Option<Census>, String> {
+        let halo = self.rule.get_range();
+        let width = self.get_width();
+
+        let created = Whole::create_sections_with(self.section_size,
+                                                  self.section_size,
+                                                  self.sections,
+                                                  self.rule.clone(),
+                                                  states);
+        let mut run = HeadlessRun::new(created);
+        let mut detector = PeriodDetector::new(DEFAULT_MAX_PERIOD);
+        let mut tracker = try!(Tracker::new(run.get_whole(), self.rule.clone()));
+        let mut census = Census::new();
+        let margin = halo + 2 * self.rule.get_range() + 1;
+
+        loop {
+            let generation = run.get_generation();
+            let cells = run.get_cells();
+
+            for event in tracker.update(generation, &cells) {
+                if let TrackEvent::Left { id } = event {
+                    for ship in tracker.get_finished().iter().filter(|ship| ship.get_id() == id) {
+                        census.add(ship.get_apgcode());
+                    }
+                }
+            }
+
+            if let Some(found) = detector.add(generation, &cells) {
+                let settled = away_from_edge(&cells, (width, width), margin);
+                census.merge(&try!(Census::of_board(&self.rule, &settled, generation, &found)));
+
+                return Ok(Some(census));
+            }
+            if generation >= self.max_generations {
+                return Ok(None);
+            }
+
+            try!(run.step());
+        }
+    }
+
+    // Runs every seed in the range spread over the threads. Err if the rule can't run objects on
+    // their own, see evolve::check.
+    pub fn run(&self, seeds: Range<u64>, threads: usize) -> Result<SoupReport, String> {
+        try!(evolve::check(&self.rule));
+        let pool = ThreadPool::new(threads);
+        let (tx, rx) = channel();
+        let count = seeds.end.saturating_sub(seeds.start);
+
+        for seed in seeds {
+            let search = self.clone();
+            let tx = tx.clone();
+
+            pool.execute(move || {
+                // The rule has been checked
+                let census = search.run_soup(seed).unwrap_or(None);
+
+                if tx.send((seed, census)).is_err() {
+                    warn!("Soup search has gone, dropping soup [{}]", seed);
+                }
+            });
+        }
+
+        let mut report = SoupReport::new(self.rule.clone());
+        for (seed, census) in rx.iter().take(count as usize) {
+            report.add(seed, census.as_ref());
+        }
+
+        Ok(report)
+    }
+}
+
+// Everything the soups settled into, with the lowest seeds each object came from
+#[derive(Clone, Debug)]
+pub struct SoupReport {
+    rule: Rule,
+    soups: u64,
+    census: Census,
+    samples: BTreeMap<String, Vec<u64>>,
+}
+
+impl SoupReport {
+    pub fn new(rule: Rule) -> SoupReport {
+        SoupReport {
+            rule: rule,
+            soups: 0,
+            census: Census::new(),
+            samples: BTreeMap::new(),
+        }
+    }
+
+    fn add_sample(&mut self, apgcode: &str, seed: u64) {
+        let samples = self.samples.entry(apgcode.to_string()).or_insert_with(|| Vec::new());
+
+        if !samples.contains(&seed) {
+            samples.push(seed);
+            samples.sort();
+            samples.truncate(MAX_SAMPLES);
+        }
+    }
+
+    // A soup's census, None for one that didn't settle
+    pub fn add(&mut self, seed: u64, census: Option<&Census>) {
+        self.soups += 1;
+
+        match census {
+            Some(census) => {
+                self.census.merge(census);
+
+                for apgcode in census.get_counts().keys() {
+                    self.add_sample(apgcode, seed);
+                }
+            }
+            None => {
+                self.census.add(UNSTABLE);
+                self.add_sample(UNSTABLE, seed);
+            }
+        }
+    }
+
+    pub fn get_soups(&self) -> u64 {
+        self.soups
+    }
+
+    pub fn get_census(&self) -> &Census {
+        &self.census
+    }
+
+    pub fn get_samples(&self, apgcode: &str) -> &[u64] {
+        self.samples.get(apgcode).map(|samples| &samples[..]).unwrap_or(&[])
+    }
+
+    // Census CSV with the sample seeds split by spaces in the last column, after comment lines
+    // with the rule and how many soups there were
+    pub fn to_csv(&self) -> String {
+        let mut out = format!("# rule {}\n# soups {}\n", self.rule, self.soups);
+
+        for (i, line) in self.census.to_csv().lines().enumerate() {
+            if i == 0 {
+                out.push_str(&format!("{},samples\n", line));
+            } else {
+                let apgcode = line.split(',').next().unwrap_or("");
+                let samples: Vec<String> =
+                    self.get_samples(apgcode).iter().map(|seed| seed.to_string()).collect();
+
+                out.push_str(&format!("{},{}\n", line, samples.join(" ")));
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use census::Census;
+    use rule::Rule;
+    use super::*;
+
+    #[test]
+    fn soup_is_the_same_for_a_seed() {
+        assert_eq!(soup(7, SOUP_SIZE), soup(7, SOUP_SIZE));
+        assert!(soup(7, SOUP_SIZE) != soup(8, SOUP_SIZE));
+
+        let alive = soup(7, SOUP_SIZE).len();
+        assert!(alive > 80 && alive < 176, "[{}] alive", alive);
+    }
+
+    #[test]
+    fn soup_report_keeps_lowest_samples() {
+        let mut report = SoupReport::new(Rule::conway());
+        let mut census = Census::new();
+        census.add("xs4_33");
+        census.add("xs4_33");
+        for seed in (0..20).rev() {
+            report.add(seed, Some(&census));
+        }
+        report.add(99, None);
+
+        assert_eq!(report.get_census().get_count("xs4_33"), 40);
+        assert_eq!(report.get_samples("xs4_33"), &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(report.to_csv(),
+                   "# rule B3/S23\n# soups 21\napgcode,count,samples\nxs4_33,40,0 1 2 3 4 5 6 7 \
+                    8 9\nUNSTABLE,1,99\n");
+    }
+
+    #[test]
+    fn soup_search_runs_soups_in_parallel() {
+        let search = SoupSearch::new(Rule::conway(), 12, 3, 1000);
+        let report = search.run(0..2, 2).unwrap();
+
+        assert_eq!(report.get_soups(), 2);
+        assert!(report.get_census().get_counts().len() > 1);
+        for apgcode in report.get_census().get_counts().keys() {
+            assert!(!report.get_samples(apgcode).is_empty());
+        }
+    }
+}
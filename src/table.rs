@@ -0,0 +1,502 @@
+// Rules given as a transition table in Golly's .rule format, the @TABLE section of it. Each
+// transition lists the state of the cell, then its neighbours (N, NE, E, SE, S, SW, W, NW for
+// Moore or N, E, S, W for von Neumann) and then the state it becomes. The first transition that
+// matches is used and a cell no transition matches stays as it is.
+//
+// Variables stand for any of a set of states. A variable used more than once in a transition
+// (or as its result) has the same state everywhere in it, otherwise each use is independent.
+//
+// The transitions are compiled into a bit per transition for each position and state, a cell
+// matches the transitions whose bits are set for the states at every position.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use rule::Neighbourhood;
+
+const BLOCK_BITS: usize = 64;
+
+const WIREWORLD: &'static str = "@RULE WireWorld
+@TABLE
+# 0 empty, 1 electron head, 2 electron tail, 3 conductor
+n_states:4
+neighborhood:Moore
+symmetries:permute
+var a={0,1,2,3}
+var b={0,1,2,3}
+var c={0,1,2,3}
+var d={0,1,2,3}
+var e={0,1,2,3}
+var f={0,1,2,3}
+var g={0,1,2,3}
+var h={0,1,2,3}
+var i={0,2,3}
+var j={0,2,3}
+var k={0,2,3}
+var l={0,2,3}
+var m={0,2,3}
+var n={0,2,3}
+var o={0,2,3}
+1,a,b,c,d,e,f,g,h,2
+2,a,b,c,d,e,f,g,h,3
+3,1,i,j,k,l,m,n,o,1
+3,1,1,i,j,k,l,m,n,1
+";
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct RuleTable {
+    name: String,
+    // Where it was read from, whole so it can be read again from anywhere
+    path: Option<String>,
+    states: u8,
+    neighbourhood: Neighbourhood,
+    blocks: usize,
+    // Indexed by ((position * states) + state) * blocks + block, position 0 is the cell itself
+    lookup: Vec<u64>,
+    outputs: Vec<u8>,
+}
+
+// A transition after variables have been swapped for the states they stand for
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Transition {
+    inputs: Vec<Vec<u8>>,
+    output: u8,
+}
+
+impl RuleTable {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_path(&self) -> Option<&str> {
+        self.path.as_ref().map(|path| path.as_ref())
+    }
+
+    pub fn get_states(&self) -> u8 {
+        self.states
+    }
+
+    // Offsets of the neighbours in the order transitions list them
+    pub fn get_offsets(&self) -> Vec<(i32, i32)> {
+        match self.neighbourhood {
+            Neighbourhood::VonNeumann => vec![(0, -1), (1, 0), (0, 1), (-1, 0)],
+            _ => vec![(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1)],
+        }
+    }
+
+    pub fn next_state(&self, state: u8, neighbours: &[u8]) -> u8 {
+        let states = self.states as usize;
+
+        for block in 0..self.blocks {
+            let mut matching = self.lookup[state as usize * self.blocks + block];
+
+            for (i, &neighbour) in neighbours.iter().enumerate() {
+                if matching == 0 {
+                    break;
+                }
+                matching &= self.lookup[((i + 1) * states + neighbour as usize) * self.blocks +
+                                        block];
+            }
+
+            if matching != 0 {
+                return self.outputs[block * BLOCK_BITS + matching.trailing_zeros() as usize];
+            }
+        }
+
+        state
+    }
+}
+
+impl fmt::Debug for RuleTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RuleTable({})", self.name)
+    }
+}
+
+pub fn built_in(name: &str) -> Option<RuleTable> {
+    match name.to_lowercase().as_ref() {
+        "wireworld" => Some(parse(WIREWORLD, "WireWorld").expect("WireWorld should be valid")),
+        _ => None,
+    }
+}
+
+// Reads NAME.rule, or the path itself if it already ends in .rule
+pub fn load(name: &str) -> Result<RuleTable, String> {
+    let path = if name.ends_with(".rule") {
+        name.to_string()
+    } else {
+        format!("{}.rule", name)
+    };
+
+    let mut text = String::new();
+    try!(File::open(&path)
+        .and_then(|mut file| file.read_to_string(&mut text))
+        .map_err(|e| format!("Failed to read rule [{}]: {}", path, e)));
+
+    let default_name = Path::new(&path).file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let mut table = try!(parse(&text, default_name));
+    table.path = env::current_dir()
+        .ok()
+        .map(|directory| directory.join(&path).to_string_lossy().into_owned());
+
+    Ok(table)
+}
+
+// The name comes from @RULE if there is one
+pub fn parse(text: &str, default_name: &str) -> Result<RuleTable, String> {
+    let mut name = default_name.to_string();
+    let mut in_table = false;
+    let mut states = None;
+    let mut neighbourhood = None;
+    let mut symmetries = "none".to_string();
+    let mut variables: HashMap<String, Vec<u8>> = HashMap::new();
+    let mut transitions = Vec::new();
+
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with('@') {
+            if line.starts_with("@RULE") {
+                name = line["@RULE".len()..].trim().to_string();
+            }
+            in_table = line == "@TABLE";
+            continue;
+        }
+        if !in_table {
+            continue;
+        }
+
+        if let Some(value) = setting(line, "n_states") {
+            let count = try!(value.parse::<u16>().map_err(|_| bad(line)));
+            if count < 2 || count > 255 {
+                return Err(format!("Rule tables need 2 to 255 states, not [{}]", count));
+            }
+            states = Some(count);
+        } else if let Some(value) = setting(line, "neighborhood") {
+            neighbourhood = Some(match value {
+                "Moore" => Neighbourhood::Moore,
+                "vonNeumann" => Neighbourhood::VonNeumann,
+                _ => return Err(format!("Unsupported neighborhood [{}]", value)),
+            });
+        } else if let Some(value) = setting(line, "symmetries") {
+            symmetries = value.to_string();
+        } else if line.starts_with("var ") {
+            let states = try!(states.ok_or("n_states has to come before variables".to_string()));
+            let mut parts = line[4..].splitn(2, '=');
+            let variable = parts.next().unwrap_or("").trim().to_string();
+            let values = parts.next().unwrap_or("").trim();
+            if variable.is_empty() || !values.starts_with('{') || !values.ends_with('}') {
+                return Err(bad(line));
+            }
+
+            let mut set = Vec::new();
+            for token in values[1..values.len() - 1].split(',') {
+                set.extend(try!(token_states(token.trim(), &variables, states)));
+            }
+            set.sort();
+            set.dedup();
+            variables.insert(variable, set);
+        } else {
+            let states = try!(states.ok_or("n_states has to come before transitions".to_string()));
+            let neighbours = try!(neighbourhood.ok_or("neighborhood has to come before \
+                                                       transitions"
+                .to_string()));
+            let neighbours = if neighbours == Neighbourhood::Moore { 8 } else { 4 };
+
+            // Without commas every state is one digit
+            let tokens: Vec<String> = if line.contains(',') {
+                line.split(',').map(|t| t.trim().to_string()).collect()
+            } else {
+                line.chars().filter(|c| !c.is_whitespace()).map(|c| c.to_string()).collect()
+            };
+            if tokens.len() != neighbours + 2 {
+                return Err(format!("Transition [{}] should have {} states", line, neighbours + 2));
+            }
+
+            for transition in try!(bind(&tokens, &variables, states)) {
+                for symmetric in try!(symmetric(&transition, &symmetries, neighbours)) {
+                    transitions.push(symmetric);
+                }
+            }
+        }
+    }
+
+    let states = try!(states.ok_or("Rule table is missing n_states".to_string()));
+    let neighbourhood = try!(neighbourhood.ok_or("Rule table is missing neighborhood".to_string()));
+
+    Ok(compile(name, states as usize, neighbourhood, &transitions))
+}
+
+fn setting<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let mut parts = line.splitn(2, ':');
+
+    if parts.next().map(|n| n.trim()) == Some(name) {
+        parts.next().map(|v| v.trim())
+    } else {
+        None
+    }
+}
+
+fn bad(line: &str) -> String {
+    format!("Can't make sense of [{}] in rule table", line)
+}
+
+fn token_states(token: &str,
+                variables: &HashMap<String, Vec<u8>>,
+                states: u16)
+                -> Result<Vec<u8>, String> {
+    match variables.get(token) {
+        Some(set) => Ok(set.clone()),
+        None => {
+            match token.parse::<u16>() {
+                Ok(state) if state < states => Ok(vec![state as u8]),
+                _ => Err(format!("Unknown state or variable [{}] in rule table", token)),
+            }
+        }
+    }
+}
+
+// Variables used more than once, or as the result, are given each of their states in turn
+fn bind(tokens: &[String],
+        variables: &HashMap<String, Vec<u8>>,
+        states: u16)
+        -> Result<Vec<Transition>, String> {
+    let output = &tokens[tokens.len() - 1];
+    let inputs = &tokens[..tokens.len() - 1];
+
+    let mut bound: Vec<&String> = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let used_again = tokens[i + 1..].contains(token) || token == output;
+        if variables.contains_key(token) && used_again && !bound.contains(&token) {
+            bound.push(token);
+        }
+    }
+    if variables.contains_key(output) && !inputs.contains(output) {
+        return Err(format!("Result [{}] isn't one of the transition's inputs", output));
+    }
+
+    let mut choices: Vec<HashMap<&String, u8>> = vec![HashMap::new()];
+    for variable in bound {
+        let mut expanded = Vec::new();
+        for choice in choices {
+            for &state in &variables[variable] {
+                let mut choice = choice.clone();
+                choice.insert(variable, state);
+                expanded.push(choice);
+            }
+        }
+        choices = expanded;
+    }
+
+    let mut transitions = Vec::with_capacity(choices.len());
+    for choice in choices {
+        let mut resolved = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            resolved.push(match choice.get(token) {
+                Some(&state) => vec![state],
+                None => try!(token_states(token, variables, states)),
+            });
+        }
+
+        let output = resolved.pop().unwrap();
+        if output.len() != 1 {
+            return Err(format!("Result [{}] has to be a single state", tokens[tokens.len() - 1]));
+        }
+
+        transitions.push(Transition {
+            inputs: resolved,
+            output: output[0],
+        });
+    }
+
+    Ok(transitions)
+}
+
+// Every rearrangement of the neighbours the symmetry allows, duplicates left out
+fn symmetric(transition: &Transition,
+             symmetries: &str,
+             neighbours: usize)
+             -> Result<Vec<Transition>, String> {
+    let rearrange = |order: &[usize]| {
+        let mut inputs = vec![transition.inputs[0].clone()];
+        inputs.extend(order.iter().map(|&i| transition.inputs[i + 1].clone()));
+
+        Transition {
+            inputs: inputs,
+            output: transition.output,
+        }
+    };
+    let rotate = |step: usize| -> Vec<Vec<usize>> {
+        (0..neighbours / step)
+            .map(|r| (0..neighbours).map(|i| (i + r * step) % neighbours).collect())
+            .collect()
+    };
+    // Mirrored left to right, N and S stay where they are
+    let reflect = |orders: Vec<Vec<usize>>| -> Vec<Vec<usize>> {
+        let mut all = orders.clone();
+        for order in orders {
+            all.push((0..neighbours).map(|i| order[(neighbours - i) % neighbours]).collect());
+        }
+        all
+    };
+    // Rotating a Moore neighbourhood by 90 degrees moves each neighbour two along
+    let quarter = neighbours / 4;
+
+    let orders = match symmetries {
+        "none" => vec![(0..neighbours).collect()],
+        "rotate4" => rotate(quarter),
+        "rotate4reflect" => reflect(rotate(quarter)),
+        "rotate8" if neighbours == 8 => rotate(1),
+        "rotate8reflect" if neighbours == 8 => reflect(rotate(1)),
+        "reflect_horizontal" => reflect(vec![(0..neighbours).collect()]),
+        "permute" => return Ok(permutations(transition)),
+        _ => return Err(format!("Unsupported symmetries [{}]", symmetries)),
+    };
+
+    let mut all: Vec<Transition> = Vec::with_capacity(orders.len());
+    for order in orders {
+        let rearranged = rearrange(&order);
+        if !all.contains(&rearranged) {
+            all.push(rearranged);
+        }
+    }
+
+    Ok(all)
+}
+
+// Every distinct order of the neighbours, stepping through them in sorted order so the same
+// inputs in different places aren't repeated
+fn permutations(transition: &Transition) -> Vec<Transition> {
+    let mut neighbours = transition.inputs[1..].to_vec();
+    neighbours.sort();
+    let mut all = Vec::new();
+
+    loop {
+        let mut inputs = vec![transition.inputs[0].clone()];
+        inputs.extend(neighbours.iter().cloned());
+        all.push(Transition {
+            inputs: inputs,
+            output: transition.output,
+        });
+
+        let pivot = match (1..neighbours.len()).rev().find(|&i| neighbours[i - 1] < neighbours[i]) {
+            Some(i) => i - 1,
+            None => break,
+        };
+        let swap = (pivot + 1..neighbours.len())
+            .rev()
+            .find(|&i| neighbours[pivot] < neighbours[i])
+            .unwrap();
+        neighbours.swap(pivot, swap);
+        neighbours[pivot + 1..].reverse();
+    }
+
+    all
+}
+
+fn compile(name: String,
+           states: usize,
+           neighbourhood: Neighbourhood,
+           transitions: &[Transition])
+           -> RuleTable {
+    let positions = if neighbourhood == Neighbourhood::Moore { 9 } else { 5 };
+    let blocks = (transitions.len() + BLOCK_BITS - 1) / BLOCK_BITS;
+    let mut lookup = vec![0; positions * states * blocks];
+
+    for (t, transition) in transitions.iter().enumerate() {
+        for (position, allowed) in transition.inputs.iter().enumerate() {
+            for &state in allowed {
+                lookup[(position * states + state as usize) * blocks + t / BLOCK_BITS] |=
+                    1 << (t % BLOCK_BITS);
+            }
+        }
+    }
+
+    RuleTable {
+        name: name,
+        path: None,
+        states: states as u8,
+        neighbourhood: neighbourhood,
+        blocks: blocks,
+        lookup: lookup,
+        outputs: transitions.iter().map(|t| t.output).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use std::path::Path;
+    use std::process;
+    use super::*;
+
+    #[test]
+    fn table_wireworld() {
+        let wireworld = built_in("WireWorld").unwrap();
+
+        assert_eq!(wireworld.get_name(), "WireWorld");
+        assert_eq!(wireworld.get_states(), 4);
+        assert_eq!(wireworld.next_state(1, &[3, 0, 0, 0, 0, 0, 0, 0]), 2);
+        assert_eq!(wireworld.next_state(2, &[1, 1, 1, 0, 0, 0, 0, 0]), 3);
+        assert_eq!(wireworld.next_state(3, &[0, 0, 0, 1, 0, 0, 0, 3]), 1);
+        assert_eq!(wireworld.next_state(3, &[1, 0, 0, 1, 0, 2, 0, 3]), 1);
+        assert_eq!(wireworld.next_state(3, &[1, 0, 0, 1, 0, 1, 0, 3]), 3);
+        assert_eq!(wireworld.next_state(0, &[1, 1, 1, 0, 0, 0, 0, 0]), 0);
+    }
+
+    #[test]
+    fn table_bound_variables_and_rotation() {
+        let text = "@TABLE\nn_states:3\nneighborhood:vonNeumann\nsymmetries:rotate4\n\
+                    var a={1,2}\nvar b={0,1,2}\n0,a,a,b,0,a\n00120 2\n";
+        let table = parse(text, "Test").unwrap();
+
+        assert_eq!(table.get_name(), "Test");
+        assert_eq!(table.get_offsets().len(), 4);
+        // N and E have to be the same and the result is that state
+        assert_eq!(table.next_state(0, &[2, 2, 1, 0]), 2);
+        assert_eq!(table.next_state(0, &[0, 1, 1, 2]), 1);
+        assert_eq!(table.next_state(0, &[1, 2, 1, 0]), 0);
+        // The compact form rotated, 1 at S and 2 at W
+        assert_eq!(table.next_state(0, &[1, 2, 0, 0]), 2);
+        assert_eq!(table.next_state(0, &[2, 1, 0, 0]), 0);
+    }
+
+    #[test]
+    fn table_rejects_bad_tables() {
+        let start = "@TABLE\nn_states:2\nneighborhood:vonNeumann\n";
+
+        assert!(parse(&format!("{}0,1,0,0,1\n", start), "").is_err());
+        assert!(parse(&format!("{}0,1,0,0,2,1\n", start), "").is_err());
+        assert!(parse(&format!("{}var a={{0,1}}\n0,a,0,0,0,b\n", start), "").is_err());
+        assert!(parse(&format!("{}symmetries:rotate8\n0,1,0,0,0,1\n", start), "").is_err());
+        assert!(parse("@TABLE\nneighborhood:hexagonal\n", "").is_err());
+        assert!(parse("@TABLE\nn_states:256\n", "").is_err());
+        assert!(parse("@TABLE\nn_states:255\nneighborhood:Moore\n", "").is_ok());
+    }
+
+    #[test]
+    fn table_loaded_keeps_whole_path() {
+        let directory = env::temp_dir()
+            .join(format!("rust_game_of_life_table_test_{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let file = directory.join("Test.rule");
+        fs::File::create(&file)
+            .and_then(|mut f| f.write_all(b"@RULE Test\n@TABLE\nn_states:2\nneighborhood:Moore\n"))
+            .unwrap();
+
+        let table = load(&file.to_string_lossy()).unwrap();
+        assert_eq!(table.get_name(), "Test");
+        assert_eq!(table.get_path().map(|path| Path::new(path).is_absolute()), Some(true));
+        assert_eq!(load(table.get_path().unwrap()), Ok(table));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
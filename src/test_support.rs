@@ -0,0 +1,7 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+// Live cells at the positions, keyed however the module under test keys them
+pub fn cells<P: Copy + Eq + Hash>(positions: &[P]) -> HashMap<P, u8> {
+    positions.iter().map(|&position| (position, 1)).collect()
+}
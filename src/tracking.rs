@@ -0,0 +1,442 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::mem;
+use census;
+use evolve;
+use population::BoundingBox;
+use rule::Rule;
+use whole::Whole;
+
+// Bigger objects aren't checked for being spaceships, which keeps tracking a busy board cheap
+pub const MAX_SHIP_CELLS: usize = 64;
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+// The apgcode and velocity of the object if it is a spaceship. Most objects on a busy board aren't,
+// so rather than finding the period of each this gives up as soon as one dies or gets too big.
+fn ship_kind(rule: &Rule,
+             object: &HashMap<(i64, i64), u8>,
+             generation: usize)
+             -> Result<Option<(String, Velocity)>, String> {
+    let (start, origin) = evolve::normalise(object);
+    let mut cells = object.clone();
+
+    for period in 1..census::MAX_OBJECT_PERIOD + 1 {
+        cells = try!(evolve::step(rule, &cells, generation + period - 1));
+        if cells.is_empty() || cells.len() > MAX_SHIP_CELLS {
+            return Ok(None);
+        }
+
+        let (shape, moved_to) = evolve::normalise(&cells);
+        if shape == start {
+            if moved_to == origin {
+                return Ok(None);
+            }
+
+            let displacement = (moved_to.0 - origin.0, moved_to.1 - origin.1);
+            let apgcode = try!(census::apgcode(rule, object, generation));
+            return Ok(Some((apgcode, Velocity::new(period, displacement))));
+        }
+    }
+
+    Ok(None)
+}
+
+// How far a spaceship moves each period
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Velocity {
+    period: usize,
+    displacement: (i64, i64),
+}
+
+impl Velocity {
+    pub fn new(period: usize, displacement: (i64, i64)) -> Velocity {
+        Velocity {
+            period: period,
+            displacement: displacement,
+        }
+    }
+
+    pub fn get_period(&self) -> usize {
+        self.period
+    }
+
+    pub fn get_displacement(&self) -> (i64, i64) {
+        self.displacement
+    }
+
+    pub fn is_orthogonal(&self) -> bool {
+        (self.displacement.0 == 0) != (self.displacement.1 == 0)
+    }
+
+    pub fn is_diagonal(&self) -> bool {
+        self.displacement.0 != 0 && self.displacement.0.abs() == self.displacement.1.abs()
+    }
+}
+
+// As speeds are usually written, c/4 diagonal, c/2 orthogonal, 2c/5 orthogonal and oblique ones
+// as (2,1)c/6
+impl fmt::Display for Velocity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let x = self.displacement.0.abs() as u64;
+        let y = self.displacement.1.abs() as u64;
+        let period = self.period as u64;
+
+        if !self.is_orthogonal() && !self.is_diagonal() {
+            let (big, small) = if x > y { (x, y) } else { (y, x) };
+            return write!(f, "({},{})c/{} oblique", big, small, period);
+        }
+
+        let distance = if x > y { x } else { y };
+        let divisor = gcd(distance, period);
+        let (distance, period) = (distance / divisor, period / divisor);
+
+        if distance != 1 {
+            try!(write!(f, "{}", distance));
+        }
+        try!(write!(f, "c"));
+        if period != 1 {
+            try!(write!(f, "/{}", period));
+        }
+
+        write!(f, " {}", if self.is_diagonal() { "diagonal" } else { "orthogonal" })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TrackEvent {
+    // A spaceship was seen for the first time
+    Found {
+        id: usize,
+        apgcode: String,
+        velocity: Velocity,
+        section: (usize, usize),
+    },
+    // It has moved out of one section's own cells into another's
+    Crossed {
+        id: usize,
+        from: (usize, usize),
+        to: (usize, usize),
+    },
+    // It has gone while up against the outside edge of the board
+    Left { id: usize },
+    // It has gone somewhere else, it ran into something
+    Lost { id: usize },
+}
+
+impl fmt::Display for TrackEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TrackEvent::Found { id, ref apgcode, velocity, section } => {
+                write!(f,
+                       "Spaceship {} found, {} moving {} in section {:?}",
+                       id,
+                       apgcode,
+                       velocity,
+                       section)
+            }
+            TrackEvent::Crossed { id, from, to } => {
+                write!(f, "Spaceship {} crossed from section {:?} to {:?}", id, from, to)
+            }
+            TrackEvent::Left { id } => write!(f, "Spaceship {} left the board", id),
+            TrackEvent::Lost { id } => write!(f, "Spaceship {} was lost", id),
+        }
+    }
+}
+
+// A spaceship being followed, with where it has been each generation
+#[derive(Clone, Debug)]
+pub struct Ship {
+    id: usize,
+    apgcode: String,
+    velocity: Velocity,
+    trajectory: Vec<(usize, BoundingBox)>,
+    section: (usize, usize),
+}
+
+impl Ship {
+    pub fn get_id(&self) -> usize {
+        self.id
+    }
+
+    pub fn get_apgcode(&self) -> &str {
+        &self.apgcode
+    }
+
+    pub fn get_velocity(&self) -> Velocity {
+        self.velocity
+    }
+
+    pub fn get_trajectory(&self) -> &Vec<(usize, BoundingBox)> {
+        &self.trajectory
+    }
+
+    pub fn get_section(&self) -> (usize, usize) {
+        self.section
+    }
+}
+
+// Picks out the spaceships on each generation of the board and follows them from one generation
+// to the next, across section edges until they leave the board or hit something
+pub struct Tracker {
+    rule: Rule,
+    // Width and height of each section's own cells, and of the whole board
+    section_size: (u32, u32),
+    board_size: (u32, u32),
+    halo: u32,
+    sections: (usize, usize),
+    ships: Vec<Ship>,
+    finished: Vec<Ship>,
+    // What each shape of object turned out to be, None if it isn't a spaceship. Shapes are kept
+    // apart by where they start up to the rule's neighbourhood period and whether the generation
+    // is odd or even, as hexagonal, triangular and Margolus rules can run the same shape
+    // differently.
+    kinds: HashMap<((i64, i64, usize), Vec<((i64, i64), u8)>), Option<(String, Velocity)>>,
+    next_id: usize,
+}
+
+impl Tracker {
+    // Err if the rule can't run objects on their own, see evolve::check, or is 3D as objects are
+    // found on the flat board
+    pub fn new(whole: &Whole, rule: Rule) -> Result<Tracker, String> {
+        try!(evolve::check(&rule));
+        if rule.is_three_dimensional() {
+            return Err(format!("Rule [{}] is 3D, spaceships can only be tracked on flat boards",
+                               rule));
+        }
+        let halo = whole.halo() as u32;
+        let sections = (whole.sections_width(), whole.sections_height());
+        let board_size = (whole.columns_count() as u32, whole.rows_count() as u32);
+
+        Ok(Tracker {
+            rule: rule,
+            section_size: ((board_size.0 - 2 * halo) / sections.0 as u32,
+                           (board_size.1 - 2 * halo) / sections.1 as u32),
+            board_size: board_size,
+            halo: halo,
+            sections: sections,
+            ships: Vec::new(),
+            finished: Vec::new(),
+            kinds: HashMap::new(),
+            next_id: 0,
+        })
+    }
+
+    pub fn get_ships(&self) -> &Vec<Ship> {
+        &self.ships
+    }
+
+    // Ships that have left the board or been lost
+    pub fn get_finished(&self) -> &Vec<Ship> {
+        &self.finished
+    }
+
+    // Which section has the cell as one of its own
+    fn section_of(&self, x: u32, y: u32) -> (usize, usize) {
+        let column = x.saturating_sub(self.halo) / self.section_size.0;
+        let row = y.saturating_sub(self.halo) / self.section_size.1;
+
+        ((column as usize).min(self.sections.0 - 1), (row as usize).min(self.sections.1 - 1))
+    }
+
+    fn centre_section(&self, bounds: &BoundingBox) -> (usize, usize) {
+        self.section_of((bounds.get_min_x() + bounds.get_max_x()) / 2,
+                        (bounds.get_min_y() + bounds.get_max_y()) / 2)
+    }
+
+    fn near_edge(&self, bounds: &BoundingBox) -> bool {
+        let margin = self.halo + 2 * self.rule.get_range() + 1;
+
+        bounds.get_min_x() < margin || bounds.get_min_y() < margin ||
+        bounds.get_max_x() + margin >= self.board_size.0 ||
+        bounds.get_max_y() + margin >= self.board_size.1
+    }
+
+    fn kind(&mut self,
+            object: &HashMap<(i64, i64), u8>,
+            generation: usize)
+            -> Option<(String, Velocity)> {
+        // The rule was checked when the tracker was made
+        let rule = &self.rule;
+        // Chances go by where each cell is, so the same shape can do anything anywhere
+        if let Rule::Stochastic { .. } = *rule {
+            return ship_kind(rule, object, generation).unwrap_or(None);
+        }
+
+        let (shape, (x, y)) = evolve::normalise(object);
+        let mut cells: Vec<((i64, i64), u8)> = shape.into_iter().collect();
+        cells.sort();
+        let (columns, rows) = rule.get_neighbourhood_period();
+        let key = ((x % columns as i64, y % rows as i64, generation % 2), cells);
+
+        self.kinds
+            .entry(key)
+            .or_insert_with(|| ship_kind(rule, object, generation).unwrap_or(None))
+            .clone()
+    }
+
+    // The cells of a generation, after the one last given. Returns what happened to the ships.
+    pub fn update(&mut self,
+                  generation: usize,
+                  cells: &HashMap<(u32, u32), u8>)
+                  -> Vec<TrackEvent> {
+        let live: HashSet<(i64, i64)> = cells.iter()
+            .filter(|&(_, &state)| state != 0)
+            .map(|(&(x, y), _)| (x as i64, y as i64))
+            .collect();
+
+        let mut seen = Vec::new();
+        for object in census::components(&live, 2) {
+            if object.len() > MAX_SHIP_CELLS {
+                continue;
+            }
+
+            let object_cells: HashMap<(i64, i64), u8> = object.iter()
+                .map(|&(x, y)| ((x, y), cells[&(x as u32, y as u32)]))
+                .collect();
+            if let Some((apgcode, velocity)) = self.kind(&object_cells, generation) {
+                let mut bounds: Option<BoundingBox> = None;
+                for &(x, y) in &object {
+                    match bounds {
+                        Some(ref mut b) => b.include(x as u32, y as u32),
+                        None => bounds = Some(BoundingBox::new(x as u32, y as u32)),
+                    }
+                }
+
+                seen.push((apgcode, velocity, bounds.unwrap()));
+            }
+        }
+
+        let mut events = Vec::new();
+        let mut following = Vec::new();
+        let range = self.rule.get_range();
+
+        for (apgcode, velocity, bounds) in seen {
+            // The nearest ship of the same kind that could have got here since it was last seen
+            let nearest = self.ships
+                .iter()
+                .enumerate()
+                .filter(|&(_, ship)| ship.apgcode == apgcode)
+                .filter_map(|(i, ship)| {
+                    let &(last_generation, last) = ship.trajectory.last().unwrap();
+                    let distance = (last.get_min_x() as i64 - bounds.get_min_x() as i64)
+                        .abs()
+                        .max((last.get_min_y() as i64 - bounds.get_min_y() as i64).abs());
+                    let reach = (range as usize * (generation - last_generation) + 2) as i64;
+
+                    if distance <= reach { Some((distance, i)) } else { None }
+                })
+                .min();
+
+            let section = self.centre_section(&bounds);
+            match nearest {
+                Some((_, i)) => {
+                    let mut ship = self.ships.remove(i);
+
+                    if ship.section != section {
+                        events.push(TrackEvent::Crossed {
+                            id: ship.id,
+                            from: ship.section,
+                            to: section,
+                        });
+                        ship.section = section;
+                    }
+                    ship.trajectory.push((generation, bounds));
+                    following.push(ship);
+                }
+                None => {
+                    events.push(TrackEvent::Found {
+                        id: self.next_id,
+                        apgcode: apgcode.clone(),
+                        velocity: velocity,
+                        section: section,
+                    });
+                    following.push(Ship {
+                        id: self.next_id,
+                        apgcode: apgcode,
+                        velocity: velocity,
+                        trajectory: vec![(generation, bounds)],
+                        section: section,
+                    });
+                    self.next_id += 1;
+                }
+            }
+        }
+
+        // Whatever wasn't seen again is gone
+        let gone = mem::replace(&mut self.ships, following);
+        for ship in gone {
+            let &(_, last) = ship.trajectory.last().unwrap();
+
+            events.push(if self.near_edge(&last) {
+                TrackEvent::Left { id: ship.id }
+            } else {
+                TrackEvent::Lost { id: ship.id }
+            });
+            self.finished.push(ship);
+        }
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use headless::HeadlessRun;
+    use rule::Rule;
+    use whole::Whole;
+    use super::*;
+
+    #[test]
+    fn velocity_to_string() {
+        assert_eq!(Velocity::new(4, (1, 1)).to_string(), "c/4 diagonal");
+        assert_eq!(Velocity::new(4, (-2, 0)).to_string(), "c/2 orthogonal");
+        assert_eq!(Velocity::new(5, (0, 2)).to_string(), "2c/5 orthogonal");
+        assert_eq!(Velocity::new(6, (1, -2)).to_string(), "(2,1)c/6 oblique");
+    }
+
+    #[test]
+    fn tracker_follows_glider_across_sections_and_off_the_board() {
+        let mut states = HashMap::new();
+        for &position in [(2, 1), (3, 2), (1, 3), (2, 3), (3, 3)].iter() {
+            states.insert(position, 1);
+        }
+        let created = Whole::create_sections_with(8, 8, 2, Rule::conway(), &states);
+        let mut run = HeadlessRun::new(created);
+        let mut tracker = Tracker::new(run.get_whole(), Rule::conway()).unwrap();
+        let mut events = tracker.update(0, &run.get_cells());
+
+        while run.get_generation() < 40 {
+            run.step().unwrap();
+            events.extend(tracker.update(run.get_generation(), &run.get_cells()));
+        }
+
+        assert_eq!(events[0],
+                   TrackEvent::Found {
+                       id: 0,
+                       apgcode: "xq4_153".to_string(),
+                       velocity: Velocity::new(4, (1, 1)),
+                       section: (0, 0),
+                   });
+        // Going diagonally it may clip the corner of another section on the way
+        assert!(events.iter().any(|event| match *event {
+            TrackEvent::Crossed { id: 0, to: (1, 1), .. } => true,
+            _ => false,
+        }));
+        assert_eq!(events.last(), Some(&TrackEvent::Left { id: 0 }));
+        assert!(tracker.get_ships().is_empty());
+    }
+
+    #[test]
+    fn tracker_rejects_rules_it_cant_follow_ships_in() {
+        let created = Whole::create_sections_with(8, 8, 2, Rule::conway(), &HashMap::new());
+        let run = HeadlessRun::new(created);
+
+        assert!(Tracker::new(run.get_whole(), Rule::parse("3D4,5,5,5").unwrap()).is_err());
+        assert!(Tracker::new(run.get_whole(), Rule::parse("B0/S8").unwrap()).is_err());
+    }
+}
@@ -4,6 +4,7 @@ use std::sync::mpsc::TryRecvError;
 use std::sync::mpsc::channel;
 use std::cmp;
 use board::Cell;
+use section::BoardSectionSide;
 
 #[derive(Copy, Clone)]
 pub struct Rectangle {
@@ -38,6 +39,29 @@ impl Rectangle {
     pub fn get_end_y(&self) -> u32 {
         self.start_y + self.height
     }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x >= self.get_start_x() && x < self.get_end_x() && y >= self.get_start_y() &&
+        y < self.get_end_y()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LinkStatus {
+    // Outside edge of the whole board, fed by the driver rather than a section
+    Edge,
+    // Nothing has been received from the section yet
+    Unknown,
+    // How many generations the joining cells from the neighbour are behind our own edge
+    Behind(usize),
 }
 
 struct ViewReceiver {
@@ -48,15 +72,18 @@ struct ViewReceiver {
 
 pub struct BoardView {
 	view: Rectangle,
+	// How wide the joining cells around each section are
+	halo: u32,
 	part_receivers: Vec<ViewReceiver>,
 }
 
 impl BoardView {
-	pub fn new(view: Rectangle, registerers: Box<[(Rectangle, Sender<Sender<Box<[Box<[Cell]>]>>>)]>) -> BoardView {
+	pub fn new(view: Rectangle, halo: u32, registerers: Box<[(Rectangle, Sender<Sender<Box<[Box<[Cell]>]>>>)]>) -> BoardView {
 		let part_receivers = BoardView::create_links(registerers);
 		
 		BoardView {
 			view: view,
+			halo: halo,
 			part_receivers: part_receivers,
 		}
 	}
@@ -120,6 +147,140 @@ impl BoardView {
 		}
 	}
 	
+	pub fn get_halo(&self) -> u32 {
+		self.halo
+	}
+	
+	pub fn get_section_areas(&self) -> Vec<Rectangle> {
+		self.part_receivers.iter().map(|p| p.covered).collect()
+	}
+	
+	// Section whose own (non joining) cells include the cell, or failing that any section covering it
+	pub fn find_section(&self, x: u32, y: u32) -> Option<usize> {
+		let halo = self.halo;
+		let owner = self.part_receivers.iter().position(|p| {
+			let c = p.covered;
+			x >= c.get_start_x() + halo && x + halo < c.get_end_x() &&
+				y >= c.get_start_y() + halo && y + halo < c.get_end_y()
+		});
+		
+		owner.or_else(|| self.part_receivers.iter().position(|p| p.covered.contains(x, y)))
+	}
+	
+	// Oldest generation of the section's own cells
+	pub fn get_section_generation(&self, index: usize) -> Option<usize> {
+		self.part_receivers[index].last_known.as_ref().and_then(|cells| {
+			let width = cells.len();
+			let height = cells.get(0).map(|c| c.len()).unwrap_or(0);
+			
+			let halo = self.halo as usize;
+			let mut oldest = None;
+			for i in halo..width.checked_sub(halo).unwrap_or(0) {
+				for j in halo..height.checked_sub(halo).unwrap_or(0) {
+					let iteration = cells[i][j].get_iteration();
+					oldest = Some(oldest.map(|o| cmp::min(o, iteration)).unwrap_or(iteration));
+				}
+			}
+			
+			oldest
+		})
+	}
+	
+	fn has_neighbour(&self, index: usize, side: BoardSectionSide) -> bool {
+		let covered = self.part_receivers[index].covered;
+		let overlap = 2 * self.halo;
+		
+		self.part_receivers.iter().any(|p| {
+			let other = p.covered;
+			match side {
+				BoardSectionSide::Top => other.get_start_x() == covered.get_start_x() &&
+					other.get_end_y().checked_sub(overlap) == Some(covered.get_start_y()),
+				BoardSectionSide::Bottom => other.get_start_x() == covered.get_start_x() &&
+					covered.get_end_y().checked_sub(overlap) == Some(other.get_start_y()),
+				BoardSectionSide::Left => other.get_start_y() == covered.get_start_y() &&
+					other.get_end_x().checked_sub(overlap) == Some(covered.get_start_x()),
+				BoardSectionSide::Right => other.get_start_y() == covered.get_start_y() &&
+					covered.get_end_x().checked_sub(overlap) == Some(other.get_start_x()),
+			}
+		})
+	}
+	
+	pub fn get_link_status(&self, index: usize, side: BoardSectionSide) -> LinkStatus {
+		if !self.has_neighbour(index, side) {
+			return LinkStatus::Edge;
+		}
+		
+		let cells = match self.part_receivers[index].last_known {
+			Some(ref cells) => cells,
+			None => return LinkStatus::Unknown,
+		};
+		let width = cells.len();
+		let height = cells.get(0).map(|c| c.len()).unwrap_or(0);
+		let h = self.halo as usize;
+		if width < 2 * h + 1 || height < 2 * h + 1 {
+			return LinkStatus::Unknown;
+		}
+		
+		// Pairs of (joining cell, our cell next to it) along the side
+		let pairs: Vec<(Cell, Cell)> = match side {
+			BoardSectionSide::Top => (h..width - h).map(|i| (cells[i][h - 1], cells[i][h])).collect(),
+			BoardSectionSide::Bottom => (h..width - h).map(|i| (cells[i][height - h], cells[i][height - h - 1])).collect(),
+			BoardSectionSide::Left => (h..height - h).map(|j| (cells[h - 1][j], cells[h][j])).collect(),
+			BoardSectionSide::Right => (h..height - h).map(|j| (cells[width - h][j], cells[width - h - 1][j])).collect(),
+		};
+		
+		let behind = pairs.iter()
+			.map(|&(joining, own)| own.get_iteration().checked_sub(joining.get_iteration()).unwrap_or(0))
+			.max()
+			.unwrap_or(0);
+		
+		LinkStatus::Behind(behind)
+	}
+	
+	// Text for the cell under the mouse, which section it belongs to and how it and its links are doing
+	pub fn describe_cell(&self, x: u32, y: u32) -> Option<String> {
+		self.find_section(x, y).map(|index| {
+			let part = &self.part_receivers[index];
+			let covered = part.covered;
+			let cell = part.last_known.as_ref().map(|cells| {
+				cells[(x - covered.get_start_x()) as usize][(y - covered.get_start_y()) as usize]
+			});
+			
+			let generation = match self.get_section_generation(index) {
+				Some(g) => g.to_string(),
+				None => "unknown".to_string(),
+			};
+			let cell_generation = match cell {
+				Some(c) => c.get_iteration().to_string(),
+				None => "unknown".to_string(),
+			};
+			
+			let sides = [("top", BoardSectionSide::Top),
+			             ("bottom", BoardSectionSide::Bottom),
+			             ("left", BoardSectionSide::Left),
+			             ("right", BoardSectionSide::Right)];
+			let links: Vec<String> = sides.iter()
+				.map(|&(name, side)| {
+					let status = match self.get_link_status(index, side) {
+						LinkStatus::Edge => "edge".to_string(),
+						LinkStatus::Unknown => "unknown".to_string(),
+						LinkStatus::Behind(0) | LinkStatus::Behind(1) => "ok".to_string(),
+						LinkStatus::Behind(n) => format!("{} behind", n),
+					};
+					format!("{} {}", name, status)
+				})
+				.collect();
+			
+			format!("Cell ({}, {}) generation {}, section {} generation {}, links {}",
+			        x,
+			        y,
+			        cell_generation,
+			        index,
+			        generation,
+			        links.join(", "))
+		})
+	}
+	
 	fn foreach_cell_in_view(&self, part: (Rectangle, &Option<Box<[Box<[Cell]>]>>), callback: &mut FnMut(Option<Cell>, u32, u32)) {
 		let covered = part.0;
 		let cells: &Option<Box<[Box<[Cell]>]>> = part.1;
@@ -140,7 +301,3 @@ impl BoardView {
 		}
 	}
 }
-
-
-
-
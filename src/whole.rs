@@ -1,22 +1,41 @@
 use section::*;
 use board::Board;
 use board::Cell;
-use std::sync::Arc;
 use std::cmp;
 use std::sync::mpsc::Sender;
 use std::sync::mpsc::SyncSender;
 use std::sync::mpsc;
 use std::collections::HashMap;
 use view::Rectangle;
+use checkpoint::Checkpoint;
+use snapshot::GlobalSnapshot;
+use snapshot::SectionSnapshot;
+use metrics::Metrics;
+use population::Population;
+use population::PopulationSeries;
+use rule::Rule;
+use rule::space_column;
+use library;
+use canonical;
+use canonical::Invariance;
+use library::Placement;
 
 pub struct Whole {
     sections: Vec<Vec<Box<BoardSection>>>,
+    checkpoint: Option<(usize, Vec<Vec<Option<Board>>>)>,
+    snapshot: Option<(usize, Vec<Vec<Option<SectionSnapshot>>>)>,
+    next_snapshot_id: usize,
+    population: PopulationSeries,
 }
 
 impl Whole {
 	pub fn new(sections: Vec<Vec<Box<BoardSection>>>) -> Whole {
 		Whole {
-			sections: sections
+			sections: sections,
+			checkpoint: None,
+			snapshot: None,
+			next_snapshot_id: 0,
+			population: PopulationSeries::new(),
 		}
 	}
 	
@@ -25,47 +44,178 @@ impl Whole {
                            whole_size: usize)
                            -> (
                            	Vec<Vec<Box<BoardSection>>>,
-                           	HashMap<BoardSectionSide, Vec<SyncSender<Arc<Vec<Cell>>>>>,
+                           	HashMap<BoardSectionSide, Vec<SyncSender<HaloMessage>>>,
                            	Box<[(Rectangle, Sender<Sender<Box<[Box<[Cell]>]>>>)]>
    ) {
-        let (mut sections, registerers) = Whole::create_sections_sub(section_height, section_width, whole_size);
+        let rule = Rule::conway();
+        let glider = library::built_in("glider").expect("Glider should be built in");
+        let states = library::place(&rule, &glider, &Placement::at(3, 3))
+            .expect("Glider should fit on the board");
+
+        Whole::create_sections_with(section_width,
+                                    section_height,
+                                    whole_size,
+                                    rule,
+                                    &states)
+    }
+
+    // The states are keyed by position on the whole board
+    pub fn create_sections_with(section_width: u32,
+                                section_height: u32,
+                                whole_size: usize,
+                                rule: Rule,
+                                states: &HashMap<(u32, u32), u8>)
+                                -> (
+                           	Vec<Vec<Box<BoardSection>>>,
+                           	HashMap<BoardSectionSide, Vec<SyncSender<HaloMessage>>>,
+                           	Box<[(Rectangle, Sender<Sender<Box<[Box<[Cell]>]>>>)]>
+   ) {
+        let (mut sections, registerers) =
+            Whole::create_sections_sub(section_width, section_height, whole_size, rule, states);
+        Whole::connect_sections(&mut sections);
+
+        let edge_senders = Whole::create_edge_senders(&mut sections);
+
+        (sections, edge_senders, registerers)
+    }
+
+    // Sections of a 3D rule's space, laid out on the board as rule::space_column says. Each
+    // section has the columns of x and rows of y of its own, all the way through z, so the
+    // states are keyed by x, y and z in the space with the halo counted in, as for
+    // create_sections_with.
+    pub fn create_space_sections(section_columns: u32,
+                                 section_rows: u32,
+                                 whole_size: usize,
+                                 rule: Rule,
+                                 states: &HashMap<(u32, u32, u32), u8>)
+                                 -> (
+                           	Vec<Vec<Box<BoardSection>>>,
+                           	HashMap<BoardSectionSide, Vec<SyncSender<HaloMessage>>>,
+                           	Box<[(Rectangle, Sender<Sender<Box<[Box<[Cell]>]>>>)]>
+   ) {
+        let depth = rule.get_depth();
+        let overlap = 2 * rule.get_range();
+        let states = states.iter()
+            .map(|(&(x, y, z), &state)| ((space_column(x, z, depth), y), state))
+            .collect();
+
+        Whole::create_sections_with(section_columns * depth + overlap,
+                                    section_rows + overlap,
+                                    whole_size,
+                                    rule,
+                                    &states)
+    }
+
+    // A single row of sections for one dimensional rules, the states are keyed by position along
+    // the line. Each section only has one row of its own between the halos.
+    pub fn create_line_sections(section_width: u32,
+                                sections_count: usize,
+                                rule: Rule,
+                                states: &HashMap<u32, u8>)
+                                -> (
+                           	Vec<Vec<Box<BoardSection>>>,
+                           	HashMap<BoardSectionSide, Vec<SyncSender<HaloMessage>>>,
+                           	Box<[(Rectangle, Sender<Sender<Box<[Box<[Cell]>]>>>)]>
+   ) {
+        let halo = rule.get_range();
+        let overlap = 2 * halo;
+        let mut boards = Vec::with_capacity(sections_count);
+
+        for x in 0..sections_count {
+            let start_x = (x as u32) * (section_width - overlap);
+
+            let mut section_states = HashMap::new();
+            for (&sx, &state) in states.iter() {
+                if sx >= start_x && sx < start_x + section_width {
+                    section_states.insert((sx - start_x, halo), state);
+                }
+            }
+
+            let mut board = Board::with_states(section_width,
+                                               1 + overlap,
+                                               &section_states,
+                                               rule.clone());
+            board.set_origin(start_x, 0);
+            boards.push(vec![board]);
+        }
+
+        let (mut sections, registerers) = Whole::create_sections_from_boards(boards);
+        Whole::connect_sections(&mut sections);
+
+        let edge_senders = Whole::create_edge_senders(&mut sections);
+
+        (sections, edge_senders, registerers)
+    }
+
+    // The sections are laid out where the checkpoint's boards say they were
+    pub fn restore_sections(checkpoint: &Checkpoint)
+                            -> (
+                           	Vec<Vec<Box<BoardSection>>>,
+                           	HashMap<BoardSectionSide, Vec<SyncSender<HaloMessage>>>,
+                           	Box<[(Rectangle, Sender<Sender<Box<[Box<[Cell]>]>>>)]>
+   ) {
+        let boards = checkpoint.get_boards().clone();
+        let (mut sections, registerers) = Whole::create_sections_from_boards(boards);
         Whole::connect_sections(&mut sections);
 
-        let edge_senders = Whole::create_edge_senders(whole_size, &mut sections);
+        let edge_senders = Whole::create_edge_senders(&mut sections);
 
         (sections, edge_senders, registerers)
     }
 
     fn create_sections_sub(section_width: u32,
                            section_height: u32,
-                           whole_size: usize)
+                           whole_size: usize,
+                           rule: Rule,
+                           states: &HashMap<(u32, u32), u8>)
                            -> (Vec<Vec<Box<BoardSection>>>, Box<[(Rectangle, Sender<Sender<Box<[Box<[Cell]>]>>>)]>) {
-       	let mut registerers = Vec::with_capacity(whole_size * whole_size);
-        let mut rows = Vec::with_capacity(whole_size);
+        let mut boards = Vec::with_capacity(whole_size);
+        let overlap = 2 * rule.get_range();
 
         for x in 0..whole_size {
-            let mut col: Vec<Box<BoardSection>> = Vec::with_capacity(whole_size);
+            let mut col = Vec::with_capacity(whole_size);
 
             for y in 0..whole_size {
+                let start_x = (x as u32) * (section_width - overlap);
+                let start_y = (y as u32) * (section_height - overlap);
+
+                // Joining cells are in both sections so get the state too
+                let mut section_states = HashMap::new();
+                for (&(sx, sy), &state) in states.iter() {
+                    if sx >= start_x && sx < start_x + section_width && sy >= start_y &&
+                       sy < start_y + section_height {
+                        section_states.insert((sx - start_x, sy - start_y), state);
+                    }
+                }
 
-                let mut alives = HashMap::new();
+                let mut board = Board::with_states(section_width,
+                                                   section_height,
+                                                   &section_states,
+                                                   rule.clone());
+                board.set_origin(start_x, start_y);
+                col.push(board);
+            }
 
-                if x == 0 && y == 0 {
-                    // Glider
-                    alives.insert((3, 5), true);
-                    alives.insert((4, 5), true);
-                    alives.insert((5, 5), true);
-                    alives.insert((5, 4), true);
-                    alives.insert((4, 3), true);
-                }
+            boards.push(col);
+        }
+
+        Whole::create_sections_from_boards(boards)
+    }
+
+    // Each board already has its origin on the whole board
+    fn create_sections_from_boards(boards: Vec<Vec<Board>>)
+                                   -> (Vec<Vec<Box<BoardSection>>>, Box<[(Rectangle, Sender<Sender<Box<[Box<[Cell]>]>>>)]>) {
+       	let mut registerers = Vec::new();
+        let mut rows = Vec::with_capacity(boards.len());
 
-                let board = Board::new(section_width, section_height, &alives);
+        for board_col in boards {
+            let mut col: Vec<Box<BoardSection>> = Vec::with_capacity(board_col.len());
+
+            for board in board_col {
+                let (start_x, start_y) = board.get_origin();
+                let area = Rectangle::new(start_x, start_y, board.get_width(), board.get_height());
                 let (section, registerer) = LocalBoardSection::create(board);
                 
-                let start_x = (x as u32) * (section_width - 2);
-                let start_y = (y as u32) * (section_height - 2);
-                let area = Rectangle::new(start_x, start_y, section_width, section_height);
-                
                 registerers.push((area, registerer));
                 col.push(Box::new(section));
             }
@@ -76,55 +226,56 @@ impl Whole {
         (rows, registerers.into_boxed_slice())
     }
 
-    fn create_edge_senders(whole_size: usize,
-                           sections: &mut Vec<Vec<Box<BoardSection>>>)
-                           -> HashMap<BoardSectionSide, Vec<SyncSender<Arc<Vec<Cell>>>>> {
-        let edges_count = whole_size * 4;
-        let max_section_index = whole_size - 1;
-        let mut senders = HashMap::with_capacity(edges_count);
-        for x in 0..whole_size {
+    fn create_edge_senders(sections: &mut Vec<Vec<Box<BoardSection>>>)
+                           -> HashMap<BoardSectionSide, Vec<SyncSender<HaloMessage>>> {
+        let sections_width = sections.len();
+        let sections_height = sections.get(0).map(|c| c.len()).unwrap_or(0);
+        let max_x = sections_width - 1;
+        let max_y = sections_height - 1;
+        let mut senders = HashMap::with_capacity(4);
+        for x in 0..sections_width {
             // Top edge
             {
                 let section = &mut sections[x][0];
                 let sender = Whole::create_sender(BoardSectionSide::Top, section);
 
                 let c = senders.entry(BoardSectionSide::Top)
-                    .or_insert_with(|| Vec::with_capacity(whole_size));
+                    .or_insert_with(|| Vec::with_capacity(sections_width));
 
                 c.push(sender);
             }
 
             // Bottom edge
             {
-                let section = &mut sections[x][max_section_index];
+                let section = &mut sections[x][max_y];
                 let sender = Whole::create_sender(BoardSectionSide::Bottom, section);
 
                 let c = senders.entry(BoardSectionSide::Bottom)
-                    .or_insert_with(|| Vec::with_capacity(whole_size));
+                    .or_insert_with(|| Vec::with_capacity(sections_width));
 
                 c.push(sender);
             }
         }
 
-        for y in 0..whole_size {
+        for y in 0..sections_height {
             // Left edge
             {
                 let section = &mut sections[0][y];
                 let sender = Whole::create_sender(BoardSectionSide::Left, section);
 
                 let c = senders.entry(BoardSectionSide::Left)
-                    .or_insert_with(|| Vec::with_capacity(whole_size));
+                    .or_insert_with(|| Vec::with_capacity(sections_height));
 
                 c.push(sender);
             }
 
             // Right edge
             {
-                let section = &mut sections[max_section_index][y];
+                let section = &mut sections[max_x][y];
                 let sender = Whole::create_sender(BoardSectionSide::Right, section);
 
                 let c = senders.entry(BoardSectionSide::Right)
-                    .or_insert_with(|| Vec::with_capacity(whole_size));
+                    .or_insert_with(|| Vec::with_capacity(sections_height));
 
                 c.push(sender);
             }
@@ -135,7 +286,7 @@ impl Whole {
 
     fn create_sender(side: BoardSectionSide,
                      section: &mut Box<BoardSection>)
-                     -> SyncSender<Arc<Vec<Cell>>> {
+                     -> SyncSender<HaloMessage> {
         let (tx, rx) = mpsc::sync_channel(1);
 
         section.add_receiver(side, rx);
@@ -203,46 +354,56 @@ impl Whole {
         self.sections.get(0).map(|c| c.len()).unwrap_or(0)
     }
 
+    // How wide the joining cells between sections are
+    pub fn halo(&self) -> usize {
+        self.sections
+            .get(0)
+            .and_then(|c| c.get(0).map(|s| s.get_board().get_halo() as usize))
+            .unwrap_or(1)
+    }
+
     pub fn columns_count(&self) -> usize {
+        let overlap = 2 * self.halo();
         let section_width = self.sections
             .get(0)
             .and_then(|c| {
                 c.get(0).map(|s| {
                     let raw_width = s.get_board().get_width() as usize;
 
-                    // Take off 2 for the joining columns
-                    cmp::max(0, raw_width - 2)
+                    // Take off the joining columns
+                    raw_width.checked_sub(overlap).unwrap_or(0)
                 })
             })
             .unwrap_or(0);
 
         let raw_width = self.sections.len() * section_width;
 
-        // Add 2 for the far left/right columns
+        // Add the far left/right columns
         if raw_width > 0 {
-            raw_width + 2
+            raw_width + overlap
         } else {
             0
         }
     }
 
     pub fn rows_count(&self) -> usize {
+        let overlap = 2 * self.halo();
         let section_height = self.sections
             .get(0)
             .and_then(|c| {
                 c.get(0).map(|s| {
                     let raw_height = s.get_board().get_height() as usize;
 
-                    // Take off 2 for the joining rows
-                    cmp::max(0, raw_height - 2)
+                    // Take off the joining rows
+                    raw_height.checked_sub(overlap).unwrap_or(0)
                 })
             })
             .unwrap_or(0);
 
         let raw_height = self.sections.get(0).map(|c| c.len()).unwrap_or(0) * section_height;
-        // Add 2 for the top and bottom rows
+        // Add the top and bottom rows
         if raw_height > 0 {
-            raw_height + 2
+            raw_height + overlap
         } else {
             0
         }
@@ -252,19 +413,20 @@ impl Whole {
         for (sx, col) in self.sections.iter().enumerate() {
             for (sy, sec) in col.iter().enumerate() {
                 let b = sec.get_board();
+                let overlap = 2 * b.get_halo();
 
-                let offset_x = (sx as u32) * (b.get_width() - 2);
-                let offset_y = (sy as u32) * (b.get_height() - 2);
+                let offset_x = (sx as u32) * (b.get_width() - overlap);
+                let offset_y = (sy as u32) * (b.get_height() - overlap);
 
                 let start_x = if sx == 0 {
                     0
                 } else {
-                    2
+                    overlap
                 };
                 let start_y = if sy == 0 {
                     0
                 } else {
-                    2
+                    overlap
                 };
 
                 for x in start_x..b.get_width() {
@@ -281,4 +443,259 @@ impl Whole {
     pub fn get_section(&mut self, x: usize, y: usize) -> &mut Box<BoardSection> {
         &mut self.sections[x][y]
     }
+
+    // Asks every section to capture itself at a generation none of them have passed yet, the
+    // sections keep running up to that generation so no section waits on the others. Err if a
+    // section has gone past it anyway, none of the sections are held then.
+    pub fn request_checkpoint(&mut self) -> Result<usize, String> {
+        let mut generation = 0;
+        for col in &self.sections {
+            for section in col {
+                let board = section.get_board();
+                let halo = board.get_halo();
+
+                for x in halo..board.get_width() - halo {
+                    for y in halo..board.get_height() - halo {
+                        generation = cmp::max(generation, board.get_cell(x, y).get_iteration());
+                    }
+                }
+            }
+        }
+
+        let mut pending = Vec::with_capacity(self.sections.len());
+        for x in 0..self.sections.len() {
+            let mut pending_col = Vec::with_capacity(self.sections[x].len());
+
+            for y in 0..self.sections[x].len() {
+                if !self.sections[x][y].request_checkpoint(generation) {
+                    for section in self.sections.iter_mut().flat_map(|col| col.iter_mut()) {
+                        section.cancel_checkpoint();
+                    }
+
+                    return Err(format!("Section [{}, {}] is already past generation [{}], not \
+                                        taking a checkpoint",
+                                       x,
+                                       y,
+                                       generation));
+                }
+
+                pending_col.push(None);
+            }
+
+            pending.push(pending_col);
+        }
+
+        debug!("Requested checkpoint of generation [{}]", generation);
+        self.checkpoint = Some((generation, pending));
+
+        Ok(generation)
+    }
+
+    // Collects what the sections have captured, returning the checkpoint once all of them have
+    pub fn try_take_checkpoint(&mut self) -> Option<Checkpoint> {
+        let complete = match self.checkpoint {
+            Some((_, ref mut pending)) => {
+                let mut complete = true;
+
+                for (x, col) in self.sections.iter_mut().enumerate() {
+                    for (y, section) in col.iter_mut().enumerate() {
+                        if pending[x][y].is_none() {
+                            pending[x][y] = section.take_checkpoint();
+                        }
+
+                        complete = complete && pending[x][y].is_some();
+                    }
+                }
+
+                complete
+            }
+            None => false,
+        };
+
+        if !complete {
+            return None;
+        }
+
+        self.checkpoint.take().map(|(generation, pending)| {
+            let boards = pending.into_iter()
+                .map(|col| col.into_iter().map(|b| b.unwrap()).collect())
+                .collect();
+
+            Checkpoint::new(generation, boards)
+        })
+    }
+
+    // Starts a Chandy-Lamport snapshot from the first section, the markers spread to the others
+    // along the halo channels while they all carry on running
+    pub fn start_snapshot(&mut self) -> usize {
+        let id = self.next_snapshot_id;
+        self.next_snapshot_id += 1;
+
+        let pending = self.sections
+            .iter()
+            .map(|col| col.iter().map(|_| None).collect())
+            .collect();
+        self.snapshot = Some((id, pending));
+
+        if let Some(section) = self.sections.get_mut(0).and_then(|c| c.get_mut(0)) {
+            section.start_snapshot(id);
+        }
+
+        id
+    }
+
+    // Collects what the sections have recorded, returning the snapshot once all of them have
+    pub fn try_take_snapshot(&mut self) -> Option<GlobalSnapshot> {
+        let complete = match self.snapshot {
+            Some((_, ref mut pending)) => {
+                let mut complete = true;
+
+                for (x, col) in self.sections.iter_mut().enumerate() {
+                    for (y, section) in col.iter_mut().enumerate() {
+                        if pending[x][y].is_none() {
+                            pending[x][y] = section.take_snapshot();
+                        }
+
+                        complete = complete && pending[x][y].is_some();
+                    }
+                }
+
+                complete
+            }
+            None => false,
+        };
+
+        if !complete {
+            return None;
+        }
+
+        self.snapshot.take().map(|(id, pending)| {
+            let sections = pending.into_iter()
+                .map(|col| col.into_iter().map(|s| s.unwrap()).collect())
+                .collect();
+
+            GlobalSnapshot::new(id, sections)
+        })
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        let mut sections = Vec::new();
+        let mut min_generation = None;
+        let mut max_generation = 0;
+
+        for (x, col) in self.sections.iter().enumerate() {
+            for (y, section) in col.iter().enumerate() {
+                sections.push(((x, y), section.get_metrics().clone()));
+
+                let board = section.get_board();
+                let halo = board.get_halo();
+                for i in halo..board.get_width() - halo {
+                    for j in halo..board.get_height() - halo {
+                        let iteration = board.get_cell(i, j).get_iteration();
+
+                        min_generation = Some(min_generation.map(|m| cmp::min(m, iteration))
+                            .unwrap_or(iteration));
+                        max_generation = cmp::max(max_generation, iteration);
+                    }
+                }
+            }
+        }
+
+        Metrics::new(sections, min_generation.unwrap_or(0), max_generation)
+    }
+
+    // Adds each generation every section has finished to the population series, in order,
+    // returns how many were added
+    pub fn update_population(&mut self) -> usize {
+        let mut generation = match self.population.get_last() {
+            Some(last) => last.get_generation() + 1,
+            None => {
+                match self.sections
+                    .iter()
+                    .flat_map(|col| col.iter())
+                    .filter_map(|section| section.get_population().get_oldest_generation())
+                    .max() {
+                    Some(generation) => generation,
+                    None => return 0,
+                }
+            }
+        };
+        let mut added = 0;
+
+        'generations: loop {
+            let mut population = Population::new(generation);
+
+            for col in &self.sections {
+                for section in col {
+                    let section_population = section.get_population();
+
+                    match section_population.get_finished(generation) {
+                        Some(part) => {
+                            let (x, y) = section.get_board().get_origin();
+                            population.add(part, x, y);
+                        }
+                        None => {
+                            // Sections only keep so many generations, skip any that have gone
+                            match section_population.get_oldest_generation() {
+                                Some(oldest) if oldest > generation => {
+                                    generation = oldest;
+                                    continue 'generations;
+                                }
+                                _ => return added,
+                            }
+                        }
+                    }
+                }
+            }
+
+            self.population.push(population);
+            added += 1;
+            generation += 1;
+        }
+    }
+
+    pub fn get_population(&self) -> &PopulationSeries {
+        &self.population
+    }
+
+    // The non zero states of every section's own cells at the generation keyed by position on
+    // the whole board, or None if some cells aren't there yet or have gone past. Cells one
+    // generation on still have their state from before, so the sections needn't be held back.
+    pub fn get_cells_at(&self, generation: usize) -> Option<HashMap<(u32, u32), u8>> {
+        let mut cells = HashMap::new();
+
+        for col in &self.sections {
+            for section in col {
+                let board = section.get_board();
+                let halo = board.get_halo();
+                let (origin_x, origin_y) = board.get_origin();
+
+                for x in halo..board.get_width() - halo {
+                    for y in halo..board.get_height() - halo {
+                        let cell = board.get_cell(x, y);
+
+                        let state = if cell.get_iteration() == generation {
+                            cell.get_state()
+                        } else if cell.get_iteration() == generation + 1 {
+                            cell.get_previous_state()
+                        } else {
+                            return None;
+                        };
+
+                        if state != 0 {
+                            cells.insert((origin_x + x, origin_y + y), state);
+                        }
+                    }
+                }
+            }
+        }
+
+        Some(cells)
+    }
+
+    // Hash of the cells at the generation that is the same however the board is split into
+    // sections, see canonical
+    pub fn get_hash_at(&self, generation: usize, invariance: Invariance) -> Option<u64> {
+        self.get_cells_at(generation).map(|cells| canonical::hash(&cells, invariance))
+    }
 }